@@ -51,6 +51,11 @@ impl Interner {
         self.vec[id.0]
     }
 
+    /// Every interned string, indexed by its id
+    pub fn strings(&self) -> &[&str] {
+        &self.vec
+    }
+
     unsafe fn alloc(&mut self, name: &str) -> &'static str {
         let cap = self.buf.capacity();
         if cap < self.buf.len() + name.len() {
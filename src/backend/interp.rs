@@ -0,0 +1,992 @@
+//! A tree-walking interpreter, the `--backend interp` alternative to running the translated lua on
+//! LuaJIT. It runs the AST of a program the translator has already checked, and behaves like the
+//! code the translator emits: the same scoping, call frames, output and runtime errors. Running the
+//! same programs on both backends cross-checks the translator and the runtime library.
+
+mod value;
+
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::mem;
+use std::rc::Rc;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::backend::interner::StrId;
+use crate::backend::translator::Translator;
+use crate::diagnostics::{Diagnostic, Failible, Span};
+use crate::frontend::ast::{
+    unsupported, Block, Expr, ExprTy, Ident, LolTy, LoopCond, OpTy, Stmt, StmtTy,
+};
+use crate::runtime::debug_info::DebugInfo;
+use crate::runtime::errors::{raised, runtime_error, stack_trace};
+
+use value::{Bukkit, Value};
+
+/// Calls a program can nest before it fails with a stack overflow, about as many as fit on
+/// LuaJIT's stack
+pub const MAX_DEPTH: usize = 6000;
+/// Stack the interpreter needs to nest `MAX_DEPTH` calls, which it should be run on a thread with
+pub const STACK_SIZE: usize = 256 << 20;
+
+type Res<T> = Result<T, Box<Diagnostic>>;
+
+/// A lua variable of the translated code: an identifier, or `ME` in methods and `O HAI IM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Name {
+    Ident(StrId),
+    Me,
+}
+
+/// The lua locals in scope, innermost first. Funkshuns keep the list they were defined in, which
+/// is shared so they see later assignments to it.
+#[derive(Clone, Default)]
+struct Env<'a>(Option<Rc<Local<'a>>>);
+
+struct Local<'a> {
+    /// A variable, or `None` where the locals of a funkshun start
+    var: Option<(Name, Rc<RefCell<Value<'a>>>)>,
+    next: Env<'a>,
+}
+
+impl<'a> Env<'a> {
+    fn push(&self, var: Option<(Name, Rc<RefCell<Value<'a>>>)>) -> Self {
+        Env(Some(Rc::new(Local {
+            var,
+            next: self.clone(),
+        })))
+    }
+
+    fn declare(&self, name: Name, value: Value<'a>) -> Self {
+        self.push(Some((name, Rc::new(RefCell::new(value)))))
+    }
+
+    /// The variable a name refers to, and whether it is an upvalue of the current funkshun
+    fn find(&self, name: Name) -> Option<(&Rc<RefCell<Value<'a>>>, bool)> {
+        let mut upvalue = false;
+        let mut local = self.0.as_deref();
+        while let Some(Local { var, next }) = local {
+            match var {
+                Some((var, value)) if *var == name => return Some((value, upvalue)),
+                Some(_) => {}
+                None => upvalue = true,
+            }
+            local = next.0.as_deref();
+        }
+        None
+    }
+}
+
+struct Funkshun<'a> {
+    name: Rc<str>,
+    args: &'a [Ident],
+    body: &'a Block,
+    /// Whether `ME` is passed as a hidden first argument
+    method: bool,
+    env: Env<'a>,
+    /// The statement that defined it, where lua reports a stack overflow
+    span: Option<Span>,
+}
+
+enum Flow<'a> {
+    Next,
+    Break,
+    Return(Value<'a>),
+}
+
+enum Call<'a> {
+    Funkshun(Rc<Funkshun<'a>>, Vec<Value<'a>>),
+    /// A method call, whose method is looked up once its arguments are evaluated
+    Method {
+        object: Value<'a>,
+        key: Rc<str>,
+        span: Span,
+        args: Vec<Value<'a>>,
+    },
+}
+
+struct Interpreter<'a, 'io> {
+    info: DebugInfo,
+    modules: &'a [(Span, Block)],
+    /// Spans of the names the translator resolved, which tells an `O HAI IM` that reopens a
+    /// bukkit from one that declares it
+    resolved: &'a HashSet<(usize, usize, usize)>,
+    /// Slot keys, by the id of their identifier
+    keys: Vec<Rc<str>>,
+    globals: HashMap<Name, Value<'a>>,
+    it: Value<'a>,
+    env: Env<'a>,
+    /// Whether declarations are locals, which they are everywhere but the top level of a file
+    local_scope: bool,
+    /// The statement each frame is running, innermost last, or `None` while a builtin runs
+    frames: Vec<Option<Span>>,
+    stdin: &'io mut dyn BufRead,
+    stdout: &'io mut dyn Write,
+}
+
+/// A program the translator has checked, along with what the interpreter needs from it
+#[derive(Debug, Clone)]
+pub struct Checked {
+    ast: Block,
+    /// The modules `CAN I HAS` finds, by the span of the import
+    modules: Vec<(Span, Block)>,
+    /// Spans of the names the translator resolved
+    resolved: HashSet<(usize, usize, usize)>,
+    info: DebugInfo,
+}
+
+impl Checked {
+    /// Keeps `ast` to run once `translator` has translated it, which must have kept its modules
+    pub fn new(ast: Block, translator: &Translator) -> Self {
+        Checked {
+            ast,
+            modules: translator.modules().to_vec(),
+            resolved: translator
+                .references
+                .iter()
+                .map(|(span, _)| (span.file, span.s, span.e))
+                .collect(),
+            info: translator.debug_info(),
+        }
+    }
+}
+
+/// Runs a checked program. `GIMMEH` reads lines from `stdin`, and `VISIBLE` writes to `stdout`.
+pub fn run(program: &Checked, stdin: &mut dyn BufRead, stdout: &mut dyn Write) -> Failible<()> {
+    let info = program.info.clone();
+    let mut interp = Interpreter {
+        modules: &program.modules,
+        resolved: &program.resolved,
+        keys: info
+            .names
+            .iter()
+            .map(|name| Rc::from(name.as_str()))
+            .collect(),
+        info,
+        globals: HashMap::new(),
+        it: Value::Noob,
+        env: Env::default(),
+        local_scope: false,
+        frames: vec![None],
+        stdin,
+        stdout,
+    };
+    let res = interp.stmts(&program.ast.0).map(|_| ());
+    let _ = interp.stdout.flush();
+    res.map_err(|e| (*e).into())
+}
+
+impl<'a, 'io> Interpreter<'a, 'io> {
+    fn stmts(&mut self, stmts: &'a [Stmt]) -> Res<Flow<'a>> {
+        for stmt in stmts {
+            let prev = self.set_span(Some(stmt.span));
+            let flow = self.stmt(stmt)?;
+            self.set_span(prev);
+            if !matches!(flow, Flow::Next) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs a nested block, whose declarations are locals that end with it
+    fn block(&mut self, block: &'a Block) -> Res<Flow<'a>> {
+        let env = self.env.clone();
+        let local_scope = mem::replace(&mut self.local_scope, true);
+        let flow = self.stmts(&block.0)?;
+        self.env = env;
+        self.local_scope = local_scope;
+        Ok(flow)
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> Res<Flow<'a>> {
+        match &stmt.ty {
+            StmtTy::Print(exprs, no_newline) => self.print(exprs, *no_newline)?,
+            StmtTy::DecAssign(name, value) => {
+                let value = self.declared_value(value, stmt.span)?;
+                self.declare(name, value);
+            }
+            StmtTy::Assignment(name, expr) => {
+                let value = self.expr(expr)?;
+                self.assign(Name::Ident(name.0), value);
+            }
+            StmtTy::Input(name) => {
+                let value = self.input();
+                self.assign(Name::Ident(name.0), value);
+            }
+            StmtTy::FunctionDef(name, args, block) => {
+                // `function f()` assigns to whichever `f` is in scope, like an assignment
+                let f = self.funkshun(name, args, block, false);
+                self.assign(Name::Ident(name.0), f);
+            }
+            StmtTy::ObjectDef(name, parent, block) => {
+                return self.object_def(name, parent.as_ref(), block)
+            }
+            StmtTy::SlotDec(object, slot, value) => {
+                let object = self.expr(object)?;
+                let value = self.declared_value(value, stmt.span)?;
+                self.dec_slot(&object, self.key(slot), value, slot.1)?;
+            }
+            StmtTy::SlotAssign(object, slot, value) => {
+                let object = self.expr(object)?;
+                let value = self.expr(value)?;
+                self.set_slot(&object, self.key(slot), value, stmt.span)?;
+            }
+            StmtTy::MethodDef(object, name, args, block) => {
+                let object = self.expr(object)?;
+                let method = self.funkshun(name, args, block, true);
+                self.dec_slot(&object, self.key(name), method, stmt.span)?;
+            }
+            StmtTy::Expr(expr) => self.it = self.expr(expr)?,
+            StmtTy::If(if_case, else_if_cases, else_case) => {
+                if self.it.truthy() {
+                    return match if_case {
+                        Some(block) => self.block(block),
+                        None => Ok(Flow::Next),
+                    };
+                }
+                for (expr, block) in else_if_cases {
+                    if self.expr(expr)?.truthy() {
+                        return self.block(block);
+                    }
+                }
+                if let Some(block) = else_case {
+                    return self.block(block);
+                }
+            }
+            StmtTy::Loop { fn_id, block, .. } => return self.loop_stmt(fn_id.as_ref(), block),
+            StmtTy::Import(_) => {
+                // Modules run where they are first imported, at the top level
+                let module = self.modules.iter().find(|(span, _)| *span == stmt.span);
+                if let Some((_, module)) = module {
+                    let local_scope = mem::replace(&mut self.local_scope, false);
+                    let flow = self.stmts(&module.0)?;
+                    self.local_scope = local_scope;
+                    return Ok(flow);
+                }
+            }
+            StmtTy::Case(cases, default) => return self.case(cases, default.as_ref()),
+            StmtTy::Break => return Ok(Flow::Break),
+            StmtTy::Return(expr) => return Ok(Flow::Return(self.expr(expr)?)),
+            // The translator has already rejected these
+            ty => return Err(Box::new(unsupported(ty.syntax(), stmt.span))),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn print(&mut self, exprs: &[Expr], no_newline: bool) -> Res<()> {
+        for value in self.list(exprs)? {
+            match value.to_yarn() {
+                Some(yarn) => {
+                    let _ = self.stdout.write_all(yarn.as_bytes());
+                }
+                None => {
+                    return Err(self
+                        .fail("bad argument #1 to 'write' (string expected, got nil)".to_string()))
+                }
+            }
+        }
+        if !no_newline {
+            let _ = self.stdout.write_all(b"\n");
+        }
+        Ok(())
+    }
+
+    /// A line of input without its newline, or NOOB once the input ends
+    fn input(&mut self) -> Value<'a> {
+        let mut line = Vec::new();
+        match self.stdin.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => Value::Noob,
+            Ok(_) => {
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                }
+                Value::Yarn(Rc::from(String::from_utf8_lossy(&line).as_ref()))
+            }
+        }
+    }
+
+    fn declared_value(
+        &mut self,
+        value: &Option<Result<Expr, LolTy>>,
+        span: Span,
+    ) -> Res<Value<'a>> {
+        match value {
+            Some(Ok(expr)) => self.expr(expr),
+            Some(Err(ty)) => self.expr(&Expr {
+                ty: ty.default_expr_kind(),
+                span,
+            }),
+            None => Ok(Value::Noob),
+        }
+    }
+
+    fn declare(&mut self, name: &Ident, value: Value<'a>) {
+        if self.local_scope {
+            self.env = self.env.declare(Name::Ident(name.0), value);
+        } else {
+            self.assign(Name::Ident(name.0), value);
+        }
+    }
+
+    fn assign(&mut self, name: Name, value: Value<'a>) {
+        match self.env.find(name) {
+            Some((var, _)) => *var.borrow_mut() = value,
+            None => {
+                self.globals.insert(name, value);
+            }
+        }
+    }
+
+    /// The value of a variable, and what lua calls the kind of variable it is
+    fn lookup(&self, name: Name) -> (Value<'a>, &'static str) {
+        match self.env.find(name) {
+            Some((var, true)) => (var.borrow().clone(), "upvalue"),
+            Some((var, false)) => (var.borrow().clone(), "local"),
+            None => (
+                self.globals.get(&name).cloned().unwrap_or(Value::Noob),
+                "global",
+            ),
+        }
+    }
+
+    fn get(&self, name: Name) -> Value<'a> {
+        self.lookup(name).0
+    }
+
+    fn key(&self, slot: &Ident) -> Rc<str> {
+        self.keys[slot.0.inner()].clone()
+    }
+
+    fn funkshun(
+        &self,
+        name: &Ident,
+        args: &'a [Ident],
+        body: &'a Block,
+        method: bool,
+    ) -> Value<'a> {
+        Value::Funkshun(Rc::new(Funkshun {
+            name: self.key(name),
+            args,
+            body,
+            method,
+            env: self.env.clone(),
+            span: self.frames.last().copied().flatten(),
+        }))
+    }
+
+    fn object_def(
+        &mut self,
+        name: &'a Ident,
+        parent: Option<&'a Expr>,
+        block: &'a Block,
+    ) -> Res<Flow<'a>> {
+        let reopened = self.resolved.contains(&(name.1.file, name.1.s, name.1.e));
+        if !reopened {
+            let parent = match parent {
+                Some(parent) => self.expr(parent)?,
+                None => Value::Noob,
+            };
+            self.declare(name, Value::bukkit(parent));
+        }
+
+        // Inside the block, `ME` is the bukkit being defined
+        let env = self.env.clone();
+        let me = self.get(Name::Ident(name.0));
+        self.env = self.env.declare(Name::Me, me.clone());
+        if let (true, Some(parent)) = (reopened, parent) {
+            let parent_value = self.expr(parent)?;
+            self.dec_slot(&me, Rc::from("parent"), parent_value, parent.span)?;
+        }
+
+        let local_scope = mem::replace(&mut self.local_scope, true);
+        let mut flow = Flow::Next;
+        for stmt in block.0.iter() {
+            // Declarations directly inside the block become slots of the bukkit
+            match &stmt.ty {
+                StmtTy::DecAssign(slot, value) => {
+                    let me = self.get(Name::Me);
+                    let value = self.declared_value(value, stmt.span)?;
+                    self.dec_slot(&me, self.key(slot), value, slot.1)?;
+                }
+                StmtTy::FunctionDef(method, args, body) => {
+                    let me = self.get(Name::Me);
+                    let method_value = self.funkshun(method, args, body, true);
+                    self.dec_slot(&me, self.key(method), method_value, stmt.span)?;
+                }
+                _ => flow = self.stmt(stmt)?,
+            }
+            if !matches!(flow, Flow::Next) {
+                break;
+            }
+        }
+        self.local_scope = local_scope;
+        self.env = env;
+        Ok(flow)
+    }
+
+    fn loop_stmt(
+        &mut self,
+        fn_id: Option<&'a (Ident, Ident, LoopCond)>,
+        block: &'a Block,
+    ) -> Res<Flow<'a>> {
+        // The loop variable is local to the loop
+        let env = self.env.clone();
+        if let Some((_, var, _)) = fn_id {
+            self.env = self.env.declare(Name::Ident(var.0), Value::Numbr(0));
+        }
+
+        let flow = loop {
+            if let Some((_, _, cond)) = fn_id {
+                let stop = match cond {
+                    LoopCond::Till(expr) => self.expr(expr)?.truthy(),
+                    LoopCond::While(expr) => !self.expr(expr)?.truthy(),
+                    LoopCond::Forever => false,
+                };
+                if stop {
+                    break Flow::Next;
+                }
+            }
+            match self.block(block)? {
+                Flow::Next => {}
+                Flow::Break => break Flow::Next,
+                flow => break flow,
+            }
+            if let Some((func, var, _)) = fn_id {
+                self.loop_update(func, var)?;
+            }
+        };
+        self.env = env;
+        Ok(flow)
+    }
+
+    fn loop_update(&mut self, func: &Ident, var: &Ident) -> Res<()> {
+        let var = Name::Ident(var.0);
+        let value = match &*self.key(func) {
+            "UPPIN" => self.arith(OpTy::Add, self.get(var), Value::Numbr(1), func.1)?,
+            "NERFIN" => self.arith(OpTy::Sub, self.get(var), Value::Numbr(1), func.1)?,
+            _ => {
+                // Any unary funkshun can be used to step the loop variable
+                let f = self.lookup(Name::Ident(func.0));
+                let f = self.callable(func, f)?;
+                let arg = self.get(var);
+                self.call(Call::Funkshun(f, vec![arg]))?
+            }
+        };
+        self.assign(var, value);
+        Ok(())
+    }
+
+    fn case(&mut self, cases: &'a [(Expr, Block)], default: Option<&'a Block>) -> Res<Flow<'a>> {
+        // Once a case has matched, every following case falls through until a `GTFO`
+        let mut matched = false;
+        for (expr, block) in cases {
+            if !matched {
+                let it = self.it.clone();
+                matched = value::eq(&it, &self.expr(expr)?);
+            }
+            if matched {
+                match self.block(block)? {
+                    Flow::Next => {}
+                    Flow::Break => return Ok(Flow::Next),
+                    flow => return Ok(flow),
+                }
+            }
+        }
+        // Reached either by falling through, or when no case matched
+        match default {
+            Some(block) => match self.block(block)? {
+                Flow::Break => Ok(Flow::Next),
+                flow => Ok(flow),
+            },
+            None => Ok(Flow::Next),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Res<Value<'a>> {
+        Ok(match &expr.ty {
+            ExprTy::Int(i) => Value::Numbr(*i),
+            ExprTy::Float(f) => Value::Numbar(*f),
+            ExprTy::String(s) => Value::Yarn(Rc::from(s.as_str())),
+            ExprTy::Null => Value::Noob,
+            ExprTy::Bool(b) => Value::Troof(*b),
+            ExprTy::Variable(id) => self.get(Name::Ident(id.0)),
+            ExprTy::Operator(op, l, r) => self.operator(*op, l, r, expr.span)?,
+            ExprTy::FunctionCall(name, args) => {
+                let (f, args) = self.callee(name, args)?;
+                self.call(Call::Funkshun(f, args))?
+            }
+            ExprTy::MethodCall(object, method, args) => {
+                let call = self.method_call(object, method, args, expr.span)?;
+                self.call(call)?
+            }
+            ExprTy::Bukkit(parent) => {
+                let parent = match parent {
+                    Some(parent) => self.expr(parent)?,
+                    None => Value::Noob,
+                };
+                Value::bukkit(parent)
+            }
+            ExprTy::Me => self.get(Name::Me),
+            ExprTy::GetSlot(object, slot) => {
+                let object = self.expr(object)?;
+                self.get_slot(&object, self.key(slot), expr.span)?
+            }
+            ty => return Err(Box::new(unsupported(ty.syntax(), expr.span))),
+        })
+    }
+
+    /// Evaluates expressions in order
+    fn list(&mut self, exprs: &[Expr]) -> Res<Vec<Value<'a>>> {
+        exprs.iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    fn operator(&mut self, op: OpTy, l: &Expr, r: &Expr, span: Span) -> Res<Value<'a>> {
+        // Both operands are evaluated, even for `BOTH OF` and `EITHER OF`
+        let (l, r) = (self.expr(l)?, self.expr(r)?);
+        Ok(match op {
+            OpTy::And => Value::Troof(l.truthy() && r.truthy()),
+            OpTy::Or => Value::Troof(l.truthy() || r.truthy()),
+            OpTy::Equal => Value::Troof(value::eq(&l, &r)),
+            OpTy::NotEq => Value::Troof(!value::eq(&l, &r)),
+            OpTy::GT | OpTy::LT | OpTy::GTE | OpTy::LTE => match value::compare(op, &l, &r) {
+                Ok(b) => Value::Troof(b),
+                Err(msg) => return Err(self.raise(msg, span)),
+            },
+            _ => self.arith(op, l, r, span)?,
+        })
+    }
+
+    fn arith(&self, op: OpTy, l: Value<'a>, r: Value<'a>, span: Span) -> Res<Value<'a>> {
+        value::arith(op, &l, &r).map_err(|msg| self.raise(msg, span))
+    }
+
+    /// The funkshun `I IZ name` calls, and its arguments
+    fn callee(&mut self, name: &Ident, args: &[Expr]) -> Res<(Rc<Funkshun<'a>>, Vec<Value<'a>>)> {
+        let f = self.lookup(Name::Ident(name.0));
+        let args = self.list(args)?;
+        Ok((self.callable(name, f)?, args))
+    }
+
+    fn callable(
+        &self,
+        name: &Ident,
+        (f, kind): (Value<'a>, &'static str),
+    ) -> Res<Rc<Funkshun<'a>>> {
+        match f {
+            Value::Funkshun(f) => Ok(f),
+            // Their metatables raise this, without the name lua gives other values
+            f @ Value::Numbar(_) | f @ Value::Bukkit(_) => {
+                Err(self.fail(format!("attempt to call a {} value", f.type_name())))
+            }
+            f => Err(self.fail(format!(
+                "attempt to call {} '{}' (a {} value)",
+                kind,
+                self.key(name),
+                f.type_name()
+            ))),
+        }
+    }
+
+    fn method_call(
+        &mut self,
+        object: &Expr,
+        method: &Ident,
+        args: &[Expr],
+        span: Span,
+    ) -> Res<Call<'a>> {
+        Ok(Call::Method {
+            object: self.expr(object)?,
+            key: self.key(method),
+            span,
+            args: self.list(args)?,
+        })
+    }
+
+    /// Makes a call in a new frame, and returns what the funkshun returns
+    fn call(&mut self, call: Call<'a>) -> Res<Value<'a>> {
+        self.frames.push(None);
+        let (f, args) = match call {
+            Call::Funkshun(f, args) => (f, args),
+            Call::Method {
+                object,
+                key,
+                span,
+                mut args,
+            } => {
+                // The method is looked up by a builtin, which leaves no frame in tracebacks
+                self.set_span(None);
+                match self.get_slot(&object, key.clone(), span)? {
+                    Value::Funkshun(f) => {
+                        args.insert(0, object);
+                        (f, args)
+                    }
+                    _ => {
+                        let msg = format!("slot `{}` is not a FUNKSHUN", key);
+                        return Err(self.raise(msg, span));
+                    }
+                }
+            }
+        };
+
+        let given = args.len() - f.method as usize;
+        if given != f.args.len() {
+            // Lua raises this in the caller, once the arguments are counted
+            self.frames.pop();
+            let s = if f.args.len() == 1 { "" } else { "s" };
+            return Err(self.fail(format!(
+                "funkshun `{}` takes {} argument{}, but was called with {}",
+                f.name,
+                f.args.len(),
+                s,
+                given
+            )));
+        }
+
+        self.set_span(f.span);
+        if self.frames.len() > MAX_DEPTH {
+            return Err(self.fail("stack overflow".to_string()));
+        }
+        let mut env = f.env.push(None);
+        let mut args = args.into_iter();
+        if f.method {
+            env = env.declare(Name::Me, args.next().unwrap_or(Value::Noob));
+        }
+        for (arg, value) in f.args.iter().zip(args) {
+            env = env.declare(Name::Ident(arg.0), value);
+        }
+        let env = mem::replace(&mut self.env, env);
+        let local_scope = mem::replace(&mut self.local_scope, true);
+        // IT is local to each funkshun
+        let it = mem::replace(&mut self.it, Value::Noob);
+        let flow = self.stmts(&f.body.0)?;
+        let it = mem::replace(&mut self.it, it);
+        self.env = env;
+        self.local_scope = local_scope;
+        self.frames.pop();
+        Ok(match flow {
+            Flow::Return(value) => value,
+            // `GTFO` returns NOOB, and reaching the end returns IT
+            Flow::Break => Value::Noob,
+            Flow::Next => it,
+        })
+    }
+
+    fn bukkit(&self, object: &Value<'a>, key: &str, span: Span) -> Res<Rc<RefCell<Bukkit<'a>>>> {
+        match object {
+            Value::Bukkit(bukkit) => Ok(bukkit.clone()),
+            _ => Err(self.raise(
+                format!("cannot use slot `{}` of a value that is not a BUKKIT", key),
+                span,
+            )),
+        }
+    }
+
+    fn dec_slot(&self, object: &Value<'a>, key: Rc<str>, value: Value<'a>, span: Span) -> Res<()> {
+        let bukkit = self.bukkit(object, &key, span)?;
+        bukkit.borrow_mut().slots.insert(key, value);
+        Ok(())
+    }
+
+    fn get_slot(&mut self, object: &Value<'a>, key: Rc<str>, span: Span) -> Res<Value<'a>> {
+        self.bukkit(object, &key, span)?;
+        if let Some(value) = object.find_slot(&key) {
+            return Ok(value);
+        }
+
+        // The `omgwtf` slot is asked for a value for slots that don't exist
+        if let Some(Value::Funkshun(omgwtf)) = object.find_slot("omgwtf") {
+            let args = vec![object.clone(), Value::Yarn(key.clone())];
+            let value = self.call(Call::Funkshun(omgwtf, args))?;
+            self.dec_slot(object, key, value.clone(), span)?;
+            return Ok(value);
+        }
+
+        Err(self.raise(format!("slot `{}` does not exist", key), span))
+    }
+
+    fn set_slot(&self, object: &Value<'a>, key: Rc<str>, value: Value<'a>, span: Span) -> Res<()> {
+        self.bukkit(object, &key, span)?;
+        if object.find_slot(&key).is_none() {
+            return Err(self.raise(format!("slot `{}` does not exist", key), span));
+        }
+        // Inherited slots are copied onto the bukkit itself when assigned
+        self.dec_slot(object, key, value, span)
+    }
+
+    fn set_span(&mut self, span: Option<Span>) -> Option<Span> {
+        mem::replace(self.frames.last_mut().unwrap(), span)
+    }
+
+    /// The statement of every frame, innermost first
+    fn traceback(&self) -> Vec<Span> {
+        self.frames.iter().rev().filter_map(|&span| span).collect()
+    }
+
+    /// An error raised by a builtin, at `span`
+    fn raise(&self, msg: String, span: Span) -> Box<Diagnostic> {
+        Box::new(stack_trace(
+            raised(msg, span),
+            span,
+            self.traceback(),
+            &self.info,
+        ))
+    }
+
+    /// An error of the lua VM, at the statement being run
+    fn fail(&self, description: String) -> Box<Diagnostic> {
+        let span = self.frames.last().copied().flatten();
+        Box::new(runtime_error(
+            description,
+            span,
+            self.traceback(),
+            &self.info,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod interp_test {
+    use super::*;
+    use crate::backend::interner::Interner;
+    use crate::diagnostics::DiagnosticType;
+    use crate::frontend::{lex::Lexer, parse::Parser};
+    use crate::sourcemap::SourceMap;
+
+    /// Runs a program on a thread with enough stack, and returns its output and runtime error
+    fn run_source(source: &'static str, input: &'static str) -> (String, Option<Diagnostic>) {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut sources = SourceMap::new();
+                let file = sources.add("test.lol".to_string(), source.to_string());
+                let mut interner = Interner::default();
+                let ast = Parser::new(Lexer::new(source.chars(), file, &mut interner))
+                    .parse()
+                    .unwrap();
+                let mut translator = Translator::new(interner, Vec::new(), sources);
+                translator.keep_modules();
+                translator.outer_block(ast.clone()).unwrap();
+                let mut out = Vec::new();
+                let program = Checked::new(ast, &translator);
+                let res = run(&program, &mut input.as_bytes(), &mut out);
+                let error = res.err().map(|es| es.into_inner()[0].clone());
+                (String::from_utf8(out).unwrap(), error)
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    fn labels(diagnostic: &Diagnostic) -> Vec<&str> {
+        diagnostic
+            .annotations
+            .iter()
+            .map(|a| a.message.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn runs_programs() {
+        let source = "HAI 1.2
+HOW IZ I counter
+    I HAS A n ITZ 0
+    HOW IZ I next
+        n R SUM OF n AN 1
+        FOUND YR n
+    IF U SAY SO
+    FOUND YR next
+IF U SAY SO
+I HAS A next ITZ I IZ counter MKAY
+I IZ next MKAY
+VISIBLE I IZ next MKAY \" \" QUOSHUNT OF 7 AN 2.0 \" \" SUM OF \"40\" AN WIN
+IM IN YR loop UPPIN YR i TIL BOTH SAEM i AN 3
+    VISIBLE i!
+IM OUTTA YR loop
+I HAS A line
+GIMMEH line
+line, WTF?
+OMG \"a\"
+    VISIBLE \"a\"
+OMG \"b\"
+    VISIBLE \"b\"
+    GTFO
+OMGWTF
+    VISIBLE \"never\"
+OIC
+VISIBLE line BOTH SAEM 1 AN 1.0
+KTHXBYE";
+        let (out, error) = run_source(source, "a\n");
+        assert!(error.is_none());
+        assert_eq!(out, "2 3.5 41\n012a\nb\naWIN\n");
+    }
+
+    #[test]
+    fn bukkits() {
+        let source = "HAI 1.3
+O HAI IM animal
+    I HAS A name ITZ \"generic\"
+    HOW IZ I describe
+        FOUND YR ME'Z name
+    IF U SAY SO
+    HOW IZ I omgwtf YR slot
+        FOUND YR slot
+    IF U SAY SO
+KTHX
+I HAS A dog ITZ LIEK A animal
+dog'Z name R \"rex\"
+VISIBLE dog IZ describe MKAY \" \" animal IZ describe MKAY \" \" dog'Z legs
+KTHXBYE";
+        let (out, error) = run_source(source, "");
+        assert!(error.is_none());
+        assert_eq!(out, "rex generic legs\n");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let source = "HAI 1.2
+HOW IZ I half YR n
+    VISIBLE \"halving\"
+    I HAS A h ITZ QUOSHUNT OF n AN 0
+    FOUND YR h
+IF U SAY SO
+VISIBLE I IZ half YR 4 MKAY
+KTHXBYE";
+        let (out, error) = run_source(source, "");
+        let error = error.unwrap();
+        assert_eq!(out, "halving\n");
+        assert_eq!(
+            labels(&error),
+            [
+                "division by zero",
+                "in `half`, called here",
+                "`half` is defined here"
+            ]
+        );
+
+        let (_, error) = run_source("HAI 1.2\nI HAS A f\nI IZ f MKAY\nKTHXBYE", "");
+        assert_eq!(
+            labels(&error.unwrap()),
+            ["attempt to call global 'f' (a NOOB value)"]
+        );
+        // Like the metatables of the runtime library
+        let (_, error) = run_source("HAI 1.2\nI HAS A f ITZ 1.5\nI IZ f MKAY\nKTHXBYE", "");
+        assert_eq!(labels(&error.unwrap()), ["attempt to call a NUMBAR value"]);
+        let source = "HAI 1.2\nI HAS A f ITZ A BUKKIT\nI IZ f MKAY\nKTHXBYE";
+        let (_, error) = run_source(source, "");
+        assert_eq!(labels(&error.unwrap()), ["attempt to call a BUKKIT value"]);
+
+        // The caller is blamed for the wrong number of arguments
+        let source = "HAI 1.2
+HOW IZ I f YR a
+IF U SAY SO
+HOW IZ I g
+    I IZ f YR 1 AN YR 2 MKAY
+IF U SAY SO
+I IZ g MKAY
+KTHXBYE";
+        let (_, error) = run_source(source, "");
+        let error = error.unwrap();
+        assert_eq!(
+            labels(&error),
+            [
+                "funkshun `f` takes 1 argument, but was called with 2",
+                "in `g`, called here",
+                "`g` is defined here"
+            ]
+        );
+        assert_eq!(error.span.s, source.find("I IZ f").unwrap());
+    }
+
+    #[test]
+    fn returns() {
+        // IT is returned from the end of a funkshun, and `GTFO` returns NOOB. Each call has its
+        // own IT, which starts out NOOB.
+        let source = "HAI 1.2
+HOW IZ I last
+    SUM OF 1 AN 2
+IF U SAY SO
+HOW IZ I early
+    3
+    GTFO
+IF U SAY SO
+HOW IZ I empty
+IF U SAY SO
+WIN
+VISIBLE I IZ last MKAY
+VISIBLE I IZ early MKAY
+VISIBLE I IZ empty MKAY
+O RLY?
+    YA RLY, VISIBLE \"kept\"
+OIC
+KTHXBYE";
+        let (out, error) = run_source(source, "");
+        assert!(error.is_none());
+        assert_eq!(out, "3\nNOOB\nNOOB\nkept\n");
+    }
+
+    #[test]
+    fn unsupported_syntax() {
+        let source = "HAI 1.2\nVISIBLE \"a\"\nVISIBLE SMOOSH 1 AN 2 MKAY\nKTHXBYE";
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lol".to_string(), source.to_string());
+        let mut interner = Interner::default();
+        let ast = Parser::new(Lexer::new(source.chars(), file, &mut interner))
+            .parse()
+            .unwrap();
+        let mut translator = Translator::new(interner, Vec::new(), sources);
+        assert!(translator.outer_block(ast.clone()).is_err());
+        // Run anyway, so the interpreter reaches it
+        let mut out = Vec::new();
+        let program = Checked::new(ast, &translator);
+        let es = run(&program, &mut std::io::empty(), &mut out).unwrap_err();
+        let diagnostic = &es.into_inner()[0];
+        assert_eq!(diagnostic.ty, DiagnosticType::Unsupported);
+        assert_eq!(labels(diagnostic), ["`SMOOSH` is not supported yet"]);
+        assert_eq!(out, b"a\n");
+    }
+
+    #[test]
+    fn returned_calls() {
+        // `FOUND YR` of a call keeps the frame of the caller, like any other call
+        let source = "HAI 1.2
+HOW IZ I inner
+    FOUND YR QUOSHUNT OF 1 AN 0
+IF U SAY SO
+HOW IZ I outer
+    FOUND YR I IZ inner MKAY
+IF U SAY SO
+VISIBLE I IZ outer MKAY
+KTHXBYE";
+        let (_, error) = run_source(source, "");
+        assert_eq!(
+            labels(&error.unwrap()),
+            [
+                "division by zero",
+                "in `inner`, called here",
+                "`inner` is defined here",
+                "in `outer`, called here",
+                "`outer` is defined here"
+            ]
+        );
+
+        let source = "HAI 1.2
+HOW IZ I down YR n
+    BOTH SAEM n AN 0, O RLY?
+        YA RLY, FOUND YR \"done\"
+    OIC
+    FOUND YR I IZ down YR DIFF OF n AN 1 MKAY
+IF U SAY SO
+VISIBLE I IZ down YR 1000 MKAY
+HOW IZ I forever YR n
+    FOUND YR I IZ forever YR n MKAY
+IF U SAY SO
+I IZ forever YR 1 MKAY
+KTHXBYE";
+        let (out, error) = run_source(source, "");
+        assert_eq!(out, "done\n");
+        let error = error.unwrap();
+        assert_eq!(labels(&error)[0], "stack overflow");
+        assert!(error.note.unwrap().contains("calls itself forever"));
+    }
+}
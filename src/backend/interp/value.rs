@@ -0,0 +1,332 @@
+//! Values of the interpreter, and the operators on them. They behave like the lua values of the
+//! other backend and the `ops` builtins, down to their error messages.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use hashbrown::HashMap;
+
+use crate::frontend::ast::OpTy;
+
+use super::Funkshun;
+
+#[derive(Clone)]
+pub enum Value<'a> {
+    Noob,
+    Troof(bool),
+    Numbr(i64),
+    Numbar(f64),
+    Yarn(Rc<str>),
+    Funkshun(Rc<Funkshun<'a>>),
+    Bukkit(Rc<RefCell<Bukkit<'a>>>),
+}
+
+#[derive(Default)]
+pub struct Bukkit<'a> {
+    /// Every declared slot, including `parent`, which slots that aren't declared are looked up in
+    pub slots: HashMap<Rc<str>, Value<'a>>,
+}
+
+/// An operand of a math operator, once implicitly cast
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Num {
+    Numbr(i64),
+    Numbar(f64),
+}
+
+impl Num {
+    fn to_float(self) -> f64 {
+        match self {
+            Num::Numbr(n) => n as f64,
+            Num::Numbar(n) => n,
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn bukkit(parent: Value<'a>) -> Self {
+        let mut bukkit = Bukkit::default();
+        if !matches!(parent, Value::Noob) {
+            bukkit.slots.insert(Rc::from("parent"), parent);
+        }
+        Value::Bukkit(Rc::new(RefCell::new(bukkit)))
+    }
+
+    /// The LOLCODE type of the value, like `NUMBR`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Noob => "NOOB",
+            Value::Troof(_) => "TROOF",
+            Value::Numbr(_) => "NUMBR",
+            Value::Numbar(_) => "NUMBAR",
+            Value::Yarn(_) => "YARN",
+            Value::Funkshun(_) => "FUNKSHUN",
+            Value::Bukkit(_) => "BUKKIT",
+        }
+    }
+
+    /// Whether the value casts to WIN, like the condition of `O RLY?`. NOOB, FAIL, 0, 0.0 and the
+    /// empty YARN are FAIL.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Noob => false,
+            Value::Troof(b) => *b,
+            Value::Numbr(n) => *n != 0,
+            Value::Numbar(n) => *n != 0.0,
+            Value::Yarn(s) => !s.is_empty(),
+            Value::Funkshun(_) | Value::Bukkit(_) => true,
+        }
+    }
+
+    /// What `VISIBLE` writes, or `None` for values it can't write
+    pub fn to_yarn(&self) -> Option<Cow<'_, str>> {
+        Some(match self {
+            Value::Noob => Cow::Borrowed("NOOB"),
+            Value::Troof(b) => Cow::Borrowed(if *b { "WIN" } else { "FAIL" }),
+            Value::Numbr(n) => Cow::Owned(n.to_string()),
+            Value::Numbar(n) => Cow::Owned(fmt_numbar(*n)),
+            Value::Yarn(s) => Cow::Borrowed(s),
+            Value::Funkshun(_) | Value::Bukkit(_) => return None,
+        })
+    }
+
+    /// The value of a slot, which is looked up in the `parent` slot when the bukkit doesn't
+    /// declare it. `None` if no bukkit declares it.
+    pub fn find_slot(&self, key: &str) -> Option<Value<'a>> {
+        let mut cur = self.clone();
+        while let Value::Bukkit(bukkit) = cur {
+            let bukkit = bukkit.borrow();
+            if let Some(value) = bukkit.slots.get(key) {
+                return Some(value.clone());
+            }
+            cur = bukkit.slots.get("parent").cloned().unwrap_or(Value::Noob);
+        }
+        None
+    }
+
+    /// Implicitly casts an operand of a math operator
+    fn to_num(&self) -> Result<Num, String> {
+        match self {
+            Value::Numbr(n) => Ok(Num::Numbr(*n)),
+            Value::Numbar(n) => Ok(Num::Numbar(*n)),
+            Value::Troof(b) => Ok(Num::Numbr(*b as i64)),
+            Value::Yarn(s) => {
+                let digits = s.strip_prefix('-').unwrap_or(s);
+                let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+                if !digits.is_empty() && is_digits(digits) {
+                    // Digits that don't fit in 64 bits aren't a NUMBR
+                    return s
+                        .parse()
+                        .map(Num::Numbr)
+                        .map_err(|_| format!("cannot implicitly cast YARN \"{}\" to a NUMBR", s));
+                }
+                match digits.split_once('.') {
+                    Some((int, frac))
+                        if is_digits(int) && is_digits(frac) && int.len() + frac.len() > 0 =>
+                    {
+                        Ok(Num::Numbar(s.parse().unwrap_or(0.0)))
+                    }
+                    _ => Err(format!(
+                        "cannot implicitly cast YARN \"{}\" to a NUMBR or NUMBAR",
+                        s
+                    )),
+                }
+            }
+            _ => Err(format!(
+                "cannot implicitly cast a {} to a NUMBR or NUMBAR",
+                self.type_name()
+            )),
+        }
+    }
+}
+
+/// Formats a NUMBAR like lua's `tostring`, which uses `%.14g`
+pub fn fmt_numbar(n: f64) -> String {
+    if n.is_nan() {
+        return if n.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+    if n.is_infinite() {
+        return if n < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    let trim = |s: &str| {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            s.to_string()
+        }
+    };
+    // The exponent once rounded to 14 significant digits picks between `%f` and `%e`
+    let scientific = format!("{:.13e}", n);
+    let (mantissa, exp) = scientific.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if (-4..14).contains(&exp) {
+        trim(&format!("{:.*}", (13 - exp) as usize, n))
+    } else {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim(mantissa), sign, exp.abs())
+    }
+}
+
+/// A math operator, or an error to raise at the operator
+pub fn arith<'a>(op: OpTy, l: &Value<'a>, r: &Value<'a>) -> Result<Value<'a>, String> {
+    let (l, r) = (l.to_num()?, r.to_num()?);
+    if let (Num::Numbr(l), Num::Numbr(r)) = (l, r) {
+        // Wraps around on overflow, like the int64 cdata of the lua backend
+        let n = match op {
+            OpTy::Add => l.wrapping_add(r),
+            OpTy::Sub => l.wrapping_sub(r),
+            OpTy::Mul => l.wrapping_mul(r),
+            OpTy::Div | OpTy::Mod if r == 0 => return Err("division by zero".to_string()),
+            OpTy::Div => l.wrapping_div(r),
+            OpTy::Mod => l.wrapping_rem(r),
+            OpTy::Max => l.max(r),
+            OpTy::Min => l.min(r),
+            _ => unreachable!("{:?} is not a math operator", op),
+        };
+        return Ok(Value::Numbr(n));
+    }
+
+    let (l, r) = (l.to_float(), r.to_float());
+    Ok(Value::Numbar(match op {
+        OpTy::Add => l + r,
+        OpTy::Sub => l - r,
+        OpTy::Mul => l * r,
+        OpTy::Div => l / r,
+        OpTy::Mod => l % r,
+        OpTy::Max if l > r => l,
+        OpTy::Min if l < r => l,
+        OpTy::Max | OpTy::Min => r,
+        _ => unreachable!("{:?} is not a math operator", op),
+    }))
+}
+
+/// `BOTH SAEM`, which compares numbers by value and casts nothing else
+pub fn eq<'a>(l: &Value<'a>, r: &Value<'a>) -> bool {
+    match (l, r) {
+        (Value::Numbr(l), Value::Numbr(r)) => l == r,
+        (Value::Numbr(_) | Value::Numbar(_), Value::Numbr(_) | Value::Numbar(_)) => {
+            let float = |v: &Value| v.to_num().map_or(f64::NAN, Num::to_float);
+            float(l) == float(r)
+        }
+        (Value::Noob, Value::Noob) => true,
+        (Value::Troof(l), Value::Troof(r)) => l == r,
+        (Value::Yarn(l), Value::Yarn(r)) => l == r,
+        (Value::Funkshun(l), Value::Funkshun(r)) => Rc::ptr_eq(l, r),
+        (Value::Bukkit(l), Value::Bukkit(r)) => Rc::ptr_eq(l, r),
+        _ => false,
+    }
+}
+
+/// A comparison operator, or an error to raise at the operator
+pub fn compare(op: OpTy, l: &Value, r: &Value) -> Result<bool, String> {
+    let (l, r) = (l.to_num()?, r.to_num()?);
+    if let (Num::Numbr(l), Num::Numbr(r)) = (l, r) {
+        return Ok(match op {
+            OpTy::GT => l > r,
+            OpTy::LT => l < r,
+            OpTy::GTE => l >= r,
+            OpTy::LTE => l <= r,
+            _ => unreachable!("{:?} is not a comparison", op),
+        });
+    }
+    let (l, r) = (l.to_float(), r.to_float());
+    Ok(match op {
+        OpTy::GT => l > r,
+        OpTy::LT => l < r,
+        OpTy::GTE => l >= r,
+        OpTy::LTE => l <= r,
+        _ => unreachable!("{:?} is not a comparison", op),
+    })
+}
+
+#[cfg(test)]
+mod value_test {
+    use super::*;
+
+    fn yarn(s: &str) -> Value<'static> {
+        Value::Yarn(Rc::from(s))
+    }
+
+    #[test]
+    fn formats_numbars() {
+        let formatted: Vec<_> = [0.0, -0.0, 1.5, 0.1 + 0.2, 1e15, 1e-5, 123456.0, 1.0 / 3.0]
+            .iter()
+            .map(|&n| fmt_numbar(n))
+            .collect();
+        assert_eq!(
+            formatted,
+            [
+                "0",
+                "-0",
+                "1.5",
+                "0.3",
+                "1e+15",
+                "1e-05",
+                "123456",
+                "0.33333333333333"
+            ]
+        );
+        assert_eq!(fmt_numbar(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn casts_operands() {
+        let sum = |l, r| match arith(OpTy::Add, &l, &r) {
+            Ok(v) => v.to_yarn().unwrap().into_owned(),
+            Err(e) => e,
+        };
+        assert_eq!(sum(yarn("40"), Value::Troof(true)), "41");
+        assert_eq!(sum(yarn("-.5"), Value::Numbr(1)), "0.5");
+        assert_eq!(
+            sum(Value::Numbr(i64::MAX), Value::Numbr(1)),
+            i64::MIN.to_string()
+        );
+        assert_eq!(
+            sum(yarn("1."), yarn("x")),
+            "cannot implicitly cast YARN \"x\" to a NUMBR or NUMBAR"
+        );
+        assert_eq!(
+            sum(Value::Noob, Value::Numbr(1)),
+            "cannot implicitly cast a NOOB to a NUMBR or NUMBAR"
+        );
+        assert_eq!(
+            sum(yarn("18446744073709551617"), Value::Numbr(1)),
+            "cannot implicitly cast YARN \"18446744073709551617\" to a NUMBR"
+        );
+        assert_eq!(
+            sum(yarn("-9223372036854775808"), Value::Numbr(1)),
+            "-9223372036854775807"
+        );
+        assert_eq!(sum(yarn("-0012"), Value::Numbr(0)), "-12");
+        assert_eq!(
+            arith(OpTy::Mod, &Value::Numbr(-7), &Value::Numbr(2))
+                .ok()
+                .map(|v| eq(&v, &Value::Numbr(-1))),
+            Some(true)
+        );
+        assert!(arith(OpTy::Div, &Value::Numbr(1), &Value::Numbr(0)).is_err());
+        assert!(eq(&Value::Numbr(1), &Value::Numbar(1.0)));
+        assert!(!eq(&Value::Numbr(1), &yarn("1")));
+    }
+
+    #[test]
+    fn truthiness() {
+        let fail = [
+            Value::Noob,
+            Value::Troof(false),
+            Value::Numbr(0),
+            Value::Numbar(0.0),
+            Value::Numbar(-0.0),
+            yarn(""),
+        ];
+        assert!(fail.iter().all(|v| !v.truthy()));
+        let win = [
+            Value::Troof(true),
+            Value::Numbr(-1),
+            Value::Numbar(0.5),
+            yarn("0"),
+        ];
+        assert!(win.iter().all(Value::truthy));
+    }
+}
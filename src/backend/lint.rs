@@ -0,0 +1,660 @@
+//! Lints, which warn about programs that are valid but probably wrong. Lints can be allowed or
+//! denied on the command line, and allowed in the source with a `BTW lulz: allow(<lint>, ...)`
+//! comment. The comment covers the whole file before `HAI`, the statement it trails, or
+//! otherwise the statement after it.
+
+use std::borrow::Cow;
+
+use hashbrown::HashMap;
+
+use crate::diagnostics::prelude::*;
+use crate::frontend::ast::*;
+use crate::frontend::lex::Comment;
+
+use super::interner::{Interner, StrId};
+
+/// Which lints are turned off, and which are errors
+#[derive(Debug, Default, Clone)]
+pub struct Levels {
+    pub allow: Vec<DiagnosticType>,
+    pub deny: Vec<DiagnosticType>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeclKind {
+    Variable,
+    Parameter,
+    /// Funkshuns and loop variables, which are never reported as unused
+    Other,
+}
+
+struct Decl {
+    name: Ident,
+    kind: DeclKind,
+    used: bool,
+}
+
+struct Linter<'a> {
+    interner: &'a Interner,
+    decls: Vec<Decl>,
+    /// Names declared in each scope, with the globals first
+    scopes: Vec<HashMap<StrId, usize>>,
+    lints: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn name(&self, name: &Ident) -> &str {
+        self.interner.lookup(name.0)
+    }
+
+    fn find(&self, name: StrId) -> Option<(usize, usize)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.get(&name).map(|&decl| (depth, decl)))
+    }
+
+    fn declare(&mut self, name: &Ident, kind: DeclKind) {
+        let depth = self.scopes.len() - 1;
+        if let Some((outer, decl)) = self.find(name.0) {
+            // Redeclaring a variable in the same scope, or a local in another local scope, is
+            // already an error
+            let shadows = outer != depth && (kind != DeclKind::Variable || outer == 0);
+            if shadows && !self.name(name).starts_with('_') {
+                let lint = Diagnostic::build(DiagnosticType::Shadowing, name.1)
+                    .annotation(
+                        Cow::Owned(format!(
+                            "`{}` shadows an earlier declaration",
+                            self.name(name)
+                        )),
+                        name.1,
+                    )
+                    .annotation(
+                        Cow::Borrowed("which is declared here"),
+                        self.decls[decl].name.1,
+                    );
+                self.lints.push(lint);
+            }
+        }
+
+        self.decls.push(Decl {
+            name: *name,
+            kind,
+            used: false,
+        });
+        let decl = self.decls.len() - 1;
+        self.scopes.last_mut().unwrap().insert(name.0, decl);
+    }
+
+    fn use_name(&mut self, name: StrId) {
+        if let Some((_, decl)) = self.find(name) {
+            self.decls[decl].used = true;
+        }
+    }
+
+    fn new_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leaves a scope, and reports whatever it declared but never used
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        let mut decls: Vec<_> = scope.values().map(|&decl| &self.decls[decl]).collect();
+        decls.sort_by_key(|decl| decl.name.1.s);
+        let mut lints = Vec::new();
+        for decl in decls {
+            let name = self.interner.lookup(decl.name.0);
+            if decl.used || name.starts_with('_') {
+                continue;
+            }
+            let (ty, what) = match decl.kind {
+                DeclKind::Variable => (DiagnosticType::UnusedVariable, "variable"),
+                DeclKind::Parameter => (DiagnosticType::UnusedParameter, "parameter"),
+                DeclKind::Other => continue,
+            };
+            let lint = Diagnostic::build(ty, decl.name.1)
+                .annotation(
+                    Cow::Owned(format!("{} `{}` is never used", what, name)),
+                    decl.name.1,
+                )
+                .note(Cow::Borrowed(
+                    "if this is intentional, start the name with an underscore",
+                ));
+            lints.push(lint);
+        }
+        self.lints.extend(lints);
+    }
+
+    /// Lints the statements of a block, without opening a scope for it
+    fn stmts(&mut self, block: &Block, outermost: bool) {
+        let exit = block
+            .0
+            .iter()
+            .position(|stmt| matches!(stmt.ty, StmtTy::Break | StmtTy::Return(_)));
+        if let Some((exit, unreachable)) =
+            exit.and_then(|i| Some((&block.0[i], block.0.get(i + 1)?)))
+        {
+            let keyword = match exit.ty {
+                StmtTy::Break => "GTFO",
+                _ => "FOUND YR",
+            };
+            let last = block.0.last().unwrap();
+            let lint = Diagnostic::build(DiagnosticType::Unreachable, unreachable.span)
+                .annotation(
+                    Cow::Borrowed("this code is never run"),
+                    Span::new(unreachable.span.s, last.span.e, last.span.file),
+                )
+                .annotation(
+                    Cow::Owned(format!("because of this `{}`", keyword)),
+                    exit.span,
+                );
+            self.lints.push(lint);
+        }
+
+        // An expression statement whose value in IT hasn't been read yet
+        let mut unread_it: Option<Span> = None;
+        for stmt in block.0.iter() {
+            if reads_it(stmt) {
+                unread_it = None;
+            }
+            if let StmtTy::Expr(expr) = &stmt.ty {
+                if let Some(unread) = unread_it {
+                    self.unused_it(unread, Some(stmt.span));
+                }
+                let is_call = matches!(expr.ty, ExprTy::FunctionCall(..) | ExprTy::MethodCall(..));
+                unread_it = if is_call { None } else { Some(stmt.span) };
+            }
+            self.stmt(stmt);
+        }
+
+        // The last value of a funkshun is its return value, and IT stays around after other
+        // blocks, so only the end of the program is known to drop it
+        if let (Some(unread), true) = (unread_it, outermost) {
+            self.unused_it(unread, None);
+        }
+    }
+
+    fn unused_it(&mut self, unread: Span, overwritten: Option<Span>) {
+        let mut lint = Diagnostic::build(DiagnosticType::UnusedIt, unread).annotation(
+            Cow::Borrowed("this value is stored in IT, but never read"),
+            unread,
+        );
+        if let Some(span) = overwritten {
+            lint = lint.annotation(Cow::Borrowed("IT is overwritten here"), span);
+        }
+        self.lints.push(lint);
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.new_scope();
+        self.stmts(block, false);
+        self.pop_scope();
+    }
+
+    fn function(&mut self, args: &[Ident], block: &Block) {
+        self.new_scope();
+        for arg in args {
+            self.declare(arg, DeclKind::Parameter);
+        }
+        self.stmts(block, false);
+        self.pop_scope();
+    }
+
+    fn decl_value(&mut self, value: &Option<Result<Expr, LolTy>>) {
+        if let Some(Ok(expr)) = value {
+            self.expr(expr);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.ty {
+            StmtTy::Assignment(_, expr) | StmtTy::Expr(expr) | StmtTy::Return(expr) => {
+                self.expr(expr)
+            }
+            StmtTy::DecAssign(name, value) => {
+                self.decl_value(value);
+                self.declare(name, DeclKind::Variable);
+            }
+            StmtTy::Import(_) | StmtTy::Break | StmtTy::Input(_) => {}
+            StmtTy::FunctionDef(name, args, block) => {
+                self.declare(name, DeclKind::Other);
+                self.function(args, block);
+            }
+            StmtTy::Case(cases, default) => {
+                for (expr, block) in cases {
+                    self.expr(expr);
+                    self.block(block);
+                }
+                if let Some(block) = default {
+                    self.block(block);
+                }
+            }
+            StmtTy::If(ya_rly, mebbes, no_wai) => {
+                if ya_rly.is_none() {
+                    let note = match no_wai {
+                        Some(_) => "negate the condition, and use a `YA RLY` branch instead",
+                        None => {
+                            "without a `YA RLY` branch, the `MEBBE` branches are always skipped"
+                        }
+                    };
+                    let lint = Diagnostic::build(DiagnosticType::MissingYaRly, stmt.span)
+                        .annotation(Cow::Borrowed("this `O RLY?` has no `YA RLY`"), stmt.span)
+                        .note(Cow::Borrowed(note));
+                    self.lints.push(lint);
+                }
+                if let Some(block) = ya_rly {
+                    self.block(block);
+                }
+                for (expr, block) in mebbes {
+                    self.expr(expr);
+                    self.block(block);
+                }
+                if let Some(block) = no_wai {
+                    self.block(block);
+                }
+            }
+            StmtTy::MutCast(name, _) => self.use_name(name.0),
+            StmtTy::Loop { fn_id, block, .. } => {
+                self.new_scope();
+                if let Some((_, var, cond)) = fn_id {
+                    self.declare(var, DeclKind::Other);
+                    if let LoopCond::Till(expr) | LoopCond::While(expr) = cond {
+                        self.expr(expr);
+                    }
+                }
+                self.block(block);
+                self.pop_scope();
+            }
+            StmtTy::Print(exprs, _) => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            StmtTy::Append(source, item) => {
+                self.expr(source);
+                self.expr(item);
+            }
+            StmtTy::SetItem(source, item, index) => {
+                self.expr(source);
+                self.expr(item);
+                if let Ok(index) = index {
+                    self.expr(index);
+                }
+            }
+            StmtTy::ObjectDef(name, parent, block) => {
+                if let Some(parent) = parent {
+                    self.expr(parent);
+                }
+                self.declare(name, DeclKind::Other);
+                self.new_scope();
+                for stmt in block.0.iter() {
+                    match &stmt.ty {
+                        // Declarations directly in the body are slots
+                        StmtTy::DecAssign(_, value) => self.decl_value(value),
+                        StmtTy::FunctionDef(_, args, block) => self.function(args, block),
+                        _ => self.stmt(stmt),
+                    }
+                }
+                self.pop_scope();
+            }
+            StmtTy::SlotDec(object, _, value) => {
+                self.expr(object);
+                self.decl_value(value);
+            }
+            StmtTy::SlotAssign(object, _, value) => {
+                self.expr(object);
+                self.expr(value);
+            }
+            StmtTy::MethodDef(object, _, args, block) => {
+                self.expr(object);
+                self.function(args, block);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.ty {
+            ExprTy::Variable(name) => self.use_name(name.0),
+            ExprTy::InterpStr(_, entries) => {
+                for InterpEntry(_, name, _) in entries {
+                    self.use_str(name);
+                }
+            }
+            ExprTy::FunctionCall(name, args) => {
+                self.use_name(name.0);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprTy::List(exprs)
+            | ExprTy::Concat(exprs)
+            | ExprTy::All(exprs)
+            | ExprTy::Any(exprs) => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            ExprTy::Cast(expr, _) | ExprTy::UnaryOp(_, expr) | ExprTy::GetSlot(expr, _) => {
+                self.expr(expr)
+            }
+            ExprTy::Operator(_, a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            ExprTy::GetItem(source, index) => {
+                self.expr(source);
+                if let Ok(index) = index {
+                    self.expr(index);
+                }
+            }
+            ExprTy::Bukkit(parent) => {
+                if let Some(parent) = parent {
+                    self.expr(parent);
+                }
+            }
+            ExprTy::MethodCall(object, _, args) => {
+                self.expr(object);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprTy::Span(_)
+            | ExprTy::Float(_)
+            | ExprTy::Int(_)
+            | ExprTy::String(_)
+            | ExprTy::Bool(_)
+            | ExprTy::Null
+            | ExprTy::It
+            | ExprTy::Me => {}
+        }
+    }
+
+    /// Uses a name that was only kept as a string, like in `:{name}`
+    fn use_str(&mut self, name: &str) {
+        let found = self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .iter()
+                .find(|(id, _)| self.interner.lookup(**id) == name)
+                .map(|(_, &decl)| decl)
+        });
+        if let Some(decl) = found {
+            self.decls[decl].used = true;
+        }
+    }
+}
+
+fn expr_reads_it(expr: &Expr) -> bool {
+    match &expr.ty {
+        ExprTy::It => true,
+        ExprTy::FunctionCall(_, exprs)
+        | ExprTy::List(exprs)
+        | ExprTy::Concat(exprs)
+        | ExprTy::All(exprs)
+        | ExprTy::Any(exprs) => exprs.iter().any(expr_reads_it),
+        ExprTy::Cast(expr, _) | ExprTy::UnaryOp(_, expr) | ExprTy::GetSlot(expr, _) => {
+            expr_reads_it(expr)
+        }
+        ExprTy::Operator(_, a, b) => expr_reads_it(a) || expr_reads_it(b),
+        ExprTy::GetItem(source, index) => {
+            expr_reads_it(source) || index.as_ref().is_ok_and(|index| expr_reads_it(index))
+        }
+        ExprTy::Bukkit(parent) => parent.as_ref().is_some_and(|parent| expr_reads_it(parent)),
+        ExprTy::MethodCall(object, _, args) => {
+            expr_reads_it(object) || args.iter().any(expr_reads_it)
+        }
+        _ => false,
+    }
+}
+
+fn block_reads_it(block: &Block) -> bool {
+    block.0.iter().any(reads_it)
+}
+
+/// Whether a statement reads IT before the next statement runs. Funkshuns have their own IT.
+fn reads_it(stmt: &Stmt) -> bool {
+    match &stmt.ty {
+        StmtTy::If(..) | StmtTy::Case(..) => true,
+        StmtTy::Assignment(_, expr) | StmtTy::Expr(expr) | StmtTy::Return(expr) => {
+            expr_reads_it(expr)
+        }
+        StmtTy::DecAssign(_, Some(Ok(expr))) | StmtTy::SlotDec(_, _, Some(Ok(expr))) => {
+            expr_reads_it(expr)
+        }
+        StmtTy::Loop { fn_id, block, .. } => {
+            let cond = match fn_id {
+                Some((_, _, LoopCond::Till(expr))) | Some((_, _, LoopCond::While(expr))) => {
+                    expr_reads_it(expr)
+                }
+                _ => false,
+            };
+            cond || block_reads_it(block)
+        }
+        StmtTy::Print(exprs, _) => exprs.iter().any(expr_reads_it),
+        StmtTy::Append(a, b) | StmtTy::SlotAssign(a, _, b) => expr_reads_it(a) || expr_reads_it(b),
+        StmtTy::SetItem(a, b, index) => {
+            expr_reads_it(a) || expr_reads_it(b) || index.as_ref().is_ok_and(expr_reads_it)
+        }
+        StmtTy::ObjectDef(_, parent, _) => parent.as_ref().is_some_and(expr_reads_it),
+        StmtTy::MethodDef(object, ..) => expr_reads_it(object),
+        _ => false,
+    }
+}
+
+/// Parses a `lulz: allow(a, b)` comment into the lints it names
+fn allowed_lints(comment: &Comment) -> Option<Vec<DiagnosticType>> {
+    let text = comment.text.trim().strip_prefix("lulz:")?.trim();
+    let list = text.strip_prefix("allow")?.trim().strip_prefix('(')?;
+    let list = list.strip_suffix(')')?;
+    Some(
+        list.split(',')
+            .filter_map(|name| DiagnosticType::lint(name.trim()))
+            .collect(),
+    )
+}
+
+fn collect_spans(block: &Block, spans: &mut Vec<Span>) {
+    for stmt in block.0.iter() {
+        spans.push(stmt.span);
+        let mut child = |block: &Block| collect_spans(block, spans);
+        match &stmt.ty {
+            StmtTy::FunctionDef(_, _, block)
+            | StmtTy::Loop { block, .. }
+            | StmtTy::ObjectDef(_, _, block)
+            | StmtTy::MethodDef(_, _, _, block) => child(block),
+            StmtTy::Case(cases, default) => {
+                cases.iter().for_each(|(_, block)| child(block));
+                default.iter().for_each(child);
+            }
+            StmtTy::If(ya_rly, mebbes, no_wai) => {
+                ya_rly.iter().for_each(&mut child);
+                mebbes.iter().for_each(|(_, block)| child(block));
+                no_wai.iter().for_each(child);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where a `BTW lulz: allow(...)` comment applies, or `None` for the whole file
+fn allowed_range(
+    comment: &Comment,
+    program: &Block,
+    spans: &[Span],
+    source: &[char],
+) -> Option<(usize, usize)> {
+    let Span { s, e, .. } = comment.span;
+    if s < program.1.s {
+        return None;
+    }
+    let line_start = source[..s.min(source.len())]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let trailed = spans
+        .iter()
+        .filter(|span| (line_start..s).contains(&span.s))
+        .map(|span| span.e)
+        .max();
+    match trailed {
+        Some(end) => Some((line_start, end)),
+        None => Some(
+            spans
+                .iter()
+                .find(|span| span.s >= e)
+                .map_or((e, e), |span| (span.s, span.e)),
+        ),
+    }
+}
+
+/// Lints a parsed program, returning warnings and denied lints as errors
+pub fn lint(
+    program: &Block,
+    comments: &[Comment],
+    source: &str,
+    interner: &Interner,
+    levels: &Levels,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        interner,
+        decls: Vec::new(),
+        scopes: vec![HashMap::new()],
+        lints: Vec::new(),
+    };
+    linter.stmts(program, true);
+    linter.pop_scope();
+
+    let mut spans = Vec::new();
+    collect_spans(program, &mut spans);
+    let source: Vec<char> = source.chars().collect();
+    let mut file_allowed = levels.allow.clone();
+    let mut allowed_ranges = Vec::new();
+    for comment in comments {
+        if let Some(lints) = allowed_lints(comment) {
+            match allowed_range(comment, program, &spans, &source) {
+                Some(range) => allowed_ranges.push((range, lints)),
+                None => file_allowed.extend(lints),
+            }
+        }
+    }
+
+    let mut lints = linter.lints;
+    lints.retain(|lint| {
+        !file_allowed.contains(&lint.ty)
+            && !allowed_ranges.iter().any(|((s, e), allowed)| {
+                (*s..*e).contains(&lint.span.s) && allowed.contains(&lint.ty)
+            })
+    });
+    lints.sort_by_key(|lint| lint.span.s);
+    lints
+        .into_iter()
+        .map(|lint| {
+            let severity = if levels.deny.contains(&lint.ty) {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            lint.severity(severity)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod lint_test {
+    use super::*;
+    use crate::frontend::{lex::Lexer, parse::Parser};
+
+    fn lints_with(source: &str, levels: &Levels) -> Vec<(DiagnosticType, Severity)> {
+        let mut interner = Interner::default();
+        let mut parser = Parser::new(Lexer::new(source.chars(), 0, &mut interner));
+        let program = parser.parse().expect("Failed to parse");
+        let comments = parser.comments();
+        lint(&program, &comments, source, &interner, levels)
+            .into_iter()
+            .map(|lint| (lint.ty, lint.severity))
+            .collect()
+    }
+
+    fn lints(source: &str) -> Vec<DiagnosticType> {
+        lints_with(source, &Levels::default())
+            .into_iter()
+            .map(|(ty, _)| ty)
+            .collect()
+    }
+
+    #[test]
+    fn unused() {
+        assert_eq!(
+            lints("HAI 1.2\nI HAS A x ITZ 1\nI HAS A _y\nHOW IZ I f YR a AN YR b\nFOUND YR a\nIF U SAY SO\nKTHXBYE\n"),
+            [DiagnosticType::UnusedVariable, DiagnosticType::UnusedParameter]
+        );
+        assert_eq!(
+            lints("HAI 1.2\nI HAS A x ITZ 1\nVISIBLE \":{x}\"\nI HAS A y\nGIMMEH y, y IS NOW A NUMBR\nKTHXBYE\n"),
+            []
+        );
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(
+            lints("HAI 1.2\nHOW IZ I f\nFOUND YR 1\nVISIBLE 1\nVISIBLE 2\nIF U SAY SO\nKTHXBYE\n"),
+            [DiagnosticType::Unreachable]
+        );
+        assert_eq!(
+            lints("HAI 1.2\nIM IN YR l\nVISIBLE 1\nGTFO\nIM OUTTA YR l\nKTHXBYE\n"),
+            []
+        );
+    }
+
+    #[test]
+    fn missing_ya_rly() {
+        assert_eq!(
+            lints("HAI 1.2\nWIN, O RLY?\nNO WAI\nVISIBLE 1\nOIC\nKTHXBYE\n"),
+            [DiagnosticType::MissingYaRly]
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            lints("HAI 1.2\nI HAS A x ITZ 1\nHOW IZ I f YR x\nFOUND YR x\nIF U SAY SO\nWIN, O RLY?\nYA RLY\nI HAS A x ITZ 2\nVISIBLE x\nOIC\nVISIBLE x\nKTHXBYE\n"),
+            [DiagnosticType::Shadowing, DiagnosticType::Shadowing]
+        );
+    }
+
+    #[test]
+    fn unused_it() {
+        assert_eq!(
+            lints("HAI 1.2\n1\nSUM OF IT AN 1\nVISIBLE IT\n2\n3, O RLY?\nYA RLY\nVISIBLE 1\nOIC\nKTHXBYE\n"),
+            [DiagnosticType::UnusedIt]
+        );
+        // The last value of a funkshun is returned
+        assert_eq!(
+            lints("HAI 1.2\nHOW IZ I f\n1\nIF U SAY SO\nI IZ f MKAY\nKTHXBYE\n"),
+            []
+        );
+    }
+
+    #[test]
+    fn allow_comments() {
+        let source = "HAI 1.2\nI HAS A a BTW lulz: allow(unused_variable)\nBTW lulz: allow(unused_variable, shadowing)\nI HAS A b\nI HAS A c\nKTHXBYE\n";
+        assert_eq!(lints(source), [DiagnosticType::UnusedVariable]);
+        let source = "BTW lulz: allow(unused_variable)\nHAI 1.2\nI HAS A a\nKTHXBYE\n";
+        assert_eq!(lints(source), []);
+    }
+
+    #[test]
+    fn levels() {
+        let levels = Levels {
+            allow: vec![DiagnosticType::UnusedParameter],
+            deny: vec![DiagnosticType::UnusedVariable],
+        };
+        assert_eq!(
+            lints_with(
+                "HAI 1.2\nI HAS A x\nHOW IZ I f YR a\nIF U SAY SO\nKTHXBYE\n",
+                &levels
+            ),
+            [(DiagnosticType::UnusedVariable, Severity::Error)]
+        );
+    }
+}
@@ -1,2 +1,4 @@
 pub mod interner;
+pub mod interp;
+pub mod lint;
 pub mod translator;
@@ -1,9 +1,16 @@
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
+use smallvec::SmallVec;
 
+use crate::coverage::{Probe, LUA_COVERAGE};
 use crate::diagnostics::prelude::*;
+use crate::frontend::{lex::Lexer, parse::Parser};
 use crate::runtime::builtins;
+use crate::runtime::debug_info::{DebugInfo, FunctionInfo};
+use crate::sourcemap::SourceMap;
 use crate::{diagnostics::Failible, frontend::ast::*};
 use std::fmt::Write;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
 
 use super::interner::{Interner, StrId};
 
@@ -11,20 +18,124 @@ pub struct Translator {
     pub code: String,
     interner: Interner,
     local_scope: bool,
-    globals: HashSet<StrId>,
-    locals: Vec<HashSet<StrId>>,
+    /// Names in scope, and the symbol they refer to
+    globals: HashMap<StrId, usize>,
+    locals: Vec<HashMap<StrId, usize>>,
+    /// Every name declared so far
+    pub symbols: Vec<Symbol>,
+    /// Every use of a declared name, and the symbol it refers to
+    pub references: Vec<(Span, usize)>,
+    /// The files translated, including the modules they import
+    pub sources: SourceMap,
+    /// What a `GTFO` currently jumps out of, innermost last
+    break_targets: Vec<BreakTarget>,
+    /// Whether `ME` refers to a bukkit here
+    has_me: bool,
+    /// Directories searched for modules after the importing file's own directory
+    search_path: Vec<PathBuf>,
+    /// Files currently being translated, along with where they were imported
+    import_stack: Vec<(PathBuf, Span)>,
+    imported: HashSet<PathBuf>,
+    /// The statement each generated line was emitted for, indexed by line number - 1
+    line_spans: Vec<Option<Span>>,
+    current_span: Option<Span>,
+    functions: Vec<FunctionInfo>,
+    /// The coverage probes emitted so far, or `None` unless coverage is on
+    probes: Option<Vec<Probe>>,
+    /// The AST of every imported module, by the span of the import, or `None` unless kept
+    modules: Option<Vec<(Span, Block)>>,
+    /// The index of each NUMBAR literal of the chunk in `LUA_NUMBARS`, by its bits
+    numbars: HashMap<u64, usize>,
+    /// Errors from statements that failed to translate, reported once the whole program is checked
+    errors: SmallVec<[Diagnostic; 1]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: Ident,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum SymbolKind {
+    /// A variable, with the type it was declared with by `ITZ A`
+    Variable(Option<LolTy>),
+    /// A funkshun, with its arguments
+    Function(Vec<Ident>),
+    /// A function of the host, defined with `define_host`
+    Host,
+}
+
+/// The global names of a program, and the lua globals they are emitted as
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    globals: HashMap<String, (String, SymbolKind)>,
+}
+
+impl SymbolTable {
+    /// The lua global `name` is emitted as, and what it is
+    pub fn get(&self, name: &str) -> Option<(&str, &SymbolKind)> {
+        self.globals
+            .get(name)
+            .map(|(symbol, kind)| (symbol.as_str(), kind))
+    }
+
+    /// The lua global of the funkshun `name`
+    pub fn function(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            (symbol, SymbolKind::Function(_)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    /// Every global, as its name, lua global and kind
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &SymbolKind)> {
+        self.globals
+            .iter()
+            .map(|(name, (symbol, kind))| (name.as_str(), symbol.as_str(), kind))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakTarget {
+    Function,
+    Loop,
+    Switch,
 }
 
 type TransRes = Failible<()>;
 
+/// NUMBRs outside of this range can't be represented exactly by a lua number
+const MAX_EXACT_INT: i64 = (1 << 53) - 1;
+/// A local of the chunk holding its NUMBAR literals, which are boxed once instead of every time
+/// they are evaluated
+const LUA_NUMBARS: &str = "_lulz_numbars";
+/// Lua allows a function 200 locals, and the body of a funkshun needs some of them too
+const MAX_PARAMETERS: usize = 150;
+
 impl Translator {
-    pub fn new(interner: Interner) -> Self {
+    pub fn new(interner: Interner, search_path: Vec<PathBuf>, sources: SourceMap) -> Self {
         Self {
             code: String::new(),
             interner,
             local_scope: false,
-            globals: HashSet::new(),
+            globals: HashMap::new(),
             locals: Vec::new(),
+            symbols: Vec::new(),
+            references: Vec::new(),
+            sources,
+            break_targets: Vec::new(),
+            has_me: false,
+            search_path,
+            import_stack: Vec::new(),
+            imported: HashSet::new(),
+            line_spans: Vec::new(),
+            current_span: None,
+            functions: Vec::new(),
+            probes: None,
+            modules: None,
+            numbars: HashMap::new(),
+            errors: SmallVec::new(),
         }
     }
 
@@ -45,6 +156,7 @@ impl Translator {
     }
 
     fn newline(&mut self) {
+        self.line_spans.push(self.current_span);
         self.writec('\n');
     }
 
@@ -84,6 +196,10 @@ impl Translator {
         self.writes("_lulz_it");
     }
 
+    fn me(&mut self) {
+        self.writes("_lulz_me");
+    }
+
     fn list(&mut self, first: Option<&Expr>, items: &[Expr]) -> TransRes {
         let mut items = items.iter();
         let next = first.map(|f| Some(f)).unwrap_or_else(|| items.next());
@@ -131,12 +247,39 @@ impl Translator {
     fn _block(&mut self, block: Block) -> TransRes {
         self.newline();
         for stmt in block.0.into_iter() {
-            self.stmt(stmt)?;
-            self.newline()
+            let prev = self.current_span.replace(stmt.span);
+            self.probe(Probe::Statement(stmt.span));
+            self.block_stmt(stmt);
+            self.newline();
+            self.current_span = prev;
         }
         Ok(())
     }
 
+    /// Translates a statement of a block. Its errors are collected, so the rest of the program is
+    /// still checked.
+    fn block_stmt(&mut self, stmt: Stmt) {
+        let scopes = self.locals.len();
+        let break_targets = self.break_targets.len();
+        let local_scope = self.local_scope;
+        let has_me = self.has_me;
+        if let Err(es) = self.stmt(stmt) {
+            self.errors.extend(es.into_inner());
+            self.locals.truncate(scopes);
+            self.break_targets.truncate(break_targets);
+            self.local_scope = local_scope;
+            self.has_me = has_me;
+        }
+    }
+
+    fn take_errors(&mut self) -> TransRes {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors).into())
+        }
+    }
+
     fn fn_block(&mut self, block: Block) -> TransRes {
         let prev = std::mem::replace(&mut self.local_scope, true);
         self._block(block)?;
@@ -152,7 +295,251 @@ impl Translator {
     }
 
     pub fn outer_block(&mut self, block: Block) -> TransRes {
-        self._block(block)
+        let path = self.file_path(block.1.file);
+        self.imported.insert(path.clone());
+        self.import_stack.push((path, block.1));
+        let start = self.code.len();
+        self._block(block)?;
+        self.import_stack.pop();
+        self.declare_numbars(start);
+        self.take_errors()
+    }
+
+    /// Declares the NUMBAR literals of the chunk at `start`, which must be the start of a line
+    /// before any of them are used. They go on that line, so later lines keep their numbers.
+    fn declare_numbars(&mut self, start: usize) {
+        if self.numbars.is_empty() {
+            return;
+        }
+        let mut numbars: Vec<_> = self.numbars.drain().collect();
+        numbars.sort_by_key(|&(_, i)| i);
+        let literals: Vec<_> = numbars
+            .iter()
+            .map(|&(bits, _)| format!("{}({})", builtins::ops::LUA_NUMBAR, f64::from_bits(bits)))
+            .collect();
+        let declaration = format!("local {}={{{}}} ", LUA_NUMBARS, literals.join(","));
+        self.code.insert_str(start, &declaration);
+    }
+
+    /// Makes a host function callable by `name` in the code translated from now on
+    pub fn define_host(&mut self, name: &str) {
+        let id = Ident(self.interner.intern(name), Span::default());
+        self.define_global(&id, SymbolKind::Host);
+        self.ident(&id);
+        self.eq();
+        self.writes(builtins::host::LUA_HOST);
+        self.writec('[');
+        self.raw_string(name);
+        self.writec(']');
+        self.newline();
+    }
+
+    /// The globals defined so far, to find them in the lua state the program ran in
+    pub fn symbol_table(&self) -> SymbolTable {
+        let globals = self
+            .globals
+            .iter()
+            .map(|(&id, &symbol)| {
+                (
+                    self.interner.lookup(id).to_string(),
+                    (Self::mangle(id), self.symbols[symbol].kind.clone()),
+                )
+            })
+            .collect();
+        SymbolTable { globals }
+    }
+
+    /// What is needed to trace errors in the generated code back to LOLCODE
+    pub fn debug_info(&self) -> DebugInfo {
+        DebugInfo {
+            line_spans: self.line_spans.clone(),
+            names: self
+                .interner
+                .strings()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            functions: self.functions.clone(),
+        }
+    }
+
+    /// Emits coverage probes in the code translated from now on, for `--coverage`
+    pub fn instrument(&mut self) {
+        self.probes.get_or_insert_with(Vec::new);
+    }
+
+    /// The coverage probes in the code, in the order they count into the table
+    pub fn probes(&self) -> &[Probe] {
+        self.probes.as_deref().unwrap_or(&[])
+    }
+
+    /// Keeps the AST of the modules imported from now on, for backends that run the AST
+    pub fn keep_modules(&mut self) {
+        self.modules.get_or_insert_with(Vec::new);
+    }
+
+    /// The kept modules, by the span of the `CAN I HAS` that translated them. Later imports of a
+    /// module translate nothing, so they have none.
+    pub fn modules(&self) -> &[(Span, Block)] {
+        self.modules.as_deref().unwrap_or(&[])
+    }
+
+    /// Counts a run of a probe, if coverage is on
+    fn probe(&mut self, probe: Probe) {
+        if let Some(probes) = self.probes.as_mut() {
+            probes.push(probe);
+            let _ = write!(
+                self.code,
+                " {0}[{1}]={0}[{1}]+1 ",
+                LUA_COVERAGE,
+                probes.len()
+            );
+        }
+    }
+
+    /// Translates one input of the repl, replacing the code of the previous input. What it
+    /// declares stays defined for later inputs, and IT is printed after every expression statement.
+    pub fn repl_block(&mut self, block: Block) -> TransRes {
+        self.code.clear();
+        self.line_spans.clear();
+        let globals = self.globals.clone();
+        let imported = self.imported.clone();
+        self.import_stack
+            .push((self.file_path(block.1.file), block.1));
+        let res = self.repl_stmts(block).and_then(|()| self.take_errors());
+        self.import_stack.clear();
+        self.declare_numbars(0);
+        if res.is_err() {
+            // None of a failed input runs, so nothing it declared or imported exists
+            self.globals = globals;
+            self.imported = imported;
+            self.locals.clear();
+            self.break_targets.clear();
+            self.local_scope = false;
+            self.has_me = false;
+            self.current_span = None;
+        }
+        res
+    }
+
+    fn repl_stmts(&mut self, block: Block) -> TransRes {
+        self.newline();
+        for stmt in block.0.into_iter() {
+            let prints_it = matches!(stmt.ty, StmtTy::Expr(_));
+            let prev = self.current_span.replace(stmt.span);
+            self.block_stmt(stmt);
+            if prints_it {
+                self.newline();
+                self.writes(builtins::io::LUA_PRINTLN);
+                self.lparen();
+                self.it_var();
+                self.rparen();
+            }
+            self.newline();
+            self.current_span = prev;
+        }
+        Ok(())
+    }
+
+    pub fn interner(&mut self) -> &mut Interner {
+        &mut self.interner
+    }
+
+    fn file_path(&self, file: usize) -> PathBuf {
+        let name = PathBuf::from(self.sources.get(file).unwrap().name());
+        name.canonicalize().unwrap_or(name)
+    }
+
+    fn find_module(&self, name: &str, importer: &Path) -> Option<PathBuf> {
+        let filename = format!("{}.lol", name);
+        importer
+            .parent()
+            .into_iter()
+            .chain(self.search_path.iter().map(|p| p.as_path()))
+            .map(|dir| dir.join(&filename))
+            .find(|path| path.is_file())
+            .map(|path| path.canonicalize().unwrap_or(path))
+    }
+
+    fn import(&mut self, name: &Ident, span: Span) -> TransRes {
+        let module = self.id_to_str(name).to_string();
+        let importer = self.import_stack.last().unwrap().0.clone();
+        let path = self.find_module(&module, &importer).ok_or_else(|| {
+            Diagnostic::build(DiagnosticType::Import, name.1)
+                .annotation(
+                    Cow::Owned(format!("could not find module `{}.lol`", module)),
+                    name.1,
+                )
+                .note(Cow::Owned(format!(
+                    "searched next to `{}` and in {} include director{}",
+                    importer.display(),
+                    self.search_path.len(),
+                    if self.search_path.len() == 1 {
+                        "y"
+                    } else {
+                        "ies"
+                    }
+                )))
+        })?;
+
+        if let Some(pos) = self.import_stack.iter().position(|(p, _)| p == &path) {
+            let mut err = Diagnostic::build(DiagnosticType::Import, span);
+            for (file, import_span) in self.import_stack[pos + 1..].iter() {
+                err = err.annotation(
+                    Cow::Owned(format!("`{}` is imported here", file.display())),
+                    *import_span,
+                );
+            }
+            let chain = self.import_stack[pos..]
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .chain(std::iter::once(path.display().to_string()))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(err
+                .annotation(
+                    Cow::Owned(format!("which imports `{}` again here", path.display())),
+                    span,
+                )
+                .note(Cow::Owned(format!("import cycle: {}", chain)))
+                .into());
+        }
+
+        if !self.imported.insert(path.clone()) {
+            // Already imported, everything it defines is in scope
+            return Ok(());
+        }
+
+        let source = read_to_string(&path).map_err(|e| {
+            Diagnostic::build(DiagnosticType::Import, name.1).annotation(
+                Cow::Owned(format!("failed to read `{}`: {}", path.display(), e)),
+                name.1,
+            )
+        })?;
+        let id = self.sources.add(path.display().to_string(), source);
+        let lexer = Lexer::new(
+            self.sources.get(id).unwrap().source().chars(),
+            id,
+            &mut self.interner,
+        );
+        let ast = Parser::new(lexer).parse()?;
+        if let Some(modules) = self.modules.as_mut() {
+            modules.push((span, ast.clone()));
+        }
+
+        // Modules are translated at the top level, so their declarations become globals
+        let locals = std::mem::take(&mut self.locals);
+        let break_targets = std::mem::take(&mut self.break_targets);
+        let local_scope = std::mem::replace(&mut self.local_scope, false);
+        let has_me = std::mem::replace(&mut self.has_me, false);
+        self.import_stack.push((path, span));
+        let res = self._block(ast);
+        self.import_stack.pop();
+        self.locals = locals;
+        self.break_targets = break_targets;
+        self.local_scope = local_scope;
+        self.has_me = has_me;
+        res
     }
 
     fn stmt(&mut self, stmt: Stmt) -> TransRes {
@@ -166,49 +553,53 @@ impl Translator {
                 None,
                 &exprs,
             )?,
-            StmtTy::DecAssign(ref name, expr) => match expr {
-                Some(var) => {
-                    let e = match var {
-                        Ok(e) => e,
-                        Err(t) => Expr {
-                            ty: t.default_expr_kind(),
-                            span: stmt.span,
-                        },
-                    };
-                    self.declaration(&name, &e)?;
-                }
-                None => self.declaration(
+            StmtTy::DecAssign(ref name, expr) => {
+                let ty = match expr {
+                    Some(Err(ty)) => Some(ty),
+                    _ => None,
+                };
+                self.declaration(
                     &name,
-                    &Expr {
-                        ty: ExprTy::Null,
-                        span: Span::default(),
-                    },
-                )?,
-            },
+                    &Self::declared_value(expr, stmt.span),
+                    SymbolKind::Variable(ty),
+                )?
+            }
             StmtTy::Assignment(name, expr) => {
                 self.assignment(&name, &expr)?;
             }
+            StmtTy::Input(name) => {
+                if !self.resolve(&name) {
+                    return Err(self.undefined_var_error(&name).into());
+                }
+                self.ident(&name);
+                self.eq();
+                self.writes(builtins::io::LUA_INPUT);
+                self.lparen();
+                self.rparen();
+            }
             StmtTy::FunctionDef(fn_name, args, block) => {
-                self.define_in_scope(&fn_name);
+                self.check_redeclaration(&fn_name, "funkshun")?;
+                self.define_in_scope(&fn_name, SymbolKind::Function(args.clone()));
+                self.record_function(&fn_name, stmt.span);
                 self.function();
                 self.space();
                 self.ident(&fn_name);
-                self.lparen();
-                self.new_scope();
-                let mut args = args.iter();
-                if let Some(first) = args.next() {
-                    self.ident(first);
-                    self.define_local(first);
-                    for arg in args {
-                        self.comma();
-                        self.ident(arg);
-                        self.define_local(arg);
-                    }
-                }
-                self.rparen();
-                self.fn_block(block)?;
-                self.pop_scope();
-                self.end();
+                self.function_body(&fn_name, &args, block, false)?;
+            }
+            StmtTy::ObjectDef(name, parent, block) => self.object_def(name, parent, block)?,
+            StmtTy::SlotDec(object, slot, value) => {
+                self.slot_dec(&object, &slot, &Self::declared_value(value, stmt.span))?
+            }
+            StmtTy::SlotAssign(object, slot, value) => {
+                let key = self.slot_key(&slot);
+                self.call_ref(
+                    builtins::bukkit::LUA_SET_SLOT,
+                    None,
+                    &[&object, &key, &value, &Self::span_expr(stmt.span)],
+                )?;
+            }
+            StmtTy::MethodDef(object, name, args, block) => {
+                self.method_def(&object, &name, &args, block, stmt.span)?
             }
             StmtTy::Expr(expr) => {
                 self.it_var();
@@ -222,43 +613,491 @@ impl Translator {
                 }
                 self.writes("if");
                 self.space();
+                self.writes(builtins::ops::LUA_TRUTHY);
+                self.lparen();
                 self.it_var();
+                self.rparen();
                 self.space();
                 self.then();
+                self.probe(Probe::Branch {
+                    span: stmt.span,
+                    arm: 0,
+                });
                 if let Some(if_block) = if_case {
                     self.block(if_block)?;
                 }
-                for (expr, block) in else_if_cases {
+                let arms = else_if_cases.len();
+                for (arm, (expr, block)) in else_if_cases.into_iter().enumerate() {
                     self.writes("elseif");
                     self.space();
-                    self.expr(&expr)?;
+                    self.condition(&expr)?;
                     self.space();
                     self.then();
+                    self.probe(Probe::Branch {
+                        span: stmt.span,
+                        arm: arm + 1,
+                    });
                     self.block(block)?;
                 }
-                if let Some(else_block) = else_case {
+                if else_case.is_some() || self.probes.is_some() {
+                    // Without a `NO WAI`, coverage still counts when no other arm is taken
                     self.writes("else");
+                    self.probe(Probe::Branch {
+                        span: stmt.span,
+                        arm: arms + 1,
+                    });
+                }
+                if let Some(else_block) = else_case {
                     self.block(else_block)?;
                 }
                 self.end();
             }
+            StmtTy::Loop { fn_id, block, .. } => self.loop_stmt(fn_id, block)?,
+            StmtTy::Import(name) => self.import(&name, stmt.span)?,
+            StmtTy::Case(cases, default) => self.case(cases, default, stmt.span)?,
+            StmtTy::Break => self.break_stmt(stmt.span)?,
             StmtTy::Return(expr) => {
-                self.writes("return");
+                // Wrapped in `do` like `GTFO`, so statements can follow it in the same lua block.
+                // The parentheses stop lua from making a tail call, which would drop the frame
+                // of this funkshun from stack traces.
+                self.writes("do return");
                 self.space();
+                self.lparen();
                 self.expr(&expr)?;
+                self.rparen();
+                self.space();
+                self.end();
             }
-            _ => todo!("Statement not implemented: {:?}", stmt),
+            ty => return Err(unsupported(ty.syntax(), stmt.span).into()),
         }
         Ok(())
     }
 
-    fn declaration(&mut self, name: &Ident, expr: &Expr) -> TransRes {
+    /// Translates the parameters and body of a funkshun. Its arguments are counted on a line of
+    /// their own with no statement, so lua blames the call when there are too many or too few.
+    fn function_body(
+        &mut self,
+        name: &Ident,
+        args: &[Ident],
+        block: Block,
+        method: bool,
+    ) -> TransRes {
+        self.check_parameters(args)?;
+        self.writes("(...)");
+        self.newline();
+        self.new_scope();
+        let prev_span = self.current_span.take();
+        let params = args.len() + method as usize;
+        if params > 0 {
+            self.local();
+            self.space();
+            if method {
+                // The receiver is passed as the hidden first argument
+                self.me();
+                if !args.is_empty() {
+                    self.comma();
+                }
+            }
+            let mut args = args.iter();
+            if let Some(first) = args.next() {
+                self.ident(first);
+                self.define_local(first, SymbolKind::Variable(None));
+                for arg in args {
+                    self.comma();
+                    self.ident(arg);
+                    self.define_local(arg, SymbolKind::Variable(None));
+                }
+            }
+            self.eq();
+            self.writes("...");
+            self.space();
+        }
+        self.writes(&format!("if select(\"#\",...)~={} then ", params));
+        self.writes(builtins::ops::LUA_ARITY);
+        self.lparen();
+        let name = self.id_to_str(name).to_string();
+        self.raw_string(&name);
+        self.writes(&format!(
+            ",{},select(\"#\",...)-{}",
+            args.len(),
+            method as usize
+        ));
+        self.rparen();
+        self.space();
+        self.end();
+        self.space();
+        // IT is local to each funkshun
+        self.local();
+        self.space();
+        self.it_var();
+
+        let has_me = self.has_me || method;
+        let prev_me = std::mem::replace(&mut self.has_me, has_me);
+        self.break_targets.push(BreakTarget::Function);
+        self.fn_block(block)?;
+        self.break_targets.pop();
+        self.has_me = prev_me;
+        self.pop_scope();
+        self.current_span = prev_span;
+        // Without a `FOUND YR` or `GTFO`, a funkshun returns IT
+        self.writes("return");
+        self.space();
+        self.it_var();
+        self.space();
+        self.end();
+        Ok(())
+    }
+
+    /// Fails on parameter lists lua can't declare
+    fn check_parameters(&self, args: &[Ident]) -> TransRes {
+        if args.len() > MAX_PARAMETERS {
+            let span = Span::new(args[0].1.s, args[args.len() - 1].1.e, args[0].1.file);
+            return Err(
+                Diagnostic::build(DiagnosticType::FunctionArgumentMany, span)
+                    .annotation(
+                        Cow::Owned(format!("this funkshun has {} parameters", args.len())),
+                        span,
+                    )
+                    .note(Cow::Owned(format!(
+                        "funkshuns can have at most {} parameters",
+                        MAX_PARAMETERS
+                    )))
+                    .into(),
+            );
+        }
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(first) = args[..i].iter().find(|other| other.0 == arg.0) {
+                return Err(Diagnostic::build(DiagnosticType::Scope, arg.1)
+                    .annotation(
+                        Cow::Owned(format!(
+                            "parameter `{}` is declared twice",
+                            self.id_to_str(arg)
+                        )),
+                        arg.1,
+                    )
+                    .annotation(Cow::Borrowed("first declared here"), first.1)
+                    .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn declared_value(value: Option<Result<Expr, LolTy>>, span: Span) -> Expr {
+        match value {
+            Some(Ok(e)) => e,
+            Some(Err(t)) => Expr {
+                ty: t.default_expr_kind(),
+                span,
+            },
+            None => Expr {
+                ty: ExprTy::Null,
+                span: Span::default(),
+            },
+        }
+    }
+
+    fn slot_key(&self, slot: &Ident) -> Expr {
+        Expr {
+            ty: ExprTy::String(self.id_to_str(slot).to_string()),
+            span: slot.1,
+        }
+    }
+
+    fn slot_dec(&mut self, object: &Expr, slot: &Ident, value: &Expr) -> TransRes {
+        let key = self.slot_key(slot);
+        self.call_ref(
+            builtins::bukkit::LUA_DEC_SLOT,
+            None,
+            &[object, &key, value, &Self::span_expr(slot.1)],
+        )
+    }
+
+    fn method_def(
+        &mut self,
+        object: &Expr,
+        name: &Ident,
+        args: &[Ident],
+        block: Block,
+        span: Span,
+    ) -> TransRes {
+        self.record_function(name, span);
+        self.writes(builtins::bukkit::LUA_DEC_SLOT);
+        self.lparen();
+        self.expr(object)?;
+        self.comma();
+        self.expr(&self.slot_key(name))?;
+        self.comma();
+        self.function();
+        self.function_body(name, args, block, true)?;
+        self.comma();
+        self.write_span(span);
+        self.rparen();
+        Ok(())
+    }
+
+    fn record_function(&mut self, name: &Ident, span: Span) {
+        self.functions.push(FunctionInfo {
+            name: name.0.inner(),
+            name_span: name.1,
+            span,
+        });
+    }
+
+    fn object_def(&mut self, name: Ident, parent: Option<Expr>, block: Block) -> TransRes {
+        let reopened = self.resolve(&name);
+        if !reopened {
+            let parent = parent.clone().map(Box::new);
+            self.declaration(
+                &name,
+                &Expr {
+                    ty: ExprTy::Bukkit(parent),
+                    span: name.1,
+                },
+                SymbolKind::Variable(Some(LolTy::Bukkit)),
+            )?;
+            self.newline();
+        }
+
+        // Inside the block, `ME` is the bukkit being defined
+        self.writes("do");
+        self.newline();
+        self.local();
+        self.space();
+        self.me();
+        self.eq();
+        self.ident(&name);
+        self.newline();
+        if let (true, Some(parent)) = (reopened, parent) {
+            let parent_slot = Ident(self.interner.intern("parent"), parent.span);
+            self.slot_dec(
+                &Expr {
+                    ty: ExprTy::Me,
+                    span: name.1,
+                },
+                &parent_slot,
+                &parent,
+            )?;
+            self.newline();
+        }
+
+        let prev_me = std::mem::replace(&mut self.has_me, true);
+        let prev_scope = std::mem::replace(&mut self.local_scope, true);
+        self.new_scope();
+        let me = Expr {
+            ty: ExprTy::Me,
+            span: name.1,
+        };
+        for stmt in block.0.into_iter() {
+            // Declarations directly inside the block become slots of the bukkit
+            match stmt.ty {
+                StmtTy::DecAssign(slot, value) => {
+                    self.slot_dec(&me, &slot, &Self::declared_value(value, stmt.span))?
+                }
+                StmtTy::FunctionDef(method, args, block) => {
+                    self.method_def(&me, &method, &args, block, stmt.span)?
+                }
+                _ => self.stmt(stmt)?,
+            }
+            self.newline();
+        }
+        self.pop_scope();
+        self.local_scope = prev_scope;
+        self.has_me = prev_me;
+
+        self.end();
+        Ok(())
+    }
+
+    fn loop_stmt(&mut self, fn_id: Option<(Ident, Ident, LoopCond)>, block: Block) -> TransRes {
+        // The whole loop lives in its own `do` block so the loop variable stays local to it
+        self.writes("do");
+        self.newline();
+        self.new_scope();
+
+        let update = match fn_id {
+            Some((func, var, cond)) => {
+                self.define_local(&var, SymbolKind::Variable(None));
+                self.local();
+                self.space();
+                self.ident(&var);
+                self.eq();
+                self.writec('0');
+                self.newline();
+                Some((func, var, cond))
+            }
+            None => None,
+        };
+
+        self.writes("while true do");
+        self.newline();
+        if let Some((_, _, ref cond)) = update {
+            match cond {
+                LoopCond::Till(expr) => {
+                    self.writes("if");
+                    self.space();
+                    self.condition(expr)?;
+                }
+                LoopCond::While(expr) => {
+                    self.writes("if not");
+                    self.space();
+                    self.condition(expr)?;
+                }
+                LoopCond::Forever => {}
+            }
+            if !matches!(cond, LoopCond::Forever) {
+                self.space();
+                self.then();
+                self.space();
+                self.writes("break");
+                self.space();
+                self.end();
+            }
+        }
+
+        self.break_targets.push(BreakTarget::Loop);
+        self.block(block)?;
+        self.break_targets.pop();
+
+        if let Some((func, var, _)) = update {
+            self.loop_update(&func, &var)?;
+            self.newline();
+        }
+
+        self.end();
+        self.newline();
+        self.pop_scope();
+        self.end();
+        Ok(())
+    }
+
+    fn loop_update(&mut self, func: &Ident, var: &Ident) -> TransRes {
+        self.ident(var);
+        self.eq();
+        match self.id_to_str(func) {
+            "UPPIN" => {
+                self.writes(builtins::ops::LUA_ADD);
+                self.lparen();
+                self.ident(var);
+                self.writes(",1,");
+                self.write_span(func.1);
+                self.rparen();
+            }
+            "NERFIN" => {
+                self.writes(builtins::ops::LUA_SUB);
+                self.lparen();
+                self.ident(var);
+                self.writes(",1,");
+                self.write_span(func.1);
+                self.rparen();
+            }
+            _ => {
+                // Any unary funkshun can be used to step the loop variable
+                self.expr(&Self::make_id_expr(*func))?;
+                self.lparen();
+                self.ident(var);
+                self.rparen();
+            }
+        }
+        Ok(())
+    }
+
+    fn case(&mut self, cases: Vec<(Expr, Block)>, default: Option<Block>, span: Span) -> TransRes {
+        if cases.is_empty() && default.is_none() {
+            // Nothing to emit
+            return Ok(());
+        }
+
+        // A single-iteration `repeat` gives `GTFO` something to break out of
+        self.writes("repeat");
+        self.newline();
+        self.local();
+        self.space();
+        self.case_matched();
+        self.eq();
+        self.boolean(false);
+        self.newline();
+
+        self.break_targets.push(BreakTarget::Switch);
+        let arms = cases.len();
+        for (arm, (expr, block)) in cases.into_iter().enumerate() {
+            // Once a case has matched, every following case falls through until a `GTFO`
+            self.writes("if");
+            self.space();
+            self.case_matched();
+            self.space();
+            self.writes("or");
+            self.space();
+            self.writes(builtins::ops::LUA_EQ);
+            self.lparen();
+            self.it_var();
+            self.comma();
+            self.expr(&expr)?;
+            self.rparen();
+            self.space();
+            self.then();
+            self.newline();
+            self.case_matched();
+            self.eq();
+            self.boolean(true);
+            self.probe(Probe::Branch { span, arm });
+            self.block(block)?;
+            self.end();
+            self.newline();
+        }
+        if let Some(default) = default {
+            // Reached either by falling through, or when no case matched
+            self.writes("do");
+            self.probe(Probe::Branch { span, arm: arms });
+            self.block(default)?;
+            self.end();
+            self.newline();
+        } else if self.probes.is_some() {
+            // Without an `OMGWTF`, coverage still counts when no case matched
+            self.writes("if not");
+            self.space();
+            self.case_matched();
+            self.space();
+            self.then();
+            self.probe(Probe::Branch { span, arm: arms });
+            self.end();
+            self.newline();
+        }
+        self.break_targets.pop();
+
+        self.writes("until true");
+        Ok(())
+    }
+
+    fn case_matched(&mut self) {
+        self.writes("_lulz_case_matched");
+    }
+
+    fn break_stmt(&mut self, span: Span) -> TransRes {
+        // `break` and `return` must be the last statement of a lua block, so wrap them in `do`
+        match self.break_targets.last() {
+            Some(BreakTarget::Loop) | Some(BreakTarget::Switch) => self.writes("do break end"),
+            // `GTFO` returns NOOB
+            Some(BreakTarget::Function) => self.writes("do return nil end"),
+            None => {
+                return Err(Diagnostic::build(DiagnosticType::Syntax, span)
+                    .annotation(
+                        Cow::Borrowed("`GTFO` can only be used inside a loop, `WTF?` or funkshun"),
+                        span,
+                    )
+                    .into())
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if `name` is already declared in the current scope, naming it as `what`
+    fn check_redeclaration(&mut self, name: &Ident, what: &str) -> TransRes {
         if self.is_in_current_scope(name) {
-            // Don't allow declaration in the same scope
             return Err(Diagnostic::build(DiagnosticType::Scope, name.1)
                 .annotation(
                     Cow::Owned(format!(
-                        "variable `{}` cannot be re-declared",
+                        "{} `{}` cannot be re-declared",
+                        what,
                         self.id_to_str(name)
                     )),
                     name.1,
@@ -268,13 +1107,18 @@ impl Translator {
                 ))
                 .into());
         }
+        Ok(())
+    }
+
+    fn declaration(&mut self, name: &Ident, expr: &Expr, kind: SymbolKind) -> TransRes {
+        self.check_redeclaration(name, "variable")?;
 
         if self.local_scope {
-            self.define_local(name);
+            self.define_local(name, kind);
             self.local();
             self.space();
         } else {
-            self.define_global(name);
+            self.define_global(name, kind);
         }
 
         self.ident(name);
@@ -284,24 +1128,31 @@ impl Translator {
         Ok(())
     }
 
-    fn define_global(&mut self, name: &Ident) {
-        self.globals.insert(name.0);
+    fn add_symbol(&mut self, name: &Ident, kind: SymbolKind) -> usize {
+        self.symbols.push(Symbol { name: *name, kind });
+        self.symbols.len() - 1
+    }
+
+    fn define_global(&mut self, name: &Ident, kind: SymbolKind) {
+        let symbol = self.add_symbol(name, kind);
+        self.globals.insert(name.0, symbol);
     }
 
-    fn define_local(&mut self, name: &Ident) {
-        self.locals.last_mut().unwrap().insert(name.0);
+    fn define_local(&mut self, name: &Ident, kind: SymbolKind) {
+        let symbol = self.add_symbol(name, kind);
+        self.locals.last_mut().unwrap().insert(name.0, symbol);
     }
 
-    fn define_in_scope(&mut self, name: &Ident) {
+    fn define_in_scope(&mut self, name: &Ident, kind: SymbolKind) {
         if self.local_scope {
-            self.define_local(name)
+            self.define_local(name, kind)
         } else {
-            self.define_global(name)
+            self.define_global(name, kind)
         }
     }
 
     fn new_scope(&mut self) {
-        self.locals.push(HashSet::new());
+        self.locals.push(HashMap::new());
     }
 
     fn pop_scope(&mut self) {
@@ -319,7 +1170,7 @@ impl Translator {
     }
 
     fn assignment(&mut self, name: &Ident, expr: &Expr) -> TransRes {
-        if self.is_defined(name) {
+        if self.resolve(name) {
             self.ident(name);
             self.eq();
             self.expr(expr)?;
@@ -333,15 +1184,26 @@ impl Translator {
     }
 
     fn is_global(&self, name: &Ident) -> bool {
-        self.globals.contains(&name.0)
+        self.globals.contains_key(&name.0)
     }
 
     fn is_local(&self, name: &Ident) -> bool {
-        self.locals.iter().any(|scope| scope.contains(&name.0))
+        self.locals.iter().any(|scope| scope.contains_key(&name.0))
     }
 
-    fn is_defined(&self, name: &Ident) -> bool {
-        self.is_global(name) || self.is_local(name)
+    /// Records a use of a name, and returns whether it is defined
+    fn resolve(&mut self, name: &Ident) -> bool {
+        let symbol = self
+            .locals
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.0))
+            .or_else(|| self.globals.get(&name.0))
+            .copied();
+        if let Some(symbol) = symbol {
+            self.references.push((name.1, symbol));
+        }
+        symbol.is_some()
     }
 
     fn is_in_current_scope(&mut self, name: &Ident) -> bool {
@@ -360,6 +1222,15 @@ impl Translator {
         Ok(())
     }
 
+    /// A lua condition that holds when `expr` casts to WIN
+    fn condition(&mut self, expr: &Expr) -> TransRes {
+        self.writes(builtins::ops::LUA_TRUTHY);
+        self.lparen();
+        self.expr(expr)?;
+        self.rparen();
+        Ok(())
+    }
+
     fn call_ref(&mut self, f: &str, first: Option<&Expr>, args: &[&Expr]) -> TransRes {
         self.writes(f);
         self.lparen();
@@ -368,13 +1239,23 @@ impl Translator {
         Ok(())
     }
 
+    /// The lua name of an identifier
+    fn mangle(id: StrId) -> String {
+        format!("_{}", id.inner())
+    }
+
     fn ident(&mut self, id: &Ident) {
-        self.writes(&format!("_{}", id.0.inner()));
+        self.writes(&Self::mangle(id.0));
     }
 
     fn raw_string(&mut self, s: &str) {
         self.quotation();
-        self.writes(&s.replace("\\", "\\\\").replace("\"", "\\\""));
+        self.writes(
+            &s.replace("\\", "\\\\")
+                .replace("\"", "\\\"")
+                // Keeps generated lines in step with `line_spans`
+                .replace("\n", "\\n"),
+        );
         self.quotation();
     }
 
@@ -382,9 +1263,18 @@ impl Translator {
         match &expr.ty {
             ExprTy::Int(i) => {
                 self.writes(&i.to_string());
+                if !(-MAX_EXACT_INT..=MAX_EXACT_INT).contains(i) {
+                    // Too big for a lua number, use a 64 bit integer
+                    self.writes("LL");
+                }
             }
-            ExprTy::Float(i) => {
-                self.writes(&i.to_string());
+            ExprTy::Float(f) => {
+                let next = self.numbars.len();
+                let i = *self.numbars.entry(f.to_bits()).or_insert(next);
+                self.writes(LUA_NUMBARS);
+                self.writec('[');
+                self.writes(&(i + 1).to_string());
+                self.writec(']');
             }
             ExprTy::String(s) => self.raw_string(s),
             ExprTy::Null => {
@@ -394,12 +1284,12 @@ impl Translator {
                 self.boolean(*b);
             }
             ExprTy::Variable(ref id) => {
-                if !self.is_defined(id) {
+                if !self.resolve(id) {
                     return Err(self.undefined_var_error(id).into());
                 }
                 self.ident(id);
             }
-            ExprTy::Operator(op_ty, ref l, ref r) => self.operator(*op_ty, &*l, &*r)?,
+            ExprTy::Operator(op_ty, ref l, ref r) => self.operator(*op_ty, &*l, &*r, expr.span)?,
             ExprTy::Span(span) => {
                 self.write_span(*span);
             }
@@ -409,7 +1299,42 @@ impl Translator {
                 self.list(None, &args)?;
                 self.rparen();
             }
-            _ => todo!("Expression not implemented: {:?}", expr),
+            ExprTy::Bukkit(parent) => {
+                self.writes(builtins::bukkit::LUA_NEW);
+                self.lparen();
+                match parent {
+                    Some(parent) => self.expr(parent)?,
+                    None => self.nil(),
+                }
+                self.rparen();
+            }
+            ExprTy::Me => {
+                if !self.has_me {
+                    return Err(Diagnostic::build(DiagnosticType::Scope, expr.span)
+                        .annotation(
+                            Cow::Borrowed("`ME` can only be used inside a method or `O HAI IM`"),
+                            expr.span,
+                        )
+                        .into());
+                }
+                self.me();
+            }
+            ExprTy::GetSlot(object, slot) => {
+                let key = self.slot_key(slot);
+                self.call_ref(
+                    builtins::bukkit::LUA_GET_SLOT,
+                    None,
+                    &[object, &key, &Self::span_expr(expr.span)],
+                )?;
+            }
+            ExprTy::MethodCall(object, method, args) => {
+                let key = self.slot_key(method);
+                let span = Self::span_expr(expr.span);
+                let mut call_args: Vec<&Expr> = vec![object, &key, &span];
+                call_args.extend(args.iter());
+                self.call_ref(builtins::bukkit::LUA_CALL_METHOD, None, &call_args)?;
+            }
+            ty => return Err(unsupported(ty.syntax(), expr.span).into()),
         }
         Ok(())
     }
@@ -447,15 +1372,16 @@ impl Translator {
         }
     }
 
-    fn operator(&mut self, op_ty: OpTy, l: &Expr, r: &Expr) -> TransRes {
-        let span = l.span.combine(&r.span);
+    fn operator(&mut self, op_ty: OpTy, l: &Expr, r: &Expr, span: Span) -> TransRes {
         let span_expr = &Self::span_expr(span);
         match op_ty {
-            OpTy::Add => self.call_ref(builtins::ops::LUA_ADD, None, &[l, r])?,
-            OpTy::Sub => self.call_ref(builtins::ops::LUA_SUB, None, &[l, r])?,
-            OpTy::Mul => self.call_ref(builtins::ops::LUA_MUL, None, &[l, r])?,
-            OpTy::Div => self.call_ref(builtins::ops::LUA_DIV, None, &[l, r])?,
-            OpTy::Mod => self.call_ref(builtins::ops::LUA_MOD, None, &[l, r])?,
+            OpTy::Add => self.call_ref(builtins::ops::LUA_ADD, None, &[l, r, span_expr])?,
+            OpTy::Sub => self.call_ref(builtins::ops::LUA_SUB, None, &[l, r, span_expr])?,
+            OpTy::Mul => self.call_ref(builtins::ops::LUA_MUL, None, &[l, r, span_expr])?,
+            OpTy::Div => self.call_ref(builtins::ops::LUA_DIV, None, &[l, r, span_expr])?,
+            OpTy::Mod => self.call_ref(builtins::ops::LUA_MOD, None, &[l, r, span_expr])?,
+            OpTy::Max => self.call_ref(builtins::ops::LUA_MAX, None, &[l, r, span_expr])?,
+            OpTy::Min => self.call_ref(builtins::ops::LUA_MIN, None, &[l, r, span_expr])?,
 
             OpTy::And => self.call_ref(builtins::ops::LUA_AND, None, &[l, r])?,
             OpTy::Or => self.call_ref(builtins::ops::LUA_OR, None, &[l, r])?,
@@ -463,12 +1389,37 @@ impl Translator {
             OpTy::Equal => self.call_ref(builtins::ops::LUA_EQ, None, &[l, r])?,
             OpTy::NotEq => self.call_ref(builtins::ops::LUA_NEQ, None, &[l, r])?,
 
-            OpTy::GT => self.call_ref(builtins::ops::LUA_GT, None, &[l, r])?,
-            OpTy::LT => self.call_ref(builtins::ops::LUA_LT, None, &[l, r])?,
-            OpTy::GTE => self.call_ref(builtins::ops::LUA_GTE, None, &[l, r])?,
-            OpTy::LTE => self.call_ref(builtins::ops::LUA_LTE, None, &[l, r])?,
-            op => todo!("Operator not implemented: {:?}", op),
+            OpTy::GT => self.call_ref(builtins::ops::LUA_GT, None, &[l, r, span_expr])?,
+            OpTy::LT => self.call_ref(builtins::ops::LUA_LT, None, &[l, r, span_expr])?,
+            OpTy::GTE => self.call_ref(builtins::ops::LUA_GTE, None, &[l, r, span_expr])?,
+            OpTy::LTE => self.call_ref(builtins::ops::LUA_LTE, None, &[l, r, span_expr])?,
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod translator_test {
+    use super::*;
+
+    /// Translates an input of the repl, as if typed next to the modules in `tests/imports`
+    fn repl_input(translator: &mut Translator, source: &str) -> TransRes {
+        let name = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/imports/repl.lol");
+        let file = translator.sources.add(name.to_string(), source.to_string());
+        let lexer = Lexer::new(source.chars(), file, &mut translator.interner);
+        let ast = Parser::new(lexer).parse_repl()?;
+        translator.repl_block(ast)
+    }
+
+    #[test]
+    fn repl_rolls_back_imports() {
+        let mut translator = Translator::new(Interner::default(), Vec::new(), SourceMap::new());
+        // The module is found, but the input fails, so it is never run
+        assert!(repl_input(&mut translator, "CAN I HAS greeting?\nVISIBLE missing\n").is_err());
+        repl_input(
+            &mut translator,
+            "CAN I HAS greeting?\nI IZ GREET YR NAME MKAY\n",
+        )
+        .unwrap();
+    }
+}
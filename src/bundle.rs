@@ -0,0 +1,268 @@
+//! Standalone executables are a copy of the compiler with a compiled program appended to it.
+//! On startup, the compiler checks whether it carries a program, and runs it if it does. The copy
+//! is stamped when it is written, so the compiler itself doesn't read its own file to check.
+
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use mlua::{ChunkMode, Lua, LuaOptions, StdLib};
+
+use crate::diagnostics::{Failible, Span};
+use crate::runtime::builtins::register_modules;
+use crate::runtime::debug_info::{DebugInfo, FunctionInfo};
+use crate::runtime::errors::{lua_error, CHUNK_NAME};
+use crate::sourcemap::SourceMap;
+
+/// Ends every executable, after the length of the appended program
+const MAGIC: &[u8; 8] = b"LULZEXE\x01";
+const TRAILER_LEN: u64 = 16;
+
+/// Found in the executable by `write_executable`, which sets the last byte of the copy it writes
+/// to 1. Executables without a program appended never look for one.
+#[used]
+static STAMP: [u8; 16] = *b"LULZ-STAMP\0\0\0\0\0\0";
+
+pub struct Bundle {
+    /// The program, as LuaJIT bytecode
+    pub chunk: Vec<u8>,
+    pub info: DebugInfo,
+    /// Every source file as `(name, source)`, in sourcemap order, to render diagnostics
+    pub files: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn int(&mut self, n: usize) {
+        self.0.extend_from_slice(&(n as u64).to_le_bytes());
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.int(b.len());
+        self.0.extend_from_slice(b);
+    }
+
+    fn span(&mut self, span: Span) {
+        self.int(span.s);
+        self.int(span.e);
+        self.int(span.file);
+    }
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn int(&mut self) -> Option<usize> {
+        let n = self.0.get(..8)?;
+        self.0 = &self.0[8..];
+        Some(u64::from_le_bytes(n.try_into().ok()?) as usize)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.int()?;
+        let b = self.0.get(..len)?;
+        self.0 = &self.0[len..];
+        Some(b)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn span(&mut self) -> Option<Span> {
+        Some(Span::new(self.int()?, self.int()?, self.int()?))
+    }
+}
+
+/// Marks a copy of the compiler as carrying a program
+fn stamp(exe: &mut [u8]) -> io::Result<()> {
+    // SAFETY: as in `Bundle::embedded`. Read at runtime, so no other copy of the stamp ends up in
+    // the executable for it to be confused with.
+    let stamp = unsafe { std::ptr::read_volatile(&STAMP) };
+    let mut found = exe
+        .windows(stamp.len())
+        .enumerate()
+        .filter(|(_, w)| *w == stamp)
+        .map(|(pos, _)| pos);
+    match (found.next(), found.next()) {
+        (Some(pos), None) => {
+            exe[pos + stamp.len() - 1] = 1;
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the running executable is not a lulz compiler",
+        )),
+    }
+}
+
+impl Bundle {
+    fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.bytes(&self.chunk);
+        enc.int(self.info.line_spans.len());
+        for span in self.info.line_spans.iter() {
+            match span {
+                // File ids are offset by one, so zero can mean there is no span
+                Some(span) => {
+                    enc.int(span.file + 1);
+                    enc.int(span.s);
+                    enc.int(span.e);
+                }
+                None => enc.int(0),
+            }
+        }
+        enc.int(self.info.names.len());
+        for name in self.info.names.iter() {
+            enc.bytes(name.as_bytes());
+        }
+        enc.int(self.info.functions.len());
+        for function in self.info.functions.iter() {
+            enc.int(function.name);
+            enc.span(function.name_span);
+            enc.span(function.span);
+        }
+        enc.int(self.files.len());
+        for (name, source) in self.files.iter() {
+            enc.bytes(name.as_bytes());
+            enc.bytes(source.as_bytes());
+        }
+        enc.0
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut dec = Decoder(data);
+        let chunk = dec.bytes()?.to_vec();
+        let line_spans = (0..dec.int()?)
+            .map(|_| match dec.int()? {
+                0 => Some(None),
+                file => Some(Some(Span::new(dec.int()?, dec.int()?, file - 1))),
+            })
+            .collect::<Option<_>>()?;
+        let names = (0..dec.int()?)
+            .map(|_| dec.string())
+            .collect::<Option<_>>()?;
+        let functions = (0..dec.int()?)
+            .map(|_| {
+                Some(FunctionInfo {
+                    name: dec.int()?,
+                    name_span: dec.span()?,
+                    span: dec.span()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        let files = (0..dec.int()?)
+            .map(|_| Some((dec.string()?, dec.string()?)))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            chunk,
+            info: DebugInfo {
+                line_spans,
+                names,
+                functions,
+            },
+            files,
+        })
+    }
+
+    /// Compiles generated code to bytecode, and collects everything needed to run it
+    pub fn new(code: &str, info: DebugInfo, sources: &SourceMap) -> Failible<Self> {
+        let lj = Lua::new();
+        let chunk = lj
+            .load(code)
+            .set_name(CHUNK_NAME)
+            .and_then(|chunk| chunk.into_function())
+            .map_err(|e| lua_error(e, &info))?
+            // Line info is kept, so errors can still be traced back to LOLCODE
+            .dump(false);
+
+        let files = (0..)
+            .map_while(|id| sources.get(id).ok())
+            .map(|file| (file.name().clone(), file.source().clone()))
+            .collect();
+
+        Ok(Self { chunk, info, files })
+    }
+
+    /// Writes a copy of the running compiler with this program appended to `path`
+    pub fn write_executable(&self, path: &Path) -> io::Result<()> {
+        let mut exe = fs::read(std::env::current_exe()?)?;
+        stamp(&mut exe)?;
+        let payload = self.encode();
+        exe.extend_from_slice(&payload);
+        exe.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        exe.extend_from_slice(MAGIC);
+
+        fs::write(path, exe)?;
+        #[cfg(unix)]
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    /// The program appended to the running executable, if there is one
+    pub fn embedded() -> Option<Self> {
+        // SAFETY: `STAMP` is a valid static. Reading it volatile keeps the compiler from assuming
+        // it still holds the value it was compiled with.
+        if unsafe { std::ptr::read_volatile(&STAMP[STAMP.len() - 1]) } == 0 {
+            return None;
+        }
+        let mut file = File::open(std::env::current_exe().ok()?).ok()?;
+        let mut trailer = [0; TRAILER_LEN as usize];
+        file.seek(SeekFrom::End(-(TRAILER_LEN as i64))).ok()?;
+        file.read_exact(&mut trailer).ok()?;
+        if &trailer[8..] != MAGIC {
+            return None;
+        }
+
+        let len = u64::from_le_bytes(trailer[..8].try_into().ok()?);
+        let mut payload = vec![0; len as usize];
+        file.seek(SeekFrom::End(-((len + TRAILER_LEN) as i64)))
+            .ok()?;
+        file.read_exact(&mut payload).ok()?;
+        Self::decode(&payload)
+    }
+
+    /// The files the program was compiled from, to render its errors
+    pub fn sources(&self) -> SourceMap {
+        let mut sources = SourceMap::new();
+        for (name, source) in self.files.iter() {
+            sources.add(name.clone(), source.clone());
+        }
+        sources
+    }
+
+    pub fn run(&self) -> Failible<()> {
+        // SAFETY: Binary chunks are refused by safe states, but this one was compiled by us. The
+        // same standard libraries are loaded as in `Lua::new`.
+        let lj = unsafe { Lua::unsafe_new_with(StdLib::ALL_SAFE, LuaOptions::default()) };
+        register_modules(&lj);
+        lj.load(&self.chunk)
+            .set_name(CHUNK_NAME)
+            .map(|chunk| chunk.set_mode(ChunkMode::Binary))
+            .and_then(|chunk| chunk.exec())
+            .map_err(|e| lua_error(e, &self.info))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod bundle_test {
+    use super::*;
+
+    #[test]
+    fn stamps() {
+        let mut exe = b"\x7fELF".to_vec();
+        exe.extend_from_slice(&STAMP);
+        exe.extend_from_slice(b"code");
+        stamp(&mut exe).unwrap();
+        assert_eq!(exe[4 + STAMP.len() - 1], 1);
+        assert_eq!(exe[4..4 + STAMP.len() - 1], STAMP[..STAMP.len() - 1]);
+        // A copy can't be stamped twice
+        assert!(stamp(&mut exe).is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt::{self, Display};
 pub enum Color {
     Blue,
     Red,
+    Green,
     Bold,
     Reset,
 }
@@ -11,6 +12,7 @@ impl Color {
     fn as_str(&self) -> &'static str {
         match self {
             Self::Red => "\x1b[31m",
+            Self::Green => "\x1b[32m",
             Self::Reset => "\x1b[0m",
             Self::Blue => "\x1b[34m",
             Self::Bold => "\x1b[1m",
@@ -0,0 +1,150 @@
+//! `lulz --coverage`, which counts the statements and branches a program runs. With coverage on,
+//! the translator emits a probe before every statement and at the start of every arm of an
+//! `O RLY?` or `WTF?`, which counts into a lua table. The counts are written in lcov format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use hashbrown::HashMap;
+use mlua::Lua;
+
+use crate::diagnostics::Span;
+use crate::sourcemap::{line_starts, SourceMap};
+
+/// The table probes count into, indexed by probe number + 1
+pub const LUA_COVERAGE: &str = "_lulz_cov";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// Counts the runs of a statement
+    Statement(Span),
+    /// Counts the times an arm of the conditional at `span` was taken. Arms are numbered in
+    /// order, and conditionals without a `NO WAI` or `OMGWTF` still have one for when no other
+    /// arm is taken.
+    Branch { span: Span, arm: usize },
+}
+
+pub fn start(lj: &Lua, probes: &[Probe]) -> mlua::Result<()> {
+    let counts = lj.create_sequence_from(vec![0; probes.len()])?;
+    lj.globals().set(LUA_COVERAGE, counts)
+}
+
+pub fn stop(lj: &Lua) -> mlua::Result<Vec<usize>> {
+    lj.globals().get(LUA_COVERAGE)
+}
+
+#[derive(Default)]
+struct FileCoverage {
+    /// The runs of the statements starting on each line
+    lines: BTreeMap<usize, usize>,
+    /// The line, block and arm of each branch, and how often it was taken, or `None` if its
+    /// conditional never ran
+    branches: Vec<(usize, usize, usize, Option<usize>)>,
+}
+
+/// Writes the counts of every probe as an lcov tracefile
+pub fn lcov(probes: &[Probe], counts: &[usize], sources: &SourceMap) -> String {
+    let mut starts: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut line = |span: Span| {
+        let starts = starts
+            .entry(span.file)
+            .or_insert_with(|| line_starts(sources.get(span.file).map_or("", |f| f.source())));
+        starts.partition_point(|&start| start <= span.s)
+    };
+
+    let mut statements = HashMap::new();
+    for (probe, &count) in probes.iter().zip(counts) {
+        if let Probe::Statement(span) = probe {
+            statements.insert((span.file, span.s, span.e), count);
+        }
+    }
+
+    let mut files: BTreeMap<usize, FileCoverage> = BTreeMap::new();
+    let mut blocks: HashMap<(usize, usize, usize), usize> = HashMap::new();
+    for (probe, &count) in probes.iter().zip(counts) {
+        match *probe {
+            Probe::Statement(span) => {
+                let file = files.entry(span.file).or_default();
+                let runs = file.lines.entry(line(span)).or_insert(0);
+                *runs = (*runs).max(count);
+            }
+            Probe::Branch { span, arm } => {
+                let key = (span.file, span.s, span.e);
+                let block = blocks.len();
+                let block = *blocks.entry(key).or_insert(block);
+                let ran = statements.get(&key).is_none_or(|&runs| runs > 0);
+                let line = line(span);
+                files.entry(span.file).or_default().branches.push((
+                    line,
+                    block,
+                    arm,
+                    Some(count).filter(|_| ran),
+                ));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (file, coverage) in files {
+        let name = sources.get(file).map_or("", |f| f.name().as_str());
+        let _ = writeln!(out, "TN:\nSF:{}", name);
+        for &(line, block, arm, taken) in coverage.branches.iter() {
+            let _ = match taken {
+                Some(taken) => writeln!(out, "BRDA:{},{},{},{}", line, block, arm, taken),
+                None => writeln!(out, "BRDA:{},{},{},-", line, block, arm),
+            };
+        }
+        let hit = |count: Option<usize>| count.is_some_and(|count| count > 0);
+        let _ = writeln!(
+            out,
+            "BRF:{}\nBRH:{}",
+            coverage.branches.len(),
+            coverage.branches.iter().filter(|b| hit(b.3)).count()
+        );
+        for (line, runs) in coverage.lines.iter() {
+            let _ = writeln!(out, "DA:{},{}", line, runs);
+        }
+        let _ = writeln!(
+            out,
+            "LF:{}\nLH:{}\nend_of_record",
+            coverage.lines.len(),
+            coverage.lines.values().filter(|&&runs| runs > 0).count()
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod coverage_test {
+    use super::*;
+
+    #[test]
+    fn lcov_records() {
+        let source = "HAI 1.2\nBOTH SAEM 1 AN 2, O RLY?\nYA RLY, VISIBLE 1\nNO WAI, VISIBLE 2\nOIC\nKTHXBYE\n";
+        let mut sources = SourceMap::new();
+        let file = sources.add("c.lol".to_string(), source.to_string());
+        let cond = Span::new(26, 60, file);
+        let never = Span::new(69, 72, file);
+        let probes = [
+            Probe::Statement(Span::new(8, 24, file)),
+            Probe::Statement(cond),
+            Probe::Branch { span: cond, arm: 0 },
+            Probe::Statement(Span::new(42, 51, file)),
+            Probe::Branch { span: cond, arm: 1 },
+            Probe::Statement(Span::new(60, 69, file)),
+            Probe::Statement(never),
+            Probe::Branch {
+                span: never,
+                arm: 0,
+            },
+        ];
+        let counts = [1, 1, 0, 0, 1, 1, 0, 0];
+
+        assert_eq!(
+            lcov(&probes, &counts, &sources),
+            "TN:\nSF:c.lol\n\
+             BRDA:2,0,0,0\nBRDA:2,0,1,1\nBRDA:5,1,0,-\nBRF:3\nBRH:1\n\
+             DA:2,1\nDA:3,0\nDA:4,1\nDA:5,0\nLF:4\nLH:2\nend_of_record\n"
+        );
+    }
+}
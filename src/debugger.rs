@@ -0,0 +1,658 @@
+//! `lulz debug`, a debug adapter speaking the debug adapter protocol over stdio. Programs run
+//! with a line hook, which maps generated lines back to LOLCODE statements to stop at breakpoints
+//! and steps. While stopped, the lua `debug` library reads the stack and its variables.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use hashbrown::HashMap;
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib};
+
+use lulz::diagnostics::Span;
+use lulz::json::{read_message, write_message, Json};
+use lulz::runtime::builtins::{register_debugger, register_modules};
+use lulz::runtime::debug_info::DebugInfo;
+use lulz::runtime::errors::{lua_error, render_errors, CHUNK_NAME};
+use lulz::sourcemap::{self, SourceMap};
+
+use crate::opts::Opts;
+
+/// Programs run on a single thread
+const THREAD_ID: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Running until a breakpoint
+    Continue,
+    /// Stopping at the next statement, for this reason
+    Step(&'static str),
+    /// Stopping at the next statement at most this many calls deep
+    StepOver(usize),
+    /// Stopping at the next statement less than this many calls deep
+    StepOut(usize),
+}
+
+/// Where a statement starts, as a file id and line and column counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+struct Session {
+    seq: usize,
+    requests: Receiver<Json>,
+    sources: SourceMap,
+    info: DebugInfo,
+    /// The position of the statement each generated line was emitted for
+    positions: Vec<Option<Position>>,
+    /// Canonical paths of the program's files, by sourcemap id
+    paths: HashMap<usize, PathBuf>,
+    /// Breakpoint lines by canonical file path
+    breakpoints: HashMap<PathBuf, Vec<usize>>,
+    mode: Mode,
+    /// The statement and position of the latest line event, to notice new statements
+    last: Option<(Span, Option<Position>)>,
+    /// While stopped, the stack level and line of each frame, innermost first
+    frames: Vec<(i64, usize)>,
+    disconnected: bool,
+}
+
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn arguments(request: &Json) -> &Json {
+    request.get("arguments").unwrap_or(&Json::Null)
+}
+
+impl Session {
+    fn send(&mut self, ty: &str, mut fields: Vec<(&str, Json)>) {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        fields.insert(1, ("type", ty.into()));
+        let stdout = io::stdout();
+        // Nobody is listening anymore if this fails, which ends the session at the next read
+        let _ = write_message(&mut stdout.lock(), &Json::object(fields));
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        self.send(
+            "response",
+            vec![
+                (
+                    "request_seq",
+                    request.get("seq").cloned().unwrap_or(Json::Null),
+                ),
+                ("success", true.into()),
+                (
+                    "command",
+                    request.get("command").cloned().unwrap_or(Json::Null),
+                ),
+                ("body", body),
+            ],
+        );
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) {
+        self.send(
+            "response",
+            vec![
+                (
+                    "request_seq",
+                    request.get("seq").cloned().unwrap_or(Json::Null),
+                ),
+                ("success", false.into()),
+                (
+                    "command",
+                    request.get("command").cloned().unwrap_or(Json::Null),
+                ),
+                ("message", message.into()),
+            ],
+        );
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send("event", vec![("event", event.into()), ("body", body)]);
+    }
+
+    fn output(&mut self, category: &str, output: &str) {
+        self.event(
+            "output",
+            Json::object(vec![
+                ("category", category.into()),
+                ("output", output.into()),
+            ]),
+        );
+    }
+
+    /// Finds where every generated line's statement is, once the program is compiled
+    fn load(&mut self, info: DebugInfo) {
+        let sourcemap = &self.sources;
+        let mut line_starts: HashMap<usize, Vec<usize>> = HashMap::new();
+        self.positions = info
+            .line_spans
+            .iter()
+            .map(|span| {
+                let span = (*span)?;
+                let starts = line_starts.entry(span.file).or_insert_with(|| {
+                    sourcemap::line_starts(sourcemap.get(span.file).map_or("", |f| f.source()))
+                });
+                let line = starts.partition_point(|&start| start <= span.s);
+                Some(Position {
+                    file: span.file,
+                    line,
+                    column: span.s - starts[line - 1] + 1,
+                })
+            })
+            .collect();
+        self.paths = line_starts
+            .keys()
+            .map(|&file| {
+                (
+                    file,
+                    canonical(sourcemap.get(file).map_or("", |f| f.name())),
+                )
+            })
+            .collect();
+        self.info = info;
+    }
+
+    fn position(&self, line: usize) -> Option<Position> {
+        self.positions.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    fn set_breakpoints(&mut self, request: &Json) {
+        let args = arguments(request);
+        let path = args
+            .get("source")
+            .and_then(|s| s.get("path"))
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        let path = canonical(path);
+        let lines: Vec<usize> = args
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|b| b.get("line").and_then(Json::as_usize))
+            .collect();
+
+        // Before the program is compiled, every breakpoint might be valid
+        let statements: Vec<usize> = self
+            .positions
+            .iter()
+            .flatten()
+            .filter(|p| self.paths.get(&p.file) == Some(&path))
+            .map(|p| p.line)
+            .collect();
+        let breakpoints: Vec<Json> = lines
+            .iter()
+            .map(|&line| {
+                let verified = self.positions.is_empty() || statements.contains(&line);
+                Json::object(vec![("verified", verified.into()), ("line", line.into())])
+            })
+            .collect();
+        self.breakpoints.insert(path, lines);
+        self.respond(
+            request,
+            Json::object(vec![("breakpoints", breakpoints.into())]),
+        );
+    }
+
+    fn call<R: for<'lua> mlua::FromLuaMulti<'lua>>(
+        lua: &Lua,
+        helper: &str,
+        args: impl for<'lua> mlua::ToLuaMulti<'lua>,
+    ) -> mlua::Result<R> {
+        lua.globals().get::<_, mlua::Function>(helper)?.call(args)
+    }
+
+    fn stack_trace(&mut self, request: &Json) {
+        let frames: Vec<Json> = self
+            .frames
+            .iter()
+            .enumerate()
+            .filter_map(|(id, &(_, line))| {
+                let span = self.info.line_span(line)?;
+                let position = self.position(line)?;
+                let name = match self.info.enclosing_function(span) {
+                    Some(function) => self.info.names[function.name].clone(),
+                    None => "main".to_string(),
+                };
+                let file = self.sources.get(position.file).ok()?;
+                let source = Json::object(vec![
+                    ("name", file.name().as_str().into()),
+                    ("path", file.name().as_str().into()),
+                ]);
+                Some(Json::object(vec![
+                    ("id", id.into()),
+                    ("name", name.into()),
+                    ("source", source),
+                    ("line", position.line.into()),
+                    ("column", position.column.into()),
+                ]))
+            })
+            .collect();
+        let total = frames.len();
+        self.respond(
+            request,
+            Json::object(vec![
+                ("stackFrames", frames.into()),
+                ("totalFrames", total.into()),
+            ]),
+        );
+    }
+
+    fn scopes(&mut self, request: &Json) {
+        let frame = arguments(request)
+            .get("frameId")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let scope = |name: &str, reference: usize| {
+            Json::object(vec![
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ])
+        };
+        // Locals of frame `n` are variables reference `2n + 1`, and globals are `2n + 2`
+        let scopes = vec![
+            scope("Locals", frame * 2 + 1),
+            scope("Globals", frame * 2 + 2),
+        ];
+        self.respond(request, Json::object(vec![("scopes", scopes.into())]));
+    }
+
+    fn variables(&mut self, lua: &Lua, request: &Json) -> mlua::Result<()> {
+        let reference = arguments(request)
+            .get("variablesReference")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let frame = match reference.checked_sub(1) {
+            Some(reference) => self.frames.get(reference / 2).map(|f| (f.0, reference % 2)),
+            None => None,
+        };
+        let vars: Vec<Vec<String>> = match frame {
+            Some((level, 0)) => {
+                let mut vars = vec![Self::call(lua, "__lulz_dbg_it", ())?];
+                vars.extend(Self::call::<Vec<Vec<String>>>(
+                    lua,
+                    "__lulz_dbg_locals",
+                    level,
+                )?);
+                vars
+            }
+            Some(_) => {
+                let mut vars: Vec<Vec<String>> = Self::call(lua, "__lulz_dbg_globals", ())?;
+                vars.sort();
+                vars
+            }
+            None => Vec::new(),
+        };
+
+        // Only mangled names are LOLCODE variables, and later locals shadow earlier ones
+        let mut named: Vec<(String, String, String)> = Vec::new();
+        for var in vars {
+            if let [name, value, ty] = &var[..] {
+                if !name.starts_with('_') {
+                    continue;
+                }
+                let name = self.info.demangle(name);
+                if name.starts_with('_') {
+                    continue;
+                }
+                named.retain(|(n, _, _)| *n != name);
+                named.push((name, value.clone(), ty.clone()));
+            }
+        }
+        let variables: Vec<Json> = named
+            .into_iter()
+            .map(|(name, value, ty)| {
+                Json::object(vec![
+                    ("name", name.into()),
+                    ("value", value.into()),
+                    ("type", ty.into()),
+                    ("variablesReference", 0usize.into()),
+                ])
+            })
+            .collect();
+        self.respond(request, Json::object(vec![("variables", variables.into())]));
+        Ok(())
+    }
+
+    /// Handles a request, with the program's lua state if it is running. Returns whether the
+    /// program should resume.
+    fn handle(&mut self, lua: Option<&Lua>, request: &Json) -> mlua::Result<bool> {
+        let depth = self.frames.len();
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let resume = |session: &mut Self, mode| {
+            session.mode = mode;
+            session.respond(request, Json::Null);
+            Ok(true)
+        };
+        match (command, lua) {
+            ("setBreakpoints", _) => self.set_breakpoints(request),
+            ("threads", _) => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                self.respond(
+                    request,
+                    Json::object(vec![("threads", vec![thread].into())]),
+                );
+            }
+            ("stackTrace", Some(_)) => self.stack_trace(request),
+            ("scopes", Some(_)) => self.scopes(request),
+            ("variables", Some(lua)) => self.variables(lua, request)?,
+            ("continue", _) => {
+                self.mode = Mode::Continue;
+                self.respond(
+                    request,
+                    Json::object(vec![("allThreadsContinued", true.into())]),
+                );
+                return Ok(true);
+            }
+            ("next", _) => return resume(self, Mode::StepOver(depth)),
+            ("stepIn", _) => return resume(self, Mode::Step("step")),
+            ("stepOut", _) => return resume(self, Mode::StepOut(depth)),
+            ("pause", _) => {
+                self.mode = Mode::Step("pause");
+                self.respond(request, Json::Null);
+            }
+            ("disconnect", _) | ("terminate", _) => {
+                self.disconnected = true;
+                return resume(self, Mode::Continue);
+            }
+            ("stackTrace", None) | ("scopes", None) | ("variables", None) => {
+                self.fail(request, "the program is not running")
+            }
+            _ => self.fail(request, &format!("unsupported request `{}`", command)),
+        }
+        Ok(false)
+    }
+
+    /// Waits for requests until the program should resume
+    fn stop(&mut self, lua: &Lua, reason: &str) -> mlua::Result<()> {
+        self.mode = Mode::Continue;
+        self.event(
+            "stopped",
+            Json::object(vec![
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        );
+        loop {
+            let request = match self.requests.recv() {
+                Ok(request) => request,
+                Err(_) => {
+                    self.disconnected = true;
+                    return Ok(());
+                }
+            };
+            if self.handle(Some(lua), &request)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn at_breakpoint(&self, position: Position, previous: Option<Position>) -> bool {
+        // A line with several statements only breaks at the first
+        if previous.is_some_and(|p| p.file == position.file && p.line == position.line) {
+            return false;
+        }
+        self.paths
+            .get(&position.file)
+            .and_then(|path| self.breakpoints.get(path))
+            .is_some_and(|lines| lines.contains(&position.line))
+    }
+
+    /// The stack level and line of each frame running generated code, innermost first
+    fn frames(lua: &Lua) -> mlua::Result<Vec<(i64, usize)>> {
+        let frames: Vec<Vec<i64>> = Self::call(lua, "__lulz_dbg_frames", ())?;
+        Ok(frames
+            .iter()
+            .filter_map(|frame| match frame[..] {
+                [level, line] => Some((level, line.max(0) as usize)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Called for every line of generated code that runs
+    fn line(&mut self, lua: &Lua, line: usize) -> mlua::Result<()> {
+        let span = match self.info.line_span(line) {
+            Some(span) => span,
+            None => return Ok(()),
+        };
+        let position = self.position(line);
+        let previous = match self.last.replace((span, position)) {
+            Some((s, _)) if s == span => return Ok(()),
+            previous => previous.and_then(|(_, position)| position),
+        };
+
+        while let Ok(request) = self.requests.try_recv() {
+            self.handle(Some(lua), &request)?;
+        }
+        // Walking the stack takes time for every frame, so it is only done to step over or out
+        // of calls, and once stopped
+        let mut frames = None;
+        let reason = match self.mode {
+            Mode::Step(reason) => Some(reason),
+            Mode::StepOver(max) if frames.insert(Self::frames(lua)?).len() <= max => Some("step"),
+            Mode::StepOut(max) if frames.insert(Self::frames(lua)?).len() < max => Some("step"),
+            _ if position.is_some_and(|p| self.at_breakpoint(p, previous)) => Some("breakpoint"),
+            _ => None,
+        };
+        if let (Some(reason), false) = (reason, self.disconnected) {
+            self.frames = match frames {
+                Some(frames) => frames,
+                None => Self::frames(lua)?,
+            };
+            self.stop(lua, reason)?;
+        }
+
+        if self.disconnected {
+            return Err(mlua::Error::RuntimeError("disconnected".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Compiles the program to debug into `sources`, returning its code and debug info or the
+/// rendered errors
+fn compile(
+    path: &str,
+    include: Vec<PathBuf>,
+    sources: &mut SourceMap,
+) -> Result<(String, DebugInfo), String> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read file `{}`: {}\n", path, e))?;
+
+    let mut compiler = lulz::Session::new(include);
+    compiler.sources = std::mem::replace(sources, SourceMap::new());
+    let res = compiler.compile(path, source);
+    *sources = compiler.sources;
+    let program = res.map_err(|es| render_errors(es, sources))?;
+    Ok((program.code, program.debug_info))
+}
+
+fn run_program(session: &Rc<RefCell<Session>>, code: &str) -> i32 {
+    // SAFETY: The debug library is only loaded to read the stack and variables of the program,
+    // by helpers of the debugger
+    let lj =
+        unsafe { Lua::unsafe_new_with(StdLib::ALL_SAFE | StdLib::DEBUG, LuaOptions::default()) };
+    register_modules(&lj);
+    let output = session.clone();
+    let print = lj
+        .create_function(move |_, s: String| {
+            output.borrow_mut().output("stdout", &s);
+            Ok(())
+        })
+        .unwrap();
+    lj.globals().set("__lulz_dbg_output", print).unwrap();
+    register_debugger(&lj);
+
+    let hook = session.clone();
+    lj.set_hook(HookTriggers::every_line(), move |lua, debug| {
+        if debug.source().source != Some(CHUNK_NAME.as_bytes()) {
+            return Ok(());
+        }
+        hook.borrow_mut()
+            .line(lua, debug.curr_line().max(0) as usize)
+    })
+    .unwrap();
+
+    let res = lj.load(code).set_name(CHUNK_NAME).and_then(|c| c.exec());
+    lj.remove_hook();
+    let mut session = session.borrow_mut();
+    match res {
+        Ok(()) => 0,
+        Err(_) if session.disconnected => 0,
+        Err(e) => {
+            let diagnostic = lua_error(e, &session.info);
+            let errors = render_errors(diagnostic.into(), &session.sources);
+            session.output("stderr", &errors);
+            1
+        }
+    }
+}
+
+pub fn run(opts: Opts) -> i32 {
+    let (send, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut input = BufReader::new(stdin.lock());
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if send.send(message).is_err() {
+                return;
+            }
+        }
+    });
+
+    let session = Rc::new(RefCell::new(Session {
+        seq: 0,
+        requests,
+        sources: SourceMap::new(),
+        info: DebugInfo::default(),
+        positions: Vec::new(),
+        paths: HashMap::new(),
+        breakpoints: HashMap::new(),
+        mode: Mode::Continue,
+        last: None,
+        frames: Vec::new(),
+        disconnected: false,
+    }));
+    let mut include = opts.include;
+    let mut program = None;
+
+    loop {
+        let request = match session.borrow().requests.recv() {
+            Ok(request) => request,
+            Err(_) => return 1,
+        };
+        let mut s = session.borrow_mut();
+        match request.get("command").and_then(Json::as_str).unwrap_or("") {
+            "initialize" => {
+                s.respond(
+                    &request,
+                    Json::object(vec![("supportsConfigurationDoneRequest", true.into())]),
+                );
+                s.event("initialized", Json::Null);
+            }
+            "launch" => {
+                let args = arguments(&request);
+                let path = args.get("program").and_then(Json::as_str).unwrap_or("");
+                if let Some(dirs) = args.get("include").and_then(Json::as_array) {
+                    include.extend(dirs.iter().filter_map(Json::as_str).map(PathBuf::from));
+                }
+                if args.get("stopOnEntry").and_then(Json::as_bool) == Some(true) {
+                    s.mode = Mode::Step("entry");
+                }
+                let include = std::mem::take(&mut include);
+                match compile(path, include, &mut s.sources) {
+                    Ok((code, info)) => {
+                        s.load(info);
+                        program = Some(code);
+                        s.respond(&request, Json::Null);
+                    }
+                    Err(errors) => {
+                        s.output("stderr", &errors);
+                        s.fail(&request, "the program failed to compile");
+                        s.event("terminated", Json::Null);
+                    }
+                }
+            }
+            "configurationDone" => {
+                s.respond(&request, Json::Null);
+                if let Some(code) = program.take() {
+                    std::mem::drop(s);
+                    let status = run_program(&session, &code);
+                    let mut s = session.borrow_mut();
+                    if s.disconnected {
+                        return 0;
+                    }
+                    s.event(
+                        "exited",
+                        Json::object(vec![("exitCode", (status as i64).into())]),
+                    );
+                    s.event("terminated", Json::Null);
+                }
+            }
+            "disconnect" | "terminate" => {
+                s.respond(&request, Json::Null);
+                return 0;
+            }
+            _ => {
+                s.handle(None, &request).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod debugger_test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let mut sources = SourceMap::new();
+        let file = sources.add(
+            "a.lol".to_string(),
+            "HAI 1.2\nVISIBLE 1, VISIBLE 2\nKTHXBYE\n".to_string(),
+        );
+        let span = |s, e| Some(Span::new(s, e, file));
+        let (_, requests) = mpsc::channel();
+        let mut session = Session {
+            seq: 0,
+            requests,
+            sources,
+            info: DebugInfo::default(),
+            positions: Vec::new(),
+            paths: HashMap::new(),
+            breakpoints: HashMap::new(),
+            mode: Mode::Continue,
+            last: None,
+            frames: Vec::new(),
+            disconnected: false,
+        };
+        session.load(DebugInfo {
+            line_spans: vec![None, span(8, 17), span(19, 28)],
+            ..DebugInfo::default()
+        });
+        let position = |line, column| Some(Position { file, line, column });
+        assert_eq!(session.position(1), None);
+        assert_eq!(session.position(2), position(2, 1));
+        assert_eq!(session.position(3), position(2, 12));
+
+        let path = session.paths[&file].clone();
+        session.breakpoints.insert(path, vec![2]);
+        assert!(session.at_breakpoint(position(2, 1).unwrap(), None));
+        assert!(!session.at_breakpoint(position(2, 12).unwrap(), position(2, 1)));
+    }
+}
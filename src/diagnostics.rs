@@ -1,11 +1,15 @@
 pub mod prelude {
-    pub use super::{Annotation, Diagnostic, DiagnosticType, Diagnostics, Failible, Span};
+    pub use super::{
+        Annotation, Diagnostic, DiagnosticType, Diagnostics, Failible, Severity, Span,
+    };
     pub use std::borrow::Cow;
 }
 
-use codespan_reporting::diagnostic;
+use codespan_reporting::{diagnostic, files::SimpleFiles};
 use std::fmt::{self, Display};
 
+use crate::json::Json;
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
 pub struct Span {
     pub s: usize,
@@ -73,8 +77,27 @@ pub enum DiagnosticType {
     Scope = 7,
     FunctionArgumentMany = 8,
     Type = 9,
+    Import = 10,
+    Unsupported = 17,
+
+    UnusedVariable = 11,
+    UnusedParameter = 12,
+    Unreachable = 13,
+    MissingYaRly = 14,
+    Shadowing = 15,
+    UnusedIt = 16,
 }
 
+/// Every diagnostic that is a lint, and can be allowed or denied
+pub const LINTS: &[DiagnosticType] = &[
+    DiagnosticType::UnusedVariable,
+    DiagnosticType::UnusedParameter,
+    DiagnosticType::Unreachable,
+    DiagnosticType::MissingYaRly,
+    DiagnosticType::Shadowing,
+    DiagnosticType::UnusedIt,
+];
+
 impl DiagnosticType {
     pub fn description(&self) -> &'static str {
         match self {
@@ -87,6 +110,14 @@ impl DiagnosticType {
             DiagnosticType::FunctionArgumentMany => "too many funkshion arguments",
             DiagnosticType::Scope => "scope error",
             DiagnosticType::Type => "mismatched types",
+            DiagnosticType::Import => "failed to import module",
+            DiagnosticType::Unsupported => "not supported yet",
+            DiagnosticType::UnusedVariable => "unused variable",
+            DiagnosticType::UnusedParameter => "unused funkshun parameter",
+            DiagnosticType::Unreachable => "unreachable statement",
+            DiagnosticType::MissingYaRly => "`O RLY?` without `YA RLY`",
+            DiagnosticType::Shadowing => "declaration shadows another",
+            DiagnosticType::UnusedIt => "value stored in IT is never read",
         }
     }
 
@@ -101,8 +132,21 @@ impl DiagnosticType {
             DiagnosticType::FunctionArgumentMany => "funk_arg_many",
             DiagnosticType::Scope => "scope",
             DiagnosticType::Type => "type_error",
+            DiagnosticType::Import => "import",
+            DiagnosticType::Unsupported => "unsupported",
+            DiagnosticType::UnusedVariable => "unused_variable",
+            DiagnosticType::UnusedParameter => "unused_parameter",
+            DiagnosticType::Unreachable => "unreachable",
+            DiagnosticType::MissingYaRly => "missing_ya_rly",
+            DiagnosticType::Shadowing => "shadowing",
+            DiagnosticType::UnusedIt => "unused_it",
         }
     }
+
+    /// Finds a lint by its name, like `unused_variable`
+    pub fn lint(name: &str) -> Option<Self> {
+        LINTS.iter().copied().find(|lint| lint.name() == name)
+    }
 }
 
 impl Display for DiagnosticType {
@@ -111,10 +155,54 @@ impl Display for DiagnosticType {
     }
 }
 
+/// How bad a diagnostic is. Only errors stop a program from running.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// How diagnostics are written to stderr
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MessageFormat {
+    /// Rendered with the source, for people
+    Human,
+    /// One JSON object per line, for tools
+    Json,
+}
+
+/// The byte offset, line and column of a char offset, with lines and columns counted from 1
+fn locate(source: &str, offset: usize) -> (usize, usize, usize) {
+    let (mut line, mut column) = (1, 1);
+    for (i, (byte, c)) in source.char_indices().enumerate() {
+        if i == offset {
+            return (byte, line, column);
+        }
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (source.len(), line, column)
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Annotation {
     pub message: Cow<'static, str>,
     pub span: Span,
+    /// Whether this is part of the problem, rather than context like a stack frame
+    pub primary: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -122,17 +210,45 @@ pub struct Annotation {
 pub struct Diagnostic {
     pub span: Span,
     pub ty: DiagnosticType,
+    pub severity: Severity,
     pub note: Option<Cow<'static, str>>,
     pub annotations: SmallVec<[Annotation; 1]>,
 }
 
 impl Annotation {
     fn into_codespan(self) -> diagnostic::Label<usize> {
-        diagnostic::Label::primary(self.span.file, self.span.s..self.span.e)
-            .with_message(self.message)
+        let range = self.span.s..self.span.e;
+        let label = if self.primary {
+            diagnostic::Label::primary(self.span.file, range)
+        } else {
+            diagnostic::Label::secondary(self.span.file, range)
+        };
+        label.with_message(self.message)
     }
     pub fn new(message: Cow<'static, str>, span: Span) -> Self {
-        Self { message, span }
+        Self {
+            message,
+            span,
+            primary: true,
+        }
+    }
+
+    fn to_json(&self, files: &SimpleFiles<String, String>) -> Json {
+        let file = files.get(self.span.file).ok();
+        let source = file.map_or("", |file| file.source().as_str());
+        let (byte_start, line_start, column_start) = locate(source, self.span.s);
+        let (byte_end, line_end, column_end) = locate(source, self.span.e);
+        Json::object(vec![
+            ("message", self.message.as_ref().into()),
+            ("file", file.map(|file| file.name().as_str()).into()),
+            ("byte_start", byte_start.into()),
+            ("byte_end", byte_end.into()),
+            ("line_start", line_start.into()),
+            ("column_start", column_start.into()),
+            ("line_end", line_end.into()),
+            ("column_end", column_end.into()),
+            ("primary", self.primary.into()),
+        ])
     }
 }
 
@@ -141,6 +257,7 @@ impl Diagnostic {
         Self {
             annotations: SmallVec::new(),
             note: None,
+            severity: Severity::Error,
             ty,
             span,
         }
@@ -151,8 +268,20 @@ impl Diagnostic {
         self
     }
 
+    /// Adds an annotation that gives context, rather than pointing at the problem
+    pub fn secondary(mut self, message: Cow<'static, str>, span: Span) -> Self {
+        self.annotations.push(Annotation {
+            primary: false,
+            ..Annotation::new(message, span)
+        });
+        self
+    }
+
     pub fn into_codespan(self) -> diagnostic::Diagnostic<usize> {
-        let mut initial = diagnostic::Diagnostic::error();
+        let mut initial = match self.severity {
+            Severity::Error => diagnostic::Diagnostic::error(),
+            Severity::Warning => diagnostic::Diagnostic::warning(),
+        };
         initial = initial
             .with_message(self.ty.description())
             .with_code(self.ty.to_string())
@@ -172,4 +301,51 @@ impl Diagnostic {
         self.note = Some(note);
         self
     }
+
+    pub fn severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// The diagnostic as a JSON object, for `--message-format=json`
+    pub fn to_json(&self, files: &SimpleFiles<String, String>) -> Json {
+        Json::object(vec![
+            ("code", format!("E{:0>3}", self.ty as usize).into()),
+            ("name", self.ty.name().into()),
+            ("severity", self.severity.name().into()),
+            ("message", self.ty.description().into()),
+            ("note", self.note.as_deref().into()),
+            (
+                "annotations",
+                self.annotations
+                    .iter()
+                    .map(|a| a.to_json(files))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_test {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let mut files = SimpleFiles::new();
+        let file = files.add("a.lol".to_string(), "HAI\nVISIBLE é x\n".to_string());
+        let diagnostic = Diagnostic::build(DiagnosticType::UnknownSymbol, Span::new(14, 15, file))
+            .annotation(Cow::Borrowed("unknown"), Span::new(14, 15, file))
+            .severity(Severity::Warning);
+        assert_eq!(
+            diagnostic.to_json(&files).to_string(),
+            concat!(
+                r#"{"code":"E005","name":"unknown_symbol","severity":"warning","#,
+                r#""message":"unknown symbol","note":null,"annotations":[{"message":"unknown","#,
+                r#""file":"a.lol","byte_start":15,"byte_end":16,"line_start":2,"column_start":11,"#,
+                r#""line_end":2,"column_end":12,"primary":true}]}"#
+            )
+        );
+    }
 }
@@ -0,0 +1,58 @@
+//! `lulz fmt`, which rewrites programs in the canonical style
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Read};
+
+use lulz::frontend::printer::format;
+use lulz::runtime::errors::emit_errors;
+use lulz::sourcemap::SourceMap;
+
+use crate::err;
+use crate::opts::Opts;
+
+/// Formats every input, and returns the exit code
+pub fn run(opts: Opts) -> i32 {
+    let mut status = 0;
+    let mut sources = SourceMap::new();
+    for input in opts.files {
+        let source = if input == "-" {
+            let mut buffer = String::new();
+            err::report(
+                io::stdin().read_to_string(&mut buffer),
+                Cow::Borrowed("Failed to read from stdin"),
+            );
+            buffer
+        } else {
+            err::report(
+                fs::read_to_string(&input),
+                Cow::Owned(format!("Failed to read file `{}`", input)),
+            )
+        };
+        let id = sources.add(input.clone(), source.clone());
+
+        let formatted = match format(&source, id) {
+            Ok(formatted) => formatted,
+            Err(es) => {
+                emit_errors(es, &sources, opts.message_format);
+                status = 1;
+                continue;
+            }
+        };
+
+        if opts.check {
+            if formatted != source {
+                eprintln!("`{}` is not formatted", input);
+                status = 1;
+            }
+        } else if input == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            err::report(
+                fs::write(&input, formatted),
+                Cow::Owned(format!("Failed to write file `{}`", input)),
+            );
+        }
+    }
+    status
+}
@@ -1,6 +1,10 @@
+use std::borrow::Cow;
 use std::hash::{Hash, Hasher};
 
-use crate::{backend::interner::StrId, diagnostics::Span};
+use crate::{
+    backend::interner::StrId,
+    diagnostics::{Diagnostic, DiagnosticType, Span},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ident(pub StrId, pub Span);
@@ -68,6 +72,49 @@ pub enum StmtTy {
     // Ident: index is the ident
     /// SetItem(source, item, index)
     SetItem(Expr, Expr, Result<Expr, bool>),
+
+    /// `O HAI IM` block
+    /// ObjectDef(name, parent, body)
+    ObjectDef(Ident, Option<Expr>, Block),
+    /// SlotDec(object, slot, value)
+    SlotDec(Expr, Ident, Option<Result<Expr, LolTy>>),
+    /// SlotAssign(object, slot, value)
+    SlotAssign(Expr, Ident, Expr),
+    /// MethodDef(object, name, args, body)
+    MethodDef(Expr, Ident, Vec<Ident>, Block),
+}
+
+impl StmtTy {
+    /// How the statement is written in LOLCODE, for messages about it
+    pub fn syntax(&self) -> &'static str {
+        match self {
+            StmtTy::Assignment(..) => "`R`",
+            StmtTy::DecAssign(..) => "`I HAS A`",
+            StmtTy::Import(..) => "`CAN HAS`",
+            StmtTy::FunctionDef(..) => "`HOW IZ I`",
+            StmtTy::Expr(..) => "an expression statement",
+            StmtTy::Case(..) => "`WTF?`",
+            StmtTy::If(..) => "`O RLY?`",
+            StmtTy::MutCast(..) => "`IS NOW A`",
+            StmtTy::Break => "`GTFO`",
+            StmtTy::Loop { .. } => "`IM IN YR`",
+            StmtTy::Return(..) => "`FOUND YR`",
+            StmtTy::Print(..) => "`VISIBLE`",
+            StmtTy::Input(..) => "`GIMMEH`",
+            StmtTy::Append(..) => "`PUT`",
+            StmtTy::SetItem(..) => "`SHUV`",
+            StmtTy::ObjectDef(..) => "`O HAI IM`",
+            StmtTy::SlotDec(..) => "`HAS A`",
+            StmtTy::SlotAssign(..) => "slot assignment",
+            StmtTy::MethodDef(..) => "`HOW IZ` a method",
+        }
+    }
+}
+
+/// An error for a statement or expression that parses, but can't be run yet
+pub fn unsupported(syntax: &'static str, span: Span) -> Diagnostic {
+    Diagnostic::build(DiagnosticType::Unsupported, span)
+        .annotation(Cow::Owned(format!("{} is not supported yet", syntax)), span)
 }
 
 #[derive(Debug, Clone)]
@@ -117,6 +164,46 @@ pub enum ExprTy {
 
     /// GetItem(source, index)
     GetItem(Box<Expr>, Result<Box<Expr>, bool>),
+
+    /// A new bukkit, inheriting from the parent if there is one
+    Bukkit(Option<Box<Expr>>),
+    /// The receiver of a method
+    Me,
+    /// GetSlot(object, slot)
+    GetSlot(Box<Expr>, Ident),
+    /// MethodCall(object, method, args)
+    MethodCall(Box<Expr>, Ident, Vec<Expr>),
+}
+
+impl ExprTy {
+    /// How the expression is written in LOLCODE, for messages about it
+    pub fn syntax(&self) -> &'static str {
+        match self {
+            ExprTy::Span(..) => "a source location",
+            ExprTy::Float(..) => "a NUMBAR",
+            ExprTy::Int(..) => "a NUMBR",
+            ExprTy::String(..) => "a YARN",
+            ExprTy::InterpStr(..) => "a YARN with `:{var}`",
+            ExprTy::Bool(..) => "a TROOF",
+            ExprTy::List(..) => "`CHAIN OF`",
+            ExprTy::Null => "`NOOB`",
+            ExprTy::It => "`IT`",
+            ExprTy::Variable(..) => "a variable",
+            ExprTy::FunctionCall(..) => "`I IZ`",
+            ExprTy::Concat(..) => "`SMOOSH`",
+            ExprTy::Cast(..) => "`MAEK`",
+            ExprTy::Operator(..) => "an operator",
+            ExprTy::All(..) => "`ALL OF`",
+            ExprTy::Any(..) => "`ANY OF`",
+            ExprTy::UnaryOp(UnOpTy::Not, _) => "`NOT`",
+            ExprTy::UnaryOp(UnOpTy::Length, _) => "`LANGTH OF`",
+            ExprTy::GetItem(..) => "`GRAB`",
+            ExprTy::Bukkit(..) => "`BUKKIT`",
+            ExprTy::Me => "`ME`",
+            ExprTy::GetSlot(..) => "`'Z`",
+            ExprTy::MethodCall(..) => "a method call",
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -156,6 +243,7 @@ pub enum LolTy {
     Numbr,
     Funkshun,
     Lizt,
+    Bukkit,
 }
 
 impl LolTy {
@@ -168,6 +256,21 @@ impl LolTy {
             LolTy::Numbr => "numbr",
             LolTy::Funkshun => "funkshun",
             LolTy::Lizt => "lizt",
+            LolTy::Bukkit => "bukkit",
+        }
+    }
+
+    /// The name of the type in LOLCODE
+    pub fn name(&self) -> &'static str {
+        match self {
+            LolTy::Noob => "NOOB",
+            LolTy::Yarn => "YARN",
+            LolTy::Troof => "TROOF",
+            LolTy::Numbar => "NUMBAR",
+            LolTy::Numbr => "NUMBR",
+            LolTy::Funkshun => "FUNKSHUN",
+            LolTy::Lizt => "LIZT",
+            LolTy::Bukkit => "BUKKIT",
         }
     }
 
@@ -178,6 +281,7 @@ impl LolTy {
             LolTy::Numbr => ExprTy::Int(0),
             LolTy::Yarn => ExprTy::String("".to_string()),
             LolTy::Lizt => ExprTy::List(Vec::new()),
+            LolTy::Bukkit => ExprTy::Bukkit(None),
             LolTy::Funkshun | LolTy::Noob => ExprTy::Null,
         }
     }
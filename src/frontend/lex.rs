@@ -57,6 +57,12 @@ pub enum TokenKind {
     Smoosh,
     Maek,
 
+    Kthx,
+    Liek,
+    Me,
+    /// The `'Z` slot access operator
+    SlotZ,
+
     Put,
     Into,
     Frum,
@@ -106,7 +112,8 @@ pub enum TokenKind {
 }
 
 impl TokenKind {
-    pub fn eq(&self, other: &Self) -> bool {
+    /// Whether both tokens are of the same kind, whatever they hold
+    pub fn same_kind(&self, other: &Self) -> bool {
         std::mem::discriminant(self) == std::mem::discriminant(other)
     }
 }
@@ -162,6 +169,11 @@ impl Display for TokenKind {
                 TokenKind::Smoosh => "token `SMOOSH`",
                 TokenKind::Maek => "token `MAEK`",
 
+                TokenKind::Kthx => "token `KTHX`",
+                TokenKind::Liek => "token `LIEK`",
+                TokenKind::Me => "token `ME`",
+                TokenKind::SlotZ => "token `'Z`",
+
                 TokenKind::Les => "token `LES`",
                 TokenKind::Greter => "token `GRETER`",
                 TokenKind::Eq => "token `EQ`",
@@ -218,6 +230,16 @@ pub struct Token {
     pub span: Span,
 }
 
+/// A comment, which the lexer skips over but keeps for tools like the formatter
+#[derive(Eq, Debug, PartialEq, Clone)]
+pub struct Comment {
+    /// The text after `BTW`, or between `OBTW` and `TLDR`
+    pub text: String,
+    /// Whether this is an `OBTW ... TLDR` comment
+    pub multiline: bool,
+    pub span: Span,
+}
+
 pub struct Lexer<'a> {
     stream: Peekable<Chars<'a>>,
     position: usize,
@@ -225,8 +247,100 @@ pub struct Lexer<'a> {
     had_newline: bool,
     pub interner: &'a mut Interner,
     peeked: Option<Failible<Token>>,
+    /// The token after `peeked`, only lexed once `peeked` is
+    peeked_second: Option<Failible<Token>>,
+    /// Every comment lexed so far, in order
+    pub comments: Vec<Comment>,
 }
 
+/// Every keyword, and the token it is lexed as
+#[rustfmt::skip]
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("HAI", TokenKind::Hai),
+    ("KTHXBYE", TokenKind::Kthxbye),
+    ("IM", TokenKind::Im),
+    ("IN", TokenKind::In),
+    ("YR", TokenKind::Yr),
+    ("TIL", TokenKind::Till),
+    ("WILE", TokenKind::Wile),
+    ("OUTTA", TokenKind::Outta),
+    ("WTF", TokenKind::Wtf),
+    ("OIC", TokenKind::Oic),
+    ("OMG", TokenKind::Omg),
+    ("OMGWTF", TokenKind::Omgwtf),
+    ("RLY", TokenKind::Rly),
+    ("MAEK", TokenKind::Maek),
+    ("NOOB", TokenKind::Noob),
+    ("O", TokenKind::O),
+    ("MEBBE", TokenKind::Mebbe),
+    ("WAI", TokenKind::Wai),
+    ("IS", TokenKind::Is),
+    ("GTFO", TokenKind::Gtfo),
+    ("CAN", TokenKind::Can),
+    ("I", TokenKind::I),
+    ("IT", TokenKind::It),
+    ("HAS", TokenKind::Has),
+    ("ITZ", TokenKind::Itz),
+    ("WIN", TokenKind::Win),
+    ("FAIL", TokenKind::Fail),
+    ("NOW", TokenKind::Now),
+    ("IZ", TokenKind::Iz),
+    ("AN", TokenKind::An),
+    ("VISIBLE", TokenKind::Visible),
+    ("HOW", TokenKind::How),
+    ("IF", TokenKind::If),
+    ("U", TokenKind::U),
+    ("SAY", TokenKind::Say),
+    ("SO", TokenKind::So),
+    ("FOUND", TokenKind::Found),
+    ("YA", TokenKind::Ya),
+    ("NO", TokenKind::No),
+    ("A", TokenKind::A),
+    ("R", TokenKind::R),
+    ("GIMMEH", TokenKind::Gimmeh),
+    ("MKAY", TokenKind::Mkay),
+    ("SMOOSH", TokenKind::Smoosh),
+
+    ("KTHX", TokenKind::Kthx),
+    ("LIEK", TokenKind::Liek),
+    ("ME", TokenKind::Me),
+
+    ("GRETER", TokenKind::Greter),
+    ("LES", TokenKind::Les),
+    ("EQ", TokenKind::Eq),
+    ("THEN", TokenKind::Then),
+
+    ("PUT", TokenKind::Put),
+    ("INTO", TokenKind::Into),
+    ("CHAIN", TokenKind::Chain),
+
+    ("GRAB", TokenKind::Grab),
+    ("SHUV", TokenKind::Shuv),
+    ("FRUNT", TokenKind::Frunt),
+    ("BAK", TokenKind::Bak),
+    ("FRUM", TokenKind::Frum),
+
+    ("SUM", TokenKind::Sum),
+    ("DIFF", TokenKind::Diff),
+    ("PRODUKT", TokenKind::Produkt),
+    ("QUOSHUNT", TokenKind::Quoshunt),
+    ("MOD", TokenKind::Mod),
+    ("BIGGR", TokenKind::Biggr),
+    ("SMALLR", TokenKind::Smallr),
+    ("OF", TokenKind::Of),
+
+    ("BOTH", TokenKind::Both),
+    ("EITHER", TokenKind::Either),
+    ("WON", TokenKind::Won),
+    ("NOT", TokenKind::Not),
+    ("LANGTH", TokenKind::Langth),
+    ("ALL", TokenKind::All),
+    ("ANY", TokenKind::Any),
+
+    ("SAEM", TokenKind::Saem),
+    ("DIFFRINT", TokenKind::Diffrint),
+];
+
 const EOF: char = '\0';
 const ESCAPES: &[(char, char)] = &[
     (':', ':'),
@@ -236,6 +350,15 @@ const ESCAPES: &[(char, char)] = &[
     ('o', '\u{7}'),
 ];
 
+/// Whether `name` lexes as an identifier, rather than as a keyword, a comment or several tokens
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if Lexer::is_id_start(c))
+        && chars.all(Lexer::is_id_continue)
+        && !matches!(name, "BTW" | "OBTW")
+        && KEYWORDS.iter().all(|(keyword, _)| *keyword != name)
+}
+
 impl<'a> Lexer<'a> {
     pub fn new(chars: Chars<'a>, source_id: usize, interner: &'a mut Interner) -> Self {
         Self {
@@ -244,7 +367,9 @@ impl<'a> Lexer<'a> {
             position: 0,
             had_newline: false,
             peeked: None,
+            peeked_second: None,
             interner,
+            comments: Vec::new(),
         }
     }
 
@@ -252,23 +377,40 @@ impl<'a> Lexer<'a> {
         match self.peeked {
             Some(ref peeked) => peeked,
             None => {
-                self.peeked = Some(self.next());
+                self.peeked = Some(self.lex());
                 self.peeked.as_ref().unwrap()
             }
         }
     }
 
-    pub fn next(&mut self) -> Failible<Token> {
-        match self.peeked.take() {
-            Some(v) => v,
+    /// Looks at the token after the next one
+    pub fn peek_second(&mut self) -> &Failible<Token> {
+        self.peek();
+        match self.peeked_second {
+            Some(ref peeked) => peeked,
             None => {
-                let tok = self.next_token_inner()?;
-                self.had_newline = tok.token_kind.eq(&TokenKind::Break);
-                Ok(tok)
+                self.peeked_second = Some(self.lex());
+                self.peeked_second.as_ref().unwrap()
             }
         }
     }
 
+    pub fn next_token(&mut self) -> Failible<Token> {
+        match self.peeked.take() {
+            Some(v) => {
+                self.peeked = self.peeked_second.take();
+                v
+            }
+            None => self.lex(),
+        }
+    }
+
+    fn lex(&mut self) -> Failible<Token> {
+        let tok = self.next_token_inner()?;
+        self.had_newline = tok.token_kind.same_kind(&TokenKind::Break);
+        Ok(tok)
+    }
+
     fn next_token_inner(&mut self) -> Failible<Token> {
         let prev_pos = self.position;
         let kind = match self.eat() {
@@ -294,6 +436,18 @@ impl<'a> Lexer<'a> {
             },
             '?' => TokenKind::Question,
             '!' => TokenKind::Bang,
+            '\'' => match self.peek_char() {
+                'Z' => {
+                    self.eat();
+                    TokenKind::SlotZ
+                }
+                c => {
+                    return Err(Self::lexer_err(
+                        c,
+                        Span::new(self.position, self.position + 1, self.source_id),
+                    ))
+                }
+            },
             '"' => self.eat_string()?,
             ',' => TokenKind::Break,
             '\n' => {
@@ -302,11 +456,11 @@ impl<'a> Lexer<'a> {
                 }
                 TokenKind::Break
             }
-            c if Self::is_whitespace(c) => return self.next(),
+            c if Self::is_whitespace(c) => return self.lex(),
             '\0' => TokenKind::Eof,
             '-' => {
                 let c = self.peek_char();
-                match self.next()?.token_kind {
+                match self.lex()?.token_kind {
                     TokenKind::Number(s) => TokenKind::Number(format!("-{}", s)),
                     _ => {
                         return Err(Self::lexer_err(
@@ -425,7 +579,7 @@ impl<'a> Lexer<'a> {
                 .into());
         }
 
-        self.next()
+        self.lex()
     }
 
     fn eat_string(&mut self) -> Failible<TokenKind> {
@@ -519,124 +673,55 @@ impl<'a> Lexer<'a> {
         self.peek_char() == EOF
     }
 
-    fn consume_multiline(&mut self) -> Failible<()> {
-        while self.peek_char() != 'T' && !self.is_eof() {
-            self.eat();
-        }
-
-        self.eat();
-        if self.peek_char() == 'L' {
-            self.eat();
-            if self.peek_char() == 'D' {
-                self.eat();
-                if self.peek_char() == 'R' {
-                    self.eat();
-                    return Ok(());
-                }
+    fn consume_multiline(&mut self) -> Failible<String> {
+        let mut acc = String::new();
+        while !self.is_eof() {
+            acc.push(self.eat());
+            if acc.ends_with("TLDR") {
+                acc.truncate(acc.len() - "TLDR".len());
+                return Ok(acc);
             }
         }
 
-        self.consume_multiline()
+        let span = Span::new(self.position, self.position + 1, self.source_id);
+        Err(Diagnostic::build(DiagnosticType::UnexpectedCharacter, span)
+            .annotation(Cow::Borrowed("expected `TLDR`, found end of file"), span)
+            .into())
     }
 
     fn ident(&mut self, first: char) -> Failible<Token> {
         let id = self.consume_while(first, Self::is_id_continue);
+        let start = self.position - id.len();
         Ok(Token {
-            span: Span::new(self.position - id.len(), self.position, self.source_id),
+            span: Span::new(start, self.position, self.source_id),
             token_kind: match &id[..] {
                 "BTW" => {
-                    self.consume_while(first, Self::is_not_newline);
+                    let text = self.consume_until(Self::is_not_newline);
+                    self.comments.push(Comment {
+                        text,
+                        multiline: false,
+                        span: Span::new(start, self.position, self.source_id),
+                    });
                     if self.had_newline {
                         // if there was already a newline before this comment, skip over the next
                         self.eat();
                     }
-                    return self.next();
+                    return self.lex();
                 }
                 "OBTW" => {
-                    self.consume_multiline()?;
-                    return self.next();
+                    let text = self.consume_multiline()?;
+                    self.comments.push(Comment {
+                        text,
+                        multiline: true,
+                        span: Span::new(start, self.position, self.source_id),
+                    });
+                    return self.lex();
                 }
-                "HAI" => TokenKind::Hai,
-                "KTHXBYE" => TokenKind::Kthxbye,
-                "IM" => TokenKind::Im,
-                "IN" => TokenKind::In,
-                "YR" => TokenKind::Yr,
-                "TIL" => TokenKind::Till,
-                "WILE" => TokenKind::Wile,
-                "OUTTA" => TokenKind::Outta,
-                "WTF" => TokenKind::Wtf,
-                "OIC" => TokenKind::Oic,
-                "OMG" => TokenKind::Omg,
-                "OMGWTF" => TokenKind::Omgwtf,
-                "RLY" => TokenKind::Rly,
-                "MAEK" => TokenKind::Maek,
-                "NOOB" => TokenKind::Noob,
-                "O" => TokenKind::O,
-                "MEBBE" => TokenKind::Mebbe,
-                "WAI" => TokenKind::Wai,
-                "IS" => TokenKind::Is,
-                "GTFO" => TokenKind::Gtfo,
-                "CAN" => TokenKind::Can,
-                "I" => TokenKind::I,
-                "IT" => TokenKind::It,
-                "HAS" => TokenKind::Has,
-                "ITZ" => TokenKind::Itz,
-                "WIN" => TokenKind::Win,
-                "FAIL" => TokenKind::Fail,
-                "NOW" => TokenKind::Now,
-                "IZ" => TokenKind::Iz,
-                "AN" => TokenKind::An,
-                "VISIBLE" => TokenKind::Visible,
-                "HOW" => TokenKind::How,
-                "IF" => TokenKind::If,
-                "U" => TokenKind::U,
-                "SAY" => TokenKind::Say,
-                "SO" => TokenKind::So,
-                "FOUND" => TokenKind::Found,
-                "YA" => TokenKind::Ya,
-                "NO" => TokenKind::No,
-                "A" => TokenKind::A,
-                "R" => TokenKind::R,
-                "GIMMEH" => TokenKind::Gimmeh,
-                "MKAY" => TokenKind::Mkay,
-                "SMOOSH" => TokenKind::Smoosh,
-
-                "GRETER" => TokenKind::Greter,
-                "LES" => TokenKind::Les,
-                "EQ" => TokenKind::Eq,
-                "THEN" => TokenKind::Then,
-
-                "PUT" => TokenKind::Put,
-                "INTO" => TokenKind::Into,
-                "CHAIN" => TokenKind::Chain,
-
-                "GRAB" => TokenKind::Grab,
-                "SHUV" => TokenKind::Shuv,
-                "FRUNT" => TokenKind::Frunt,
-                "BAK" => TokenKind::Bak,
-                "FRUM" => TokenKind::Frum,
-
-                "SUM" => TokenKind::Sum,
-                "DIFF" => TokenKind::Diff,
-                "PRODUKT" => TokenKind::Produkt,
-                "QUOSHUNT" => TokenKind::Quoshunt,
-                "MOD" => TokenKind::Mod,
-                "BIGGR" => TokenKind::Biggr,
-                "SMALLR" => TokenKind::Smallr,
-                "OF" => TokenKind::Of,
-
-                "BOTH" => TokenKind::Both,
-                "EITHER" => TokenKind::Either,
-                "WON" => TokenKind::Won,
-                "NOT" => TokenKind::Not,
-                "LANGTH" => TokenKind::Langth,
-                "ALL" => TokenKind::All,
-                "ANY" => TokenKind::Any,
-
-                "SAEM" => TokenKind::Saem,
-                "DIFFRINT" => TokenKind::Diffrint,
-
-                _ => TokenKind::Ident(self.interner.intern(&id)),
+                _ => KEYWORDS
+                    .iter()
+                    .find(|(keyword, _)| *keyword == id)
+                    .map(|(_, token)| token.clone())
+                    .unwrap_or_else(|| TokenKind::Ident(self.interner.intern(&id))),
             },
         })
     }
@@ -651,7 +736,7 @@ mod lexer_test {
             let mut interner = Interner::default();
             let mut lexer = Lexer::new(source.chars(), 0, &mut interner);
             assert_eq!(
-                lexer.next().map_err(|e| e.into_inner()[0].ty),
+                lexer.next_token().map_err(|e| e.into_inner()[0].ty),
                 Err(err.clone())
             );
         }
@@ -661,7 +746,7 @@ mod lexer_test {
         for (source, token) in map.iter() {
             let mut interner = Interner::default();
             let mut lexer = Lexer::new(source.chars(), 0, &mut interner);
-            assert_eq!(lexer.next().map(|t| t.token_kind), Ok(token.clone()));
+            assert_eq!(lexer.next_token().map(|t| t.token_kind), Ok(token.clone()));
         }
     }
 
@@ -681,6 +766,33 @@ mod lexer_test {
         ]);
     }
 
+    #[test]
+    fn comment_trivia() {
+        let mut interner = Interner::default();
+        let mut lexer = Lexer::new("x BTW one\nOBTW two\nTLDR y".chars(), 0, &mut interner);
+        while lexer.next_token().unwrap().token_kind != TokenKind::Eof {}
+        assert_eq!(
+            lexer.comments,
+            vec![
+                Comment {
+                    text: " one".to_string(),
+                    multiline: false,
+                    span: Span::new(2, 9, 0),
+                },
+                Comment {
+                    text: " two\n".to_string(),
+                    multiline: true,
+                    span: Span::new(10, 23, 0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_multiline_comment() {
+        assert_err(&[("OBTW never closed", DiagnosticType::UnexpectedCharacter)]);
+    }
+
     #[test]
     fn keywords() {
         assert_map(&[
@@ -760,6 +872,9 @@ mod lexer_test {
             ("EQ", TokenKind::Eq),
             ("GRETER", TokenKind::Greter),
             ("LES", TokenKind::Les),
+            ("KTHX", TokenKind::Kthx),
+            ("LIEK", TokenKind::Liek),
+            ("ME", TokenKind::Me),
         ]);
     }
 
@@ -769,6 +884,7 @@ mod lexer_test {
             (".", TokenKind::Dot),
             ("!", TokenKind::Bang),
             ("?", TokenKind::Question),
+            ("'Z", TokenKind::SlotZ),
         ]);
     }
 
@@ -791,11 +907,20 @@ mod lexer_test {
         let mut interner = Interner::default();
         let mut lexer = Lexer::new("helloWorldIdent".chars(), 0, &mut interner);
         assert_eq!(
-            lexer.next().map(|t| t.token_kind),
+            lexer.next_token().map(|t| t.token_kind),
             Ok(TokenKind::Ident(interner.intern("helloWorldIdent")))
         );
     }
 
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("SPAWN"));
+        assert!(is_identifier("_cat_2"));
+        for name in ["", "2cats", "my cat", "cät", "VISIBLE", "BTW"].iter() {
+            assert!(!is_identifier(name), "{}", name);
+        }
+    }
+
     #[test]
     fn string() {
         assert_map(&[
@@ -825,12 +950,15 @@ mod lexer_test {
             ("\"", DiagnosticType::UnexpectedCharacter),
             ("}", DiagnosticType::UnexpectedCharacter),
             ("-asf", DiagnosticType::UnexpectedCharacter),
+            ("'a", DiagnosticType::UnexpectedCharacter),
         ]);
     }
 
     #[test]
-    fn token_eq() {
-        assert!(TokenKind::Break.eq(&TokenKind::Break));
-        assert!(!TokenKind::Break.eq(&TokenKind::Itz));
+    fn same_kind() {
+        assert!(TokenKind::Break.same_kind(&TokenKind::Break));
+        assert!(!TokenKind::Break.same_kind(&TokenKind::Itz));
+        let (one, two) = ("1".to_string(), "2".to_string());
+        assert!(TokenKind::Number(one).same_kind(&TokenKind::Number(two)));
     }
 }
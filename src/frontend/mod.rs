@@ -1,3 +1,4 @@
 pub mod ast;
 pub mod lex;
 pub mod parse;
+pub mod printer;
@@ -1,12 +1,21 @@
-use crate::ast::*;
 use crate::backend::interner::{Interner, StrId};
 use crate::diagnostics::prelude::*;
-use crate::lex::{Lexer, Token, TokenKind};
+use crate::frontend::ast::*;
+use crate::frontend::lex::{Comment, Lexer, Token, TokenKind};
+use smallvec::SmallVec;
 
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_span: Span,
     source_id: usize,
+    /// Errors from statements that were skipped to keep parsing
+    errors: SmallVec<[Diagnostic; 1]>,
+    /// Whether the last token was a line break
+    after_break: bool,
+    /// How many blocks have been started, to tell if a statement failed before its body
+    bodies: usize,
+    /// The version after `HAI`
+    pub version: String,
 }
 
 impl<'a> Parser<'a> {
@@ -14,13 +23,18 @@ impl<'a> Parser<'a> {
         Self {
             current_span: Span::new(0, 0, lexer.source_id),
             source_id: lexer.source_id,
+            errors: SmallVec::new(),
+            after_break: false,
+            bodies: 0,
+            version: String::new(),
             lexer,
         }
     }
 
     fn next_token(&mut self) -> Failible<Token> {
-        let tok = self.lexer.next()?;
+        let tok = self.lexer.next_token()?;
         self.current_span = tok.span;
+        self.after_break = tok.token_kind.same_kind(&TokenKind::Break);
         Ok(tok)
     }
 
@@ -73,6 +87,11 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse(&mut self) -> Failible<Block> {
+        let res = self.program();
+        self.finish(res)
+    }
+
+    fn program(&mut self) -> Failible<Block> {
         self.eat_lines()?;
         self.expect(TokenKind::Hai)?;
         self.version()?;
@@ -84,10 +103,165 @@ impl<'a> Parser<'a> {
         Ok(block)
     }
 
+    /// Parses statements without the surrounding `HAI` and `KTHXBYE`, as typed into the repl
+    pub fn parse_repl(&mut self) -> Failible<Block> {
+        let res = self.repl_input();
+        self.finish(res)
+    }
+
+    fn repl_input(&mut self) -> Failible<Block> {
+        self.eat_lines()?;
+        let block = self.block(Some(&[TokenKind::Eof]))?;
+        self.eat_lines()?;
+        self.expect(TokenKind::Eof)?;
+        Ok(block)
+    }
+
+    /// Takes the comments skipped over while parsing
+    pub fn comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.lexer.comments)
+    }
+
+    /// Fails with every error collected while parsing, if there were any
+    fn finish(&mut self, res: Failible<Block>) -> Failible<Block> {
+        let mut errors = std::mem::take(&mut self.errors);
+        match res {
+            Ok(block) if errors.is_empty() => Ok(block),
+            Ok(_) => Err(errors.into()),
+            Err(es) => {
+                errors.extend(es.into_inner());
+                Err(errors.into())
+            }
+        }
+    }
+
+    /// Whether a token only continues or closes a block, and can't start a statement
+    fn continues_block(token: &TokenKind) -> bool {
+        matches!(
+            token,
+            TokenKind::Oic
+                | TokenKind::Ya
+                | TokenKind::No
+                | TokenKind::Mebbe
+                | TokenKind::Omg
+                | TokenKind::Omgwtf
+                | TokenKind::If
+                | TokenKind::Im
+                | TokenKind::Kthx
+                | TokenKind::Kthxbye
+        )
+    }
+
+    /// Whether the next token is a part of a block whose opening failed to parse, rather than of
+    /// the block around it
+    fn closes_unclosed(&mut self, unclosed: usize) -> Failible<bool> {
+        Ok(unclosed > 0
+            && !self.peek_eq(&TokenKind::Kthxbye)?
+            && Self::continues_block(&self.peek_token()?.token_kind))
+    }
+
+    /// Skips the rest of a statement that failed to parse, up to the next line break
+    fn synchronize(&mut self) {
+        if self.after_break {
+            // The error was at the end of the line
+            return;
+        }
+        loop {
+            match self.peek_token() {
+                Ok(tok) if tok.token_kind.same_kind(&TokenKind::Break) => return,
+                Ok(tok) if tok.token_kind.same_kind(&TokenKind::Eof) => return,
+                Ok(_) => {
+                    let _ = self.next_token();
+                }
+                // The bad character was skipped by the lexer
+                Err(es) => self.errors.extend(es.into_inner()),
+            }
+        }
+    }
+
+    /// Records an error for a statement followed by something other than a line break, and skips
+    /// to the end of the line
+    fn missing_break(&mut self) {
+        match self.next_token() {
+            Ok(tok) => self.errors.push(
+                Diagnostic::build(DiagnosticType::Syntax, tok.span).annotation(
+                    Cow::Owned(format!(
+                        "expected the statement to end, but found {}",
+                        tok.token_kind
+                    )),
+                    tok.span,
+                ),
+            ),
+            Err(es) => self.errors.extend(es.into_inner()),
+        }
+        self.synchronize();
+    }
+
+    /// Parses a statement, or records its errors and skips it. `unclosed` counts the blocks whose
+    /// opening line failed to parse: their bodies are parsed as statements of this block, and
+    /// their other parts are skipped without an error up to the matching closer.
+    fn recovering_statement(&mut self, unclosed: &mut usize) -> Option<Stmt> {
+        let bodies = self.bodies;
+        let tok = match self.next_token() {
+            Ok(tok) => tok,
+            Err(es) => {
+                self.errors.extend(es.into_inner());
+                self.synchronize();
+                return None;
+            }
+        };
+        // `IM` both opens and closes loops
+        let closes_loop = match tok.token_kind {
+            TokenKind::Im => self.peek_eq(&TokenKind::Outta),
+            _ => Ok(false),
+        };
+        let opens_block = match tok.token_kind {
+            TokenKind::O | TokenKind::Wtf | TokenKind::How => true,
+            TokenKind::Im => !matches!(closes_loop, Ok(true)),
+            _ => false,
+        };
+        if *unclosed > 0 {
+            let closes = match tok.token_kind {
+                TokenKind::Ya
+                | TokenKind::No
+                | TokenKind::Mebbe
+                | TokenKind::Omg
+                | TokenKind::Omgwtf => Some(false),
+                TokenKind::Oic | TokenKind::If | TokenKind::Kthx => Some(true),
+                TokenKind::Im if matches!(closes_loop, Ok(true)) => Some(true),
+                _ => None,
+            };
+            if let Some(closes) = closes {
+                if closes {
+                    // The matching closer, so later block parts are errors again
+                    *unclosed -= 1;
+                }
+                self.synchronize();
+                return None;
+            }
+        }
+        match closes_loop.and_then(|_| self.statement(tok)) {
+            Ok(stmt) => Some(stmt),
+            Err(es) => {
+                self.errors.extend(es.into_inner());
+                if opens_block && self.bodies == bodies {
+                    *unclosed += 1;
+                }
+                self.synchronize();
+                None
+            }
+        }
+    }
+
     fn version(&mut self) -> Failible<()> {
-        self.expect(TokenKind::Number(String::new()))?;
+        let major = self.expect(TokenKind::Number(String::new()))?;
         self.expect(TokenKind::Dot)?;
-        self.expect(TokenKind::Number(String::new()))?;
+        let minor = self.expect(TokenKind::Number(String::new()))?;
+        if let (TokenKind::Number(major), TokenKind::Number(minor)) =
+            (major.token_kind, minor.token_kind)
+        {
+            self.version = format!("{}.{}", major, minor);
+        }
         Ok(())
     }
 
@@ -95,8 +269,10 @@ impl<'a> Parser<'a> {
     fn next_tok_is(&mut self, tokens_after: Option<&'static [TokenKind]>) -> Failible<bool> {
         if let Some(tokens) = tokens_after {
             for tok in tokens {
-                if tok.eq(&self.peek_token()?.token_kind) {
-                    return Ok(true);
+                if tok.same_kind(&self.peek_token()?.token_kind) {
+                    // `IM IN YR` opens a nested loop, only `IM OUTTA YR` closes one
+                    return Ok(!tok.same_kind(&TokenKind::Im)
+                        || matches!(self.lexer.peek_second(), Ok(next) if next.token_kind.same_kind(&TokenKind::Outta)));
                 }
             }
         }
@@ -105,23 +281,23 @@ impl<'a> Parser<'a> {
 
     fn block(&mut self, tokens_after: Option<&'static [TokenKind]>) -> Failible<Block> {
         let start_span = self.current_span.s;
-        let statements = if self.next_tok_is(tokens_after)? {
-            vec![]
-        } else {
-            let mut statements = vec![self.statement()?];
-            loop {
-                if self.check(&TokenKind::Break)? {
-                    self.eat_lines()?;
-                    if self.next_tok_is(tokens_after)? {
+        let mut statements = Vec::new();
+        let mut unclosed = 0;
+        self.bodies += 1;
+        while !self.peek_eq(&TokenKind::Eof)?
+            && (!self.next_tok_is(tokens_after)? || self.closes_unclosed(unclosed)?)
+        {
+            if let Some(stmt) = self.recovering_statement(&mut unclosed) {
+                statements.push(stmt);
+                if !self.peek_eq(&TokenKind::Break)? {
+                    if self.next_tok_is(tokens_after)? || self.peek_eq(&TokenKind::Eof)? {
                         break;
                     }
-                    statements.push(self.statement()?);
-                } else {
-                    break;
+                    self.missing_break();
                 }
             }
-            statements
-        };
+            self.eat_lines()?;
+        }
         let end_span = self.current_span.e;
 
         Ok(Block(
@@ -130,8 +306,8 @@ impl<'a> Parser<'a> {
         ))
     }
 
-    fn statement(&mut self) -> Failible<Stmt> {
-        let next_token = self.next_token()?;
+    /// Parses the rest of a statement starting with `next_token`
+    fn statement(&mut self, next_token: Token) -> Failible<Stmt> {
         match next_token.token_kind {
             TokenKind::Put => self.append(next_token.span),
             TokenKind::Shuv => self.assign_array(next_token.span),
@@ -139,14 +315,20 @@ impl<'a> Parser<'a> {
             TokenKind::How => self.function(next_token.span),
             TokenKind::Found => self.return_statement(next_token.span),
             TokenKind::Wtf => self.case(next_token.span),
-            TokenKind::O => self.conditional(next_token.span),
+            TokenKind::O => {
+                if self.peek_eq(&TokenKind::Hai)? {
+                    self.object(next_token.span)
+                } else {
+                    self.conditional(next_token.span)
+                }
+            }
             TokenKind::Gtfo => Ok(Stmt {
                 span: next_token.span,
                 ty: StmtTy::Break,
             }),
             TokenKind::Can => self.import(next_token.span),
             TokenKind::I => {
-                if self.peek_token()?.token_kind.eq(&TokenKind::Iz) {
+                if self.peek_token()?.token_kind.same_kind(&TokenKind::Iz) {
                     self.assignment_or_expr(next_token)
                 } else {
                     self.declaration_assignment(next_token.span)
@@ -199,7 +381,7 @@ impl<'a> Parser<'a> {
         self.expect(TokenKind::Yr)?;
         let block_name = self.ident()?;
         let mut fn_id = None;
-        if TokenKind::Ident(StrId::default()).eq(&self.peek_token()?.token_kind) {
+        if TokenKind::Ident(StrId::default()).same_kind(&self.peek_token()?.token_kind) {
             let func = self.ident()?;
             self.expect(TokenKind::Yr)?;
             let index = self.ident()?;
@@ -273,7 +455,11 @@ impl<'a> Parser<'a> {
 
     fn function(&mut self, span: Span) -> Failible<Stmt> {
         self.expect(TokenKind::Iz)?;
-        self.expect(TokenKind::I)?;
+        let receiver = if self.check(&TokenKind::I)? {
+            None
+        } else {
+            Some(self.expr()?)
+        };
         let fn_name = self.ident()?;
         let mut args = Vec::new();
         if self.check(&TokenKind::Yr)? {
@@ -295,7 +481,29 @@ impl<'a> Parser<'a> {
         self.expect(TokenKind::So)?;
         Ok(Stmt {
             span: Span::new(span.s, self.current_span.e, self.source_id),
-            ty: StmtTy::FunctionDef(fn_name, args, block),
+            ty: match receiver {
+                Some(receiver) => StmtTy::MethodDef(receiver, fn_name, args, block),
+                None => StmtTy::FunctionDef(fn_name, args, block),
+            },
+        })
+    }
+
+    fn object(&mut self, span: Span) -> Failible<Stmt> {
+        self.expect(TokenKind::Hai)?;
+        self.expect(TokenKind::Im)?;
+        let name = self.ident()?;
+        let parent = if self.check(&TokenKind::Im)? {
+            self.expect(TokenKind::Liek)?;
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        self.expect_lines()?;
+        let block = self.block(Some(&[TokenKind::Kthx]))?;
+        self.expect(TokenKind::Kthx)?;
+        Ok(Stmt {
+            span: Span::new(span.s, self.current_span.e, self.source_id),
+            ty: StmtTy::ObjectDef(name, parent, block),
         })
     }
 
@@ -319,7 +527,7 @@ impl<'a> Parser<'a> {
             })
         } else {
             let mut cases = Vec::new();
-            if !TokenKind::Omgwtf.eq(&self.peek_token()?.token_kind) {
+            if !TokenKind::Omgwtf.same_kind(&self.peek_token()?.token_kind) {
                 cases.push(self.case_block(false)?);
                 loop {
                     let is_omg = self.check(&TokenKind::Omg)?;
@@ -340,6 +548,7 @@ impl<'a> Parser<'a> {
             };
 
             self.expect(TokenKind::Oic)?;
+            Self::check_case_values(&cases)?;
 
             Ok(Stmt {
                 span: Span::new(span.s, self.current_span.e, self.source_id),
@@ -348,6 +557,32 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn check_case_values(cases: &[(Expr, Block)]) -> Failible<()> {
+        for (i, (expr, _)) in cases.iter().enumerate() {
+            match expr.ty {
+                ExprTy::Int(..)
+                | ExprTy::Float(..)
+                | ExprTy::String(..)
+                | ExprTy::Bool(..)
+                | ExprTy::Null => {}
+                _ => {
+                    return Err(Diagnostic::build(DiagnosticType::Syntax, expr.span)
+                        .annotation(Cow::Borrowed("`OMG` values must be literals"), expr.span)
+                        .into())
+                }
+            }
+
+            if let Some((first, _)) = cases[..i].iter().find(|(prev, _)| prev == expr) {
+                return Err(Diagnostic::build(DiagnosticType::Syntax, expr.span)
+                    .annotation(Cow::Borrowed("value first used here"), first.span)
+                    .annotation(Cow::Borrowed("but is used again here"), expr.span)
+                    .note(Cow::Borrowed("each `OMG` value in a `WTF?` must be unique"))
+                    .into());
+            }
+        }
+        Ok(())
+    }
+
     fn conditional(&mut self, span: Span) -> Failible<Stmt> {
         self.expect(TokenKind::Rly)?;
         self.expect(TokenKind::Question)?;
@@ -407,21 +642,32 @@ impl<'a> Parser<'a> {
         self.expect(TokenKind::Has)?;
         self.expect(TokenKind::A)?;
         let ident = self.ident()?;
-        let expr = if self.check(&TokenKind::Itz)? {
-            if self.check(&TokenKind::A)? {
-                Some(Err(self.ty()?))
-            } else {
-                Some(Ok(self.expr()?))
-            }
-        } else {
-            None
-        };
+        let expr = self.declaration_value()?;
         Ok(Stmt {
             span: Span::new(span.s, self.current_span.e, self.source_id),
             ty: StmtTy::DecAssign(ident, expr),
         })
     }
 
+    fn declaration_value(&mut self) -> Failible<Option<Result<Expr, LolTy>>> {
+        if !self.check(&TokenKind::Itz)? {
+            return Ok(None);
+        }
+        if self.check(&TokenKind::A)? {
+            Ok(Some(Err(self.ty()?)))
+        } else if self.check(&TokenKind::Liek)? {
+            let start = self.current_span;
+            self.expect(TokenKind::A)?;
+            let parent = self.expr()?;
+            Ok(Some(Ok(Expr {
+                ty: ExprTy::Bukkit(Some(Box::new(parent))),
+                span: start.combine(&self.current_span),
+            })))
+        } else {
+            Ok(Some(Ok(self.expr()?)))
+        }
+    }
+
     fn print(&mut self, span: Span) -> Failible<Stmt> {
         let mut args = vec![self.expr()?];
         while !(self.peek_eq(&TokenKind::Break)? || self.peek_eq(&TokenKind::Bang)?) {
@@ -435,50 +681,54 @@ impl<'a> Parser<'a> {
     }
 
     fn assignment_or_expr(&mut self, prev: Token) -> Failible<Stmt> {
+        let start = prev.span;
+        let target = self.expr_inner(Some(prev))?;
         let statement_kind = match self.peek_token()?.token_kind {
             TokenKind::Is => {
                 self.next_token()?;
                 self.expect(TokenKind::Now)?;
                 self.expect(TokenKind::A)?;
-                StmtTy::MutCast(
-                    Ident(
-                        match prev.token_kind {
-                            TokenKind::Ident(s) => s,
-                            _ => unreachable!(),
-                        },
-                        prev.span,
-                    ),
-                    self.ty()?,
-                )
+                StmtTy::MutCast(Self::assignable_ident(target)?, self.ty()?)
             }
             TokenKind::R => {
                 self.next_token()?;
                 let expr = self.expr()?;
-                StmtTy::Assignment(
-                    Ident(
-                        match prev.token_kind {
-                            TokenKind::Ident(s) => s,
-                            _ => unreachable!(),
-                        },
-                        prev.span,
-                    ),
-                    expr,
-                )
+                match target.ty {
+                    ExprTy::GetSlot(object, slot) => StmtTy::SlotAssign(*object, slot, expr),
+                    _ => StmtTy::Assignment(Self::assignable_ident(target)?, expr),
+                }
+            }
+            TokenKind::Has => {
+                self.next_token()?;
+                self.expect(TokenKind::A)?;
+                let slot = self.ident()?;
+                StmtTy::SlotDec(target, slot, self.declaration_value()?)
             }
             _ => {
-                let expr = self.expr_inner(Some(prev))?;
                 return Ok(Stmt {
-                    span: expr.span,
-                    ty: StmtTy::Expr(expr),
+                    span: target.span,
+                    ty: StmtTy::Expr(target),
                 });
             }
         };
         Ok(Stmt {
             ty: statement_kind,
-            span: Span::new(prev.span.s, self.current_span.e, self.source_id),
+            span: Span::new(start.s, self.current_span.e, self.source_id),
         })
     }
 
+    fn assignable_ident(target: Expr) -> Failible<Ident> {
+        match target.ty {
+            ExprTy::Variable(id) => Ok(id),
+            _ => Err(Diagnostic::build(DiagnosticType::Syntax, target.span)
+                .annotation(
+                    Cow::Borrowed("expected a variable on the left hand side"),
+                    target.span,
+                )
+                .into()),
+        }
+    }
+
     fn ty(&mut self) -> Failible<LolTy> {
         if self.check(&TokenKind::Noob)? {
             return Ok(LolTy::Noob);
@@ -490,6 +740,7 @@ impl<'a> Parser<'a> {
             "NUMBR" => LolTy::Numbr,
             "NUMBAR" => LolTy::Numbar,
             "LIZT" => LolTy::Lizt,
+            "BUKKIT" => LolTy::Bukkit,
             s => {
                 return Err(Diagnostic::build(DiagnosticType::UnknownSymbol, id.1)
                     .annotation(Cow::Owned(format!("`{}` is not a TYPE", s)), id.1)
@@ -499,7 +750,7 @@ impl<'a> Parser<'a> {
     }
 
     fn check(&mut self, token: &TokenKind) -> Failible<bool> {
-        if self.peek_token()?.token_kind.eq(token) {
+        if self.peek_token()?.token_kind.same_kind(token) {
             self.next_token()?;
             Ok(true)
         } else {
@@ -508,7 +759,7 @@ impl<'a> Parser<'a> {
     }
 
     fn peek_eq(&mut self, token: &TokenKind) -> Failible<bool> {
-        if self.peek_token()?.token_kind.eq(token) {
+        if self.peek_token()?.token_kind.same_kind(token) {
             Ok(true)
         } else {
             Ok(false)
@@ -537,7 +788,7 @@ impl<'a> Parser<'a> {
 
     fn repeated(&mut self) -> Failible<Vec<Expr>> {
         let mut args = vec![self.expr()?];
-        while !self.peek_token()?.token_kind.eq(&TokenKind::Mkay) {
+        while !self.peek_token()?.token_kind.same_kind(&TokenKind::Mkay) {
             self.check(&TokenKind::An)?;
             args.push(self.expr()?);
         }
@@ -579,6 +830,43 @@ impl<'a> Parser<'a> {
             .into())
     }
 
+    fn call_args(&mut self) -> Failible<Vec<Expr>> {
+        let mut args = Vec::new();
+        if self.check(&TokenKind::Yr)? {
+            args.push(self.expr()?);
+            loop {
+                if self.check(&TokenKind::An)? {
+                    self.expect(TokenKind::Yr)?;
+                    args.push(self.expr()?);
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenKind::Mkay)?;
+        Ok(args)
+    }
+
+    /// Slot accesses (`'Z`) and method calls (`IZ`) following an object
+    fn postfix(&mut self, mut expr: Expr) -> Failible<Expr> {
+        while self.check(&TokenKind::SlotZ)? {
+            let slot = self.ident()?;
+            expr = Expr {
+                span: Span::new(expr.span.s, self.current_span.e, self.source_id),
+                ty: ExprTy::GetSlot(Box::new(expr), slot),
+            };
+        }
+        if self.check(&TokenKind::Iz)? {
+            let method = self.ident()?;
+            let args = self.call_args()?;
+            expr = Expr {
+                span: Span::new(expr.span.s, self.current_span.e, self.source_id),
+                ty: ExprTy::MethodCall(Box::new(expr), method, args),
+            };
+        }
+        Ok(expr)
+    }
+
     fn expr_inner(&mut self, prev: Option<Token>) -> Failible<Expr> {
         let to_match = match prev {
             Some(val) => val,
@@ -600,7 +888,18 @@ impl<'a> Parser<'a> {
                 let ty = self.ty()?;
                 ExprTy::Cast(Box::new(expr), ty)
             }
-            TokenKind::Ident(id) => ExprTy::Variable(Ident(id, to_match.span)),
+            TokenKind::Ident(id) => {
+                return self.postfix(Expr {
+                    ty: ExprTy::Variable(Ident(id, to_match.span)),
+                    span: to_match.span,
+                })
+            }
+            TokenKind::Me => {
+                return self.postfix(Expr {
+                    ty: ExprTy::Me,
+                    span: to_match.span,
+                })
+            }
             TokenKind::String(s) => ExprTy::String(s),
             TokenKind::InterpStr(s, interps) => ExprTy::InterpStr(s, interps),
             TokenKind::Win => ExprTy::Bool(true),
@@ -659,20 +958,7 @@ impl<'a> Parser<'a> {
             TokenKind::I => {
                 self.expect(TokenKind::Iz)?;
                 let name = self.ident()?;
-                let mut args = Vec::new();
-                if self.check(&TokenKind::Yr)? {
-                    args.push(self.expr()?);
-                    loop {
-                        if self.check(&TokenKind::An)? {
-                            self.expect(TokenKind::Yr)?;
-                            args.push(self.expr()?);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-                self.expect(TokenKind::Mkay)?;
-                ExprTy::FunctionCall(name, args)
+                ExprTy::FunctionCall(name, self.call_args()?)
             }
 
             TokenKind::All => {
@@ -769,6 +1055,24 @@ mod parse_test {
         };
     }
 
+    macro_rules! assert_err_count {
+        ($stream: expr, $count: expr, $name: ident) => {
+            #[test]
+            fn $name() {
+                let mut interner = Interner::default();
+                let lexer = Lexer::new($stream.chars(), 0, &mut interner);
+                let mut parser = Parser::new(lexer);
+                match parser.parse() {
+                    Ok(val) => panic!("Expected Err value, found {:?}", val),
+                    Err(e) => {
+                        let errors = e.into_inner();
+                        assert_eq!(errors.len(), $count, "{:#?}", errors);
+                    }
+                }
+            }
+        };
+    }
+
     macro_rules! assert_ast {
         ($stream: expr, $name: ident, [$($pat: pat,)*]) => {
             #[test]
@@ -1539,6 +1843,61 @@ KTHXBYE"#,
         [StmtTy::If(..),]
     );
 
+    assert_ast!(
+        r#"HAI 1.3
+O HAI IM animal
+    I HAS A name ITZ "animal"
+    HOW IZ I speak
+        VISIBLE ME'Z name
+    IF U SAY SO
+KTHX
+I HAS A cat ITZ LIEK A animal
+cat HAS A lives ITZ 9
+cat'Z name R "cat"
+HOW IZ cat purr
+    VISIBLE "purr"
+IF U SAY SO
+cat IZ speak MKAY
+KTHXBYE"#,
+        bukkit_full,
+        [
+            StmtTy::ObjectDef(_, None, _),
+            StmtTy::DecAssign(
+                _,
+                Some(Ok(Expr {
+                    ty: ExprTy::Bukkit(Some(_)),
+                    ..
+                }))
+            ),
+            StmtTy::SlotDec(_, _, Some(Ok(_))),
+            StmtTy::SlotAssign(..),
+            StmtTy::MethodDef(..),
+            StmtTy::Expr(Expr {
+                ty: ExprTy::MethodCall(..),
+                ..
+            }),
+        ]
+    );
+
+    assert_ast!(
+        "HAI 1.3, O HAI IM cat IM LIEK animal, KTHX, KTHXBYE",
+        bukkit_inherit_block,
+        [StmtTy::ObjectDef(_, Some(_), _),]
+    );
+
+    assert_ast!(
+        "HAI 1.3, VISIBLE a'Z b'Z c, KTHXBYE",
+        bukkit_slot_chain,
+        [StmtTy::Print(..),]
+    );
+
+    assert_err!(
+        "HAI 1.3, SUM OF 1 AN 2 R 3, KTHXBYE",
+        DiagnosticType::Syntax,
+        1,
+        assign_to_non_variable
+    );
+
     assert_err!(
         r#"HAI 1.4
 IM IN YR bLock UPPIN YR i TIL WIN
@@ -1549,6 +1908,31 @@ KTHXBYE"#,
         mismatched_blocks_loop
     );
 
+    assert_err!(
+        r#"HAI 1.4
+WTF ?
+    OMG 10
+    OMG SUM OF 1 AN 2
+OIC
+KTHXBYE"#,
+        DiagnosticType::Syntax,
+        1,
+        case_non_literal
+    );
+
+    assert_err!(
+        r#"HAI 1.4
+WTF ?
+    OMG "a"
+    OMG "b"
+    OMG "a"
+OIC
+KTHXBYE"#,
+        DiagnosticType::Syntax,
+        2,
+        case_duplicate
+    );
+
     assert_err!(
         "HAI 1.4, ANY OF MKAY, KTHXBYE",
         DiagnosticType::Syntax,
@@ -1569,4 +1953,68 @@ KTHXBYE"#,
         1,
         expected_expr_found_break
     );
+
+    assert_err_count!(
+        "HAI 1.4\nVISIBLE SUM OF 1\nI HAS A\nVISIBLE 1\nGIMMEH 3\nKTHXBYE",
+        3,
+        recover_after_statement
+    );
+
+    assert_err_count!(
+        "HAI 1.4, I HAS A x ITZ 1 AN 2, VISIBLE x, x R, KTHXBYE",
+        2,
+        recover_after_soft_break
+    );
+
+    assert_err_count!(
+        r#"HAI 1.4
+HOW IZ I test YR
+    VISIBLE SUM OF 1
+IF U SAY SO
+VISIBLE 1 AN
+KTHXBYE"#,
+        3,
+        recover_inside_block
+    );
+
+    assert_err_count!(
+        r#"HAI 1.4
+WIN, O RLY
+    YA RLY
+        VISIBLE 1
+    NO WAI
+        VISIBLE 2
+OIC
+KTHXBYE"#,
+        1,
+        recover_skips_rest_of_failed_block
+    );
+
+    assert_err_count!(
+        r#"HAI 1.4
+HOW IZ I test
+    WIN, O RLY
+        YA RLY
+            VISIBLE SUM OF 1
+    OIC
+    VISIBLE 1 AN
+    OIC
+IF U SAY SO
+KTHXBYE"#,
+        4,
+        recover_two_errors_after_failed_block
+    );
+
+    assert_err_count!(
+        r#"HAI 1.4
+IM IN YR outer
+    IM IN YR
+        VISIBLE SUM OF 1
+    IM OUTTA YR inner
+    VISIBLE 1 AN
+IM OUTTA YR outer
+KTHXBYE"#,
+        3,
+        recover_at_closer_of_failed_loop
+    );
 }
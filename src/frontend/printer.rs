@@ -0,0 +1,527 @@
+//! Prints a program back out as canonical LOLCODE, keeping its comments
+
+use std::borrow::Borrow;
+
+use crate::backend::interner::Interner;
+use crate::diagnostics::prelude::*;
+use crate::frontend::ast::*;
+use crate::frontend::lex::{Comment, Lexer};
+use crate::frontend::parse::Parser;
+
+const INDENT: &str = "    ";
+
+/// Formats a program, failing if it doesn't parse
+pub fn format(source: &str, id: usize) -> Failible<String> {
+    let mut interner = Interner::default();
+    let mut parser = Parser::new(Lexer::new(source.chars(), id, &mut interner));
+    let block = parser.parse()?;
+    let version = std::mem::take(&mut parser.version);
+    let comments = parser.comments();
+    std::mem::drop(parser);
+
+    let mut printer = Printer {
+        source: source.chars().collect(),
+        interner: &interner,
+        comments,
+        next_comment: 0,
+        out: String::with_capacity(source.len()),
+        indent: 0,
+        prev_end: None,
+    };
+    printer.program(&version, &block);
+    Ok(printer.out)
+}
+
+struct Printer<'a> {
+    source: Vec<char>,
+    interner: &'a Interner,
+    comments: Vec<Comment>,
+    /// The first comment that hasn't been printed yet
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// Where the last line printed in the current block ended in the source, to keep blank lines
+    /// between statements
+    prev_end: Option<usize>,
+}
+
+impl<'a> Printer<'a> {
+    fn in_comment(&self, pos: usize) -> bool {
+        self.comments
+            .iter()
+            .any(|c| c.span.s <= pos && pos < c.span.e)
+    }
+
+    fn is_word_char(&self, pos: usize) -> bool {
+        self.source
+            .get(pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+    }
+
+    /// Finds the last `keyword` before `pos`. Keywords like `OIC` aren't kept in the AST, but
+    /// comments must still be placed around them.
+    fn keyword_before(&self, keyword: &str, pos: usize) -> usize {
+        let keyword: Vec<char> = keyword.chars().collect();
+        let pos = pos.min(self.source.len());
+        (0..=pos.saturating_sub(keyword.len()))
+            .rev()
+            .find(|&i| {
+                self.source[i..].starts_with(&keyword)
+                    && (i == 0 || !self.is_word_char(i - 1))
+                    && !self.is_word_char(i + keyword.len())
+                    && !self.in_comment(i)
+            })
+            .unwrap_or(pos)
+    }
+
+    /// Whether there is code before `pos` on its line
+    fn after_code(&self, pos: usize) -> bool {
+        self.source[..pos]
+            .iter()
+            .rev()
+            .take_while(|c| **c != '\n')
+            .any(|c| !c.is_whitespace())
+    }
+
+    /// Whether there is an empty line in the source between `s` and `e`
+    fn blank_line_between(&self, s: usize, e: usize) -> bool {
+        let mut newlines = 0;
+        for c in self.source[s.min(e)..e].iter() {
+            match c {
+                '\n' => {
+                    newlines += 1;
+                    if newlines == 2 {
+                        return true;
+                    }
+                }
+                c if c.is_whitespace() => {}
+                _ => newlines = 0,
+            }
+        }
+        false
+    }
+
+    /// Starts a line for something at `s` in the source
+    fn open(&mut self, s: usize) {
+        if let Some(prev) = self.prev_end {
+            if self.blank_line_between(prev, s) {
+                self.out.push('\n');
+            }
+        }
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn comment_text(comment: &Comment) -> String {
+        if comment.multiline {
+            format!("OBTW{}TLDR", comment.text)
+        } else {
+            format!("BTW{}", comment.text.trim_end())
+        }
+    }
+
+    /// Prints every comment before `pos`. Comments after code stay at the end of its line.
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.s >= pos {
+                break;
+            }
+            let text = Self::comment_text(comment);
+            let span = comment.span;
+            self.next_comment += 1;
+
+            if self.after_code(span.s) && self.out.ends_with('\n') {
+                self.out.pop();
+                self.out.push(' ');
+            } else {
+                self.open(span.s);
+            }
+            self.out.push_str(&text);
+            self.out.push('\n');
+            self.prev_end = Some(span.e);
+        }
+    }
+
+    /// Prints a line of code from `s` to `e` in the source
+    fn line(&mut self, s: usize, e: usize, text: &str) {
+        self.comments_before(s);
+        self.open(s);
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.prev_end = Some(e);
+    }
+
+    /// Prints a keyword line that isn't in the AST, like `OIC`, found before `pos`
+    fn keyword_line(&mut self, keyword: &str, text: &str, pos: usize) -> usize {
+        let s = self.keyword_before(keyword, pos);
+        self.line(s, s + keyword.len(), text);
+        s
+    }
+
+    fn program(&mut self, version: &str, block: &Block) {
+        self.keyword_line("HAI", &format!("HAI {}", version), block.1.s);
+        self.prev_end = None;
+        let end = self.keyword_before("KTHXBYE", self.source.len());
+        self.stmts(&block.0);
+        self.comments_before(end);
+        self.prev_end = None;
+        self.keyword_line("KTHXBYE", "KTHXBYE", self.source.len());
+        self.comments_before(usize::MAX);
+    }
+
+    /// Prints an indented block, and the comments before `end` inside it
+    fn block(&mut self, block: &Block, end: usize) {
+        self.indent += 1;
+        self.prev_end = None;
+        self.stmts(&block.0);
+        self.comments_before(end);
+        self.prev_end = None;
+        self.indent -= 1;
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        let mut stmts = stmts.iter().peekable();
+        while let Some(stmt) = stmts.next() {
+            // A condition is written on the same line as the `O RLY?` or `WTF?` that uses it
+            if let (StmtTy::Expr(expr), Some(next)) = (&stmt.ty, stmts.peek()) {
+                let uncommented = !self
+                    .comments
+                    .iter()
+                    .any(|c| c.span.s >= stmt.span.e && c.span.e <= next.span.s);
+                if matches!(next.ty, StmtTy::If(..) | StmtTy::Case(..)) && uncommented {
+                    let prefix = format!("{}, ", self.expr(expr));
+                    self.stmt(next, stmt.span.s, &prefix);
+                    stmts.next();
+                    continue;
+                }
+            }
+            self.stmt(stmt, stmt.span.s, "");
+        }
+    }
+
+    fn args(&self, keyword: &str, args: &[Ident]) -> String {
+        let mut out = String::new();
+        for (i, arg) in args.iter().enumerate() {
+            out.push_str(if i == 0 { " YR " } else { " AN YR " });
+            out.push_str(self.name(arg));
+        }
+        format!("{}{}", keyword, out)
+    }
+
+    /// Prints a statement starting at `s`, after `prefix` on its first line
+    fn stmt(&mut self, stmt: &Stmt, s: usize, prefix: &str) {
+        let e = stmt.span.e;
+        match &stmt.ty {
+            StmtTy::If(ya_rly, mebbe, no_wai) => {
+                self.line(s, s, &format!("{}O RLY?", prefix));
+                let oic = self.keyword_before("OIC", e);
+                let mut sections = Vec::new();
+                if let Some(block) = ya_rly {
+                    sections.push(("YA", "YA RLY".to_string(), block.1.s, block));
+                }
+                for (expr, block) in mebbe {
+                    let text = format!("MEBBE {}", self.expr(expr));
+                    sections.push(("MEBBE", text, expr.span.s, block));
+                }
+                if let Some(block) = no_wai {
+                    sections.push(("NO", "NO WAI".to_string(), block.1.s, block));
+                }
+                self.sections(sections, oic);
+                self.keyword_line("OIC", "OIC", e);
+            }
+            StmtTy::Case(cases, omgwtf) => {
+                self.line(s, s, &format!("{}WTF?", prefix));
+                let oic = self.keyword_before("OIC", e);
+                let mut sections = Vec::new();
+                for (expr, block) in cases {
+                    let text = format!("OMG {}", self.expr(expr));
+                    sections.push(("OMG", text, expr.span.s, block));
+                }
+                if let Some(block) = omgwtf {
+                    sections.push(("OMGWTF", "OMGWTF".to_string(), block.1.s, block));
+                }
+                self.sections(sections, oic);
+                self.keyword_line("OIC", "OIC", e);
+            }
+            StmtTy::FunctionDef(name, args, block) => {
+                let keyword = format!("HOW IZ I {}", self.name(name));
+                self.line(s, block.1.s, &self.args(&keyword, args));
+                self.block(block, self.keyword_before("IF", e));
+                self.keyword_line("IF", "IF U SAY SO", e);
+            }
+            StmtTy::MethodDef(object, name, args, block) => {
+                let keyword = format!("HOW IZ {} {}", self.expr(object), self.name(name));
+                self.line(s, block.1.s, &self.args(&keyword, args));
+                self.block(block, self.keyword_before("IF", e));
+                self.keyword_line("IF", "IF U SAY SO", e);
+            }
+            StmtTy::Loop {
+                block_name,
+                fn_id,
+                block,
+            } => {
+                let mut header = format!("IM IN YR {}", self.name(block_name));
+                if let Some((func, var, cond)) = fn_id {
+                    header.push_str(&format!(" {} YR {}", self.name(func), self.name(var)));
+                    match cond {
+                        LoopCond::Till(expr) => {
+                            header.push_str(&format!(" TIL {}", self.expr(expr)))
+                        }
+                        LoopCond::While(expr) => {
+                            header.push_str(&format!(" WILE {}", self.expr(expr)))
+                        }
+                        LoopCond::Forever => {}
+                    }
+                }
+                self.line(s, block.1.s, &header);
+                let outta = self.keyword_before("IM", e);
+                self.block(block, outta);
+                let closer = format!("IM OUTTA YR {}", self.name(block_name));
+                self.keyword_line("IM", &closer, e);
+            }
+            StmtTy::ObjectDef(name, parent, block) => {
+                let mut header = format!("O HAI IM {}", self.name(name));
+                if let Some(parent) = parent {
+                    header.push_str(&format!(" IM LIEK {}", self.expr(parent)));
+                }
+                self.line(s, block.1.s, &header);
+                self.block(block, self.keyword_before("KTHX", e));
+                self.keyword_line("KTHX", "KTHX", e);
+            }
+            _ => {
+                let text = format!("{}{}", prefix, self.simple_stmt(&stmt.ty));
+                self.line(s, e, &text);
+            }
+        }
+    }
+
+    /// Prints the parts of an `O RLY?` or `WTF?`, as `(keyword, line, position, block)`. The
+    /// keyword is the last one before the position.
+    fn sections(&mut self, sections: Vec<(&str, String, usize, &Block)>, end: usize) {
+        let starts: Vec<usize> = sections
+            .iter()
+            .map(|(keyword, _, pos, _)| self.keyword_before(keyword, *pos))
+            .collect();
+        self.indent += 1;
+        for (i, (_, text, _, block)) in sections.iter().enumerate() {
+            self.line(starts[i], starts[i], text);
+            self.block(block, starts.get(i + 1).copied().unwrap_or(end));
+        }
+        self.indent -= 1;
+    }
+
+    fn declaration_value(&self, value: &Option<Result<Expr, LolTy>>) -> String {
+        match value {
+            None => String::new(),
+            Some(Err(ty)) => format!(" ITZ A {}", ty.name()),
+            Some(Ok(Expr {
+                ty: ExprTy::Bukkit(Some(parent)),
+                ..
+            })) => format!(" ITZ LIEK A {}", self.expr(parent)),
+            Some(Ok(expr)) => format!(" ITZ {}", self.expr(expr)),
+        }
+    }
+
+    fn simple_stmt(&self, stmt: &StmtTy) -> String {
+        match stmt {
+            StmtTy::Assignment(name, expr) => format!("{} R {}", self.name(name), self.expr(expr)),
+            StmtTy::DecAssign(name, value) => format!(
+                "I HAS A {}{}",
+                self.name(name),
+                self.declaration_value(value)
+            ),
+            StmtTy::Import(name) => format!("CAN I HAS {}?", self.name(name)),
+            StmtTy::Expr(expr) => self.expr(expr),
+            StmtTy::MutCast(name, ty) => format!("{} IS NOW A {}", self.name(name), ty.name()),
+            StmtTy::Break => "GTFO".to_string(),
+            StmtTy::Return(expr) => format!("FOUND YR {}", self.expr(expr)),
+            StmtTy::Print(args, no_newline) => format!(
+                "VISIBLE {}{}",
+                self.exprs(args, " "),
+                if *no_newline { "!" } else { "" }
+            ),
+            StmtTy::Input(name) => format!("GIMMEH {}", self.name(name)),
+            StmtTy::Append(source, item) => {
+                format!("PUT {} INTO {}", self.expr(item), self.expr(source))
+            }
+            StmtTy::SetItem(source, item, index) => format!(
+                "SHUV {} INTO {} OF {}",
+                self.expr(item),
+                self.index(index),
+                self.expr(source)
+            ),
+            StmtTy::SlotDec(object, slot, value) => format!(
+                "{} HAS A {}{}",
+                self.expr(object),
+                self.name(slot),
+                self.declaration_value(value)
+            ),
+            StmtTy::SlotAssign(object, slot, expr) => format!(
+                "{}'Z {} R {}",
+                self.expr(object),
+                self.name(slot),
+                self.expr(expr)
+            ),
+            StmtTy::If(..)
+            | StmtTy::Case(..)
+            | StmtTy::FunctionDef(..)
+            | StmtTy::MethodDef(..)
+            | StmtTy::Loop { .. }
+            | StmtTy::ObjectDef(..) => unreachable!("blocks are printed over several lines"),
+        }
+    }
+
+    fn name(&self, name: &Ident) -> &str {
+        self.interner.lookup(name.0)
+    }
+
+    /// The source of an expression, as written
+    fn source(&self, span: Span) -> String {
+        self.source[span.s..span.e.min(self.source.len())]
+            .iter()
+            .collect()
+    }
+
+    fn exprs(&self, exprs: &[Expr], separator: &str) -> String {
+        exprs
+            .iter()
+            .map(|e| self.expr(e))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    fn index<T: Borrow<Expr>>(&self, index: &Result<T, bool>) -> String {
+        match index {
+            Ok(expr) => self.expr(expr.borrow()),
+            Err(true) => "FRUNT".to_string(),
+            Err(false) => "BAK".to_string(),
+        }
+    }
+
+    fn call_args(&self, args: &[Expr]) -> String {
+        let mut out = String::new();
+        for (i, arg) in args.iter().enumerate() {
+            out.push_str(if i == 0 { "YR " } else { " AN YR " });
+            out.push_str(&self.expr(arg));
+        }
+        if !args.is_empty() {
+            out.push(' ');
+        }
+        out.push_str("MKAY");
+        out
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.ty {
+            // Literals keep their spelling, like escapes in strings and trailing zeros
+            ExprTy::String(_) | ExprTy::InterpStr(..) => self.source(expr.span),
+            ExprTy::Float(_) => self
+                .source(expr.span)
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect(),
+            ExprTy::Int(n) => n.to_string(),
+            ExprTy::Bool(true) => "WIN".to_string(),
+            ExprTy::Bool(false) => "FAIL".to_string(),
+            ExprTy::Null => "NOOB".to_string(),
+            ExprTy::It => "IT".to_string(),
+            ExprTy::Me => "ME".to_string(),
+            ExprTy::Variable(name) => self.name(name).to_string(),
+            ExprTy::List(items) => format!("CHAIN OF {} MKAY", self.exprs(items, " AN ")),
+            ExprTy::FunctionCall(name, args) => {
+                format!("I IZ {} {}", self.name(name), self.call_args(args))
+            }
+            ExprTy::Concat(args) => format!("SMOOSH {} MKAY", self.exprs(args, " AN ")),
+            ExprTy::Cast(expr, ty) => format!("MAEK {} A {}", self.expr(expr), ty.name()),
+            ExprTy::Operator(op, l, r) => {
+                let (l, r) = (self.expr(l), self.expr(r));
+                match op {
+                    OpTy::Add => format!("SUM OF {} AN {}", l, r),
+                    OpTy::Sub => format!("DIFF OF {} AN {}", l, r),
+                    OpTy::Mul => format!("PRODUKT OF {} AN {}", l, r),
+                    OpTy::Div => format!("QUOSHUNT OF {} AN {}", l, r),
+                    OpTy::Mod => format!("MOD OF {} AN {}", l, r),
+                    OpTy::Max => format!("BIGGR OF {} AN {}", l, r),
+                    OpTy::Min => format!("SMALLR OF {} AN {}", l, r),
+                    OpTy::And => format!("BOTH OF {} AN {}", l, r),
+                    OpTy::Or => format!("EITHER OF {} AN {}", l, r),
+                    OpTy::Equal => format!("BOTH SAEM {} AN {}", l, r),
+                    OpTy::NotEq => format!("DIFFRINT {} AN {}", l, r),
+                    OpTy::GT => format!("IZ {} GRETER THEN {}", l, r),
+                    OpTy::GTE => format!("IZ {} GRETER EQ THEN {}", l, r),
+                    OpTy::LT => format!("IZ {} LES THEN {}", l, r),
+                    OpTy::LTE => format!("IZ {} LES EQ THEN {}", l, r),
+                }
+            }
+            ExprTy::All(args) => format!("ALL OF {} MKAY", self.exprs(args, " AN ")),
+            ExprTy::Any(args) => format!("ANY OF {} MKAY", self.exprs(args, " AN ")),
+            ExprTy::UnaryOp(UnOpTy::Not, expr) => format!("NOT {}", self.expr(expr)),
+            ExprTy::UnaryOp(UnOpTy::Length, expr) => format!("LANGTH OF {}", self.expr(expr)),
+            ExprTy::GetItem(source, index) => {
+                format!("GRAB {} FRUM {}", self.index(index), self.expr(source))
+            }
+            ExprTy::GetSlot(object, slot) => format!("{}'Z {}", self.expr(object), self.name(slot)),
+            ExprTy::MethodCall(object, name, args) => format!(
+                "{} IZ {} {}",
+                self.expr(object),
+                self.name(name),
+                self.call_args(args)
+            ),
+            // Only made by the parser in declarations, which print it themselves
+            ExprTy::Bukkit(_) | ExprTy::Span(_) => self.source(expr.span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod printer_test {
+    use super::*;
+
+    fn assert_format(source: &str, expected: &str) {
+        let formatted = format(source, 0).expect("Failed to parse");
+        assert_eq!(formatted, expected);
+        // Formatting is stable
+        assert_eq!(format(&formatted, 0).expect("Failed to reparse"), expected);
+    }
+
+    #[test]
+    fn indents_blocks() {
+        assert_format(
+            "HAI 1.2\nHOW IZ I f YR a AN YR b\nBOTH SAEM a AN b, O RLY?\nYA RLY\nFOUND YR 1\nNO WAI\nGTFO\nOIC\nIF U SAY SO\nKTHXBYE\n",
+            "HAI 1.2\nHOW IZ I f YR a AN YR b\n    BOTH SAEM a AN b, O RLY?\n        YA RLY\n            FOUND YR 1\n        NO WAI\n            GTFO\n    OIC\nIF U SAY SO\nKTHXBYE\n",
+        );
+    }
+
+    #[test]
+    fn splits_statements() {
+        assert_format(
+            "HAI 1.2, I HAS A x ITZ...\n  12, VISIBLE   x  \"a:)\"!\nKTHXBYE",
+            "HAI 1.2\nI HAS A x ITZ 12\nVISIBLE x \"a:)\"!\nKTHXBYE\n",
+        );
+    }
+
+    #[test]
+    fn normalises_keywords() {
+        assert_format(
+            "HAI 1.2\nVISIBLE SMOOSH 1 2.50 MKAY\nBOTH OF WIN FAIL\nKTHXBYE\n",
+            "HAI 1.2\nVISIBLE SMOOSH 1 AN 2.50 MKAY\nBOTH OF WIN AN FAIL\nKTHXBYE\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments() {
+        assert_format(
+            "BTW header\nHAI 1.2 BTW version\n\n\nOBTW\nblock\nTLDR\nIM IN YR l BTW loop\n  VISIBLE 1 BTW one\n      BTW last\nIM OUTTA YR l\n\nKTHXBYE\nBTW trailer\n",
+            "BTW header\nHAI 1.2 BTW version\n\nOBTW\nblock\nTLDR\nIM IN YR l BTW loop\n    VISIBLE 1 BTW one\n    BTW last\nIM OUTTA YR l\nKTHXBYE\nBTW trailer\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_between_sections() {
+        assert_format(
+            "HAI 1.2\nx, WTF?\nOMG 1 BTW first\nVISIBLE 1\nBTW end of first\nOMGWTF\nVISIBLE 2\nOIC BTW done\nKTHXBYE\n",
+            "HAI 1.2\nx, WTF?\n    OMG 1 BTW first\n        VISIBLE 1\n        BTW end of first\n    OMGWTF\n        VISIBLE 2\nOIC BTW done\nKTHXBYE\n",
+        );
+    }
+}
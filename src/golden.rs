@@ -0,0 +1,419 @@
+//! `lulz test`, which runs golden tests. Each test is a `.lol` file starting with a header like
+//! `BTW { "input": "stdin", "output": "expected stdout" }`, or `BTW { "status": "error" }` for
+//! programs that must fail by reporting an error. Failing tests can also name the error they fail
+//! with, as in `"code": "E005"`, and any test can name text its diagnostics must contain, as in
+//! `"stderr": "in `f`, called here"`. A crash of lulz itself fails any test.
+//!
+//! Tests that are known to fail are listed in `KNOWN_FAILURES`, with why. They still run, and are
+//! reported as known failures unless they pass, which fails them until they are taken off the list.
+//!
+//! Tests run on the backend picked with `--backend`. With `--differential`, they run on both
+//! backends instead, which must agree on the status, output and diagnostics of every program.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use lulz::json::Json;
+
+use crate::color::Color;
+use crate::opts::{Backend, Opts};
+
+/// Tests that fail on both backends, by their path from the root of the repository
+const KNOWN_FAILURES: &[(&str, &str)] = &[
+    (
+        "tests/closures/not_poped_args.lol",
+        "uses :{var} interpolation",
+    ),
+    (
+        "tests/closures/not_poped_nested.lol",
+        "uses :{var} interpolation",
+    ),
+    ("tests/closures/reverse_access.lol", "uses CHAIN OF"),
+    ("tests/closures/reverse_access_rec.lol", "uses CHAIN OF"),
+    ("tests/exprs/casts/bool_convs.lol", "uses MAEK"),
+    ("tests/exprs/casts/float_str_to_int.lol", "uses MAEK"),
+    ("tests/exprs/casts/float_to_int.lol", "uses MAEK"),
+    ("tests/exprs/casts/float_to_string.lol", "uses MAEK"),
+    ("tests/exprs/casts/int_to_string.lol", "uses MAEK"),
+    ("tests/exprs/casts/string_to_float.lol", "uses MAEK"),
+    ("tests/exprs/casts/string_to_int.lol", "uses MAEK"),
+    ("tests/exprs/ops/bools/all.lol", "uses ALL OF"),
+    ("tests/exprs/ops/bools/any.lol", "uses ANY OF"),
+    ("tests/exprs/ops/bools/not.lol", "uses NOT"),
+    (
+        "tests/exprs/ops/bools/xor.lol",
+        "uses WON OF, which doesn't parse",
+    ),
+    (
+        "tests/exprs/ops/math/nerfin.lol",
+        "calls NERFIN, which only steps loops",
+    ),
+    (
+        "tests/exprs/ops/math/uppin.lol",
+        "calls UPPIN, which only steps loops",
+    ),
+    (
+        "tests/functions/basic_basic.lol",
+        "expects no newline after the output of VISIBLE",
+    ),
+    ("tests/functions/returns.lol", "uses SMOOSH"),
+    ("tests/it/basic_expr.lol", "uses IT as an expression"),
+    ("tests/lizt/accessing.lol", "uses CHAIN OF, PUT and GRAB"),
+    ("tests/lizt/frunt_back.lol", "uses CHAIN OF, PUT and SHUV"),
+    ("tests/lizt/init.lol", "uses CHAIN OF"),
+    ("tests/lizt/length.lol", "uses CHAIN OF, PUT and LANGTH OF"),
+    ("tests/lizt/list_literal.lol", "uses CHAIN OF and SHUV"),
+    ("tests/lizt/pass_by_ref.lol", "uses CHAIN OF and PUT"),
+    ("tests/lizt/push.lol", "uses CHAIN OF and PUT"),
+    ("tests/lizt/setters.lol", "uses CHAIN OF, PUT and SHUV"),
+    ("tests/perf/many_strings.lol", "uses SMOOSH"),
+    (
+        "tests/strings/interpolation.lol",
+        "uses :{var} interpolation",
+    ),
+    ("tests/strings/length.lol", "uses LANGTH OF"),
+    ("tests/variables/is_now_a.lol", "uses IS NOW A"),
+];
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    /// A test in `KNOWN_FAILURES` failed, as it is known to
+    KnownFailure(String),
+    /// The test has no header, so there's nothing to check
+    Skipped(String),
+}
+
+struct Expected {
+    input: String,
+    output: Option<String>,
+    error: bool,
+    code: Option<String>,
+    stderr: Option<String>,
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<_> = match fs::read_dir(path) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => return,
+        };
+        entries.sort();
+        for entry in entries {
+            collect(&entry, files);
+        }
+    } else if path.extension().is_some_and(|ext| ext == "lol") {
+        files.push(path.to_path_buf());
+    }
+}
+
+fn header(source: &str) -> Option<Expected> {
+    let first = source.lines().next()?.trim();
+    let header = Json::parse(first.strip_prefix("BTW")?)?;
+    let string = |key| header.get(key).and_then(Json::as_str).map(str::to_string);
+    Some(Expected {
+        input: string("input").unwrap_or_default(),
+        output: string("output"),
+        error: string("status").as_deref() == Some("error"),
+        code: string("code"),
+        stderr: string("stderr"),
+    })
+}
+
+/// Removes terminal colors, to search the diagnostics a test printed
+fn strip_colors(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Escapes newlines, so outputs fit on one line
+fn escape(s: &str) -> String {
+    s.replace('\n', "\\n")
+}
+
+/// Fails a test if lulz itself crashed, rather than reporting an error in the program
+fn crashed(output: &Output, stderr: &str) -> Option<Outcome> {
+    if output.status.code() == Some(101) || stderr.contains("panicked at") {
+        Some(Outcome::Failed(format!(
+            "lulz crashed ({}):\n{}",
+            output.status, stderr
+        )))
+    } else {
+        None
+    }
+}
+
+/// Runs a test program on `backend`, with `input` as its stdin
+fn run_lulz(exe: &Path, file: &Path, backend: Backend, input: &str) -> Result<Output, String> {
+    let mut child = Command::new(exe)
+        .args(["--backend", backend.name()])
+        .arg(file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to start lulz: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A program that doesn't read all of its input closes stdin early
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run lulz: {}", e))
+}
+
+fn run_test(exe: &Path, file: &Path, backend: Backend) -> Outcome {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => return Outcome::Failed(format!("failed to read the test: {}", e)),
+    };
+    let expected = match header(&source) {
+        Some(expected) => expected,
+        None => return Outcome::Skipped("no header".to_string()),
+    };
+
+    let output = match run_lulz(exe, file, backend, &expected.input) {
+        Ok(output) => output,
+        Err(e) => return Outcome::Failed(e),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = strip_colors(&String::from_utf8_lossy(&output.stderr));
+    if let Some(outcome) = crashed(&output, &stderr) {
+        return outcome;
+    }
+    if let Some(text) = expected
+        .stderr
+        .filter(|text| !stderr.contains(text.as_str()))
+    {
+        return Outcome::Failed(format!(
+            "expected `{}` in stderr, but saw:\n{}",
+            text, stderr
+        ));
+    }
+
+    if expected.error {
+        if output.status.success() {
+            return Outcome::Failed("expected an error, but the program succeeded".to_string());
+        }
+        if output.status.code() != Some(1) || !stderr.contains("error[E") {
+            return Outcome::Failed(format!(
+                "expected an error to be reported, but lulz failed ({}):\n{}",
+                output.status, stderr
+            ));
+        }
+        return match expected.code {
+            Some(code) if !stderr.contains(&format!("[{}", code)) => {
+                Outcome::Failed(format!("expected error `{}`, but saw:\n{}", code, stderr))
+            }
+            _ => Outcome::Passed,
+        };
+    }
+
+    if !output.status.success() {
+        return Outcome::Failed(format!(
+            "the program failed ({}):\n{}",
+            output.status, stderr
+        ));
+    }
+    match expected.output {
+        Some(out) if out != stdout => Outcome::Failed(format!(
+            "saw:\n    \"{}\"\nexpected:\n    \"{}\"\nstderr:\n{}",
+            escape(&stdout),
+            escape(&out),
+            stderr
+        )),
+        _ => Outcome::Passed,
+    }
+}
+
+/// Turns the outcome of a test in `KNOWN_FAILURES` into a known failure, or fails it if it passed,
+/// so the list doesn't go stale
+fn known_failure(file: &Path, outcome: Outcome) -> Outcome {
+    let why = match KNOWN_FAILURES.iter().find(|(path, _)| file.ends_with(path)) {
+        Some((_, why)) => why,
+        None => return outcome,
+    };
+    match outcome {
+        Outcome::Failed(_) => Outcome::KnownFailure(why.to_string()),
+        Outcome::Passed => {
+            Outcome::Failed("passed, so it should be taken off `KNOWN_FAILURES`".to_string())
+        }
+        outcome => outcome,
+    }
+}
+
+/// Runs a test program on both backends, and reports the first way they disagree. Tests without a
+/// header still run, with no input.
+fn compare_backends(exe: &Path, file: &Path) -> Outcome {
+    let expected = match fs::read_to_string(file) {
+        Ok(source) => header(&source),
+        Err(e) => return Outcome::Failed(format!("failed to read the test: {}", e)),
+    };
+    let input = expected.map(|expected| expected.input).unwrap_or_default();
+    let run = |backend| run_lulz(exe, file, backend, &input);
+    let (lua, interp) = match (run(Backend::Lua), run(Backend::Interp)) {
+        (Ok(lua), Ok(interp)) => (lua, interp),
+        (Err(e), _) | (_, Err(e)) => return Outcome::Failed(e),
+    };
+
+    let text = |bytes: &[u8]| strip_colors(&String::from_utf8_lossy(bytes));
+    for output in [&lua, &interp] {
+        if let Some(outcome) = crashed(output, &text(&output.stderr)) {
+            return outcome;
+        }
+    }
+    let differences = [
+        ("status", lua.status.to_string(), interp.status.to_string()),
+        ("stdout", text(&lua.stdout), text(&interp.stdout)),
+        ("stderr", text(&lua.stderr), text(&interp.stderr)),
+    ];
+    match differences.iter().find(|(_, lua, interp)| lua != interp) {
+        Some((what, lua, interp)) => Outcome::Failed(format!(
+            "the backends disagree on {}\nlua:\n    \"{}\"\ninterp:\n    \"{}\"",
+            what,
+            escape(lua),
+            escape(interp)
+        )),
+        None => Outcome::Passed,
+    }
+}
+
+/// Runs the tests in every input, and returns the exit code
+pub fn run(opts: Opts) -> i32 {
+    let mut files = Vec::new();
+    for input in opts.files.iter() {
+        collect(Path::new(input), &mut files);
+    }
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Failed to find the lulz executable: {}", e);
+            return 1;
+        }
+    };
+
+    let files = Arc::new(files);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (send, recv) = mpsc::channel();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    for _ in 0..workers.min(files.len()) {
+        let (files, next, send, exe) = (files.clone(), next.clone(), send.clone(), exe.clone());
+        let (backend, differential) = (opts.backend, opts.differential);
+        thread::spawn(move || loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            match files.get(i) {
+                Some(file) => {
+                    let outcome = if differential {
+                        compare_backends(&exe, file)
+                    } else {
+                        known_failure(file, run_test(&exe, file, backend))
+                    };
+                    if send.send((i, outcome)).is_err() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        });
+    }
+    std::mem::drop(send);
+
+    let (mut passed, mut failed, mut known, mut skipped) = (0, 0, 0, 0);
+    let mut failures = Vec::new();
+    for (i, outcome) in recv {
+        let name = files[i].display();
+        match outcome {
+            Outcome::Passed => {
+                passed += 1;
+                println!("{}... {}ok{}", name, Color::Green, Color::Reset);
+            }
+            Outcome::KnownFailure(why) => {
+                known += 1;
+                println!(
+                    "{}... {}known failure, {}{}",
+                    name,
+                    Color::Blue,
+                    why,
+                    Color::Reset
+                );
+            }
+            Outcome::Skipped(why) => {
+                skipped += 1;
+                println!(
+                    "{}... {}skipped, {}{}",
+                    name,
+                    Color::Blue,
+                    why,
+                    Color::Reset
+                );
+            }
+            Outcome::Failed(why) => {
+                failed += 1;
+                println!("{}... {}FAILED{}", name, Color::Red, Color::Reset);
+                failures.push((i, why));
+            }
+        }
+    }
+
+    failures.sort_by_key(|(i, _)| *i);
+    for (i, why) in failures.iter() {
+        println!("\n{}{}{}:", Color::Bold, files[*i].display(), Color::Reset);
+        for line in why.lines() {
+            println!("    {}", line);
+        }
+    }
+    println!(
+        "\n{} passed, {} failed, {} known failures, {} skipped",
+        passed, failed, known, skipped
+    );
+
+    if failed == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod golden_test {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let expected = header("BTW { \"input\": \"a\", \"output\": \"b\\n\" }\nHAI 1.2").unwrap();
+        assert_eq!(expected.input, "a");
+        assert_eq!(expected.output.as_deref(), Some("b\n"));
+        assert!(!expected.error);
+
+        let expected = header("BTW {\"status\": \"error\", \"code\": \"E005\"}").unwrap();
+        assert!(expected.error);
+        assert_eq!(expected.code.as_deref(), Some("E005"));
+        assert!(expected.stderr.is_none());
+
+        let expected = header("BTW {\"status\": \"error\", \"stderr\": \"in `f`\"}").unwrap();
+        assert_eq!(expected.stderr.as_deref(), Some("in `f`"));
+
+        assert!(header("HAI 1.2").is_none());
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            strip_colors("\x1b[1m\x1b[38;5;9merror[E003: syntax]\x1b[0m"),
+            "error[E003: syntax]"
+        );
+    }
+}
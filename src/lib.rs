@@ -0,0 +1,535 @@
+//! A fast and spec-compliant LOLCODE implementation, which translates LOLCODE to lua and runs it
+//! on LuaJIT. Besides the `lulz` binary, programs can be compiled and run from rust:
+//!
+//! ```no_run
+//! let mut session = lulz::Session::new(Vec::new());
+//! let program = session.compile("hai.lol", "HAI 1.2\nVISIBLE \"O HAI\"\nKTHXBYE")?;
+//! let mut out = Vec::new();
+//! program.run_with(&mut std::io::empty(), &mut out)?;
+//! assert_eq!(out, b"O HAI\n");
+//! # Ok::<(), lulz::diagnostics::Diagnostics>(())
+//! ```
+//!
+//! Programs can call rust functions registered with [`Session::register`], like funkshuns:
+//!
+//! ```no_run
+//! use lulz::runtime::host::LolValue;
+//!
+//! let mut session = lulz::Session::new(Vec::new());
+//! session.register("TWICE", |_, args| match args.first() {
+//!     Some(LolValue::Numbr(n)) => Ok(LolValue::Numbr(n * 2)),
+//!     _ => Ok(LolValue::Noob),
+//! });
+//! let program = session.compile("twice.lol", "HAI 1.2\nVISIBLE I IZ TWICE YR 21 MKAY\nKTHXBYE")?;
+//! program.run()?;
+//! # Ok::<(), lulz::diagnostics::Diagnostics>(())
+//! ```
+//!
+//! Running a program returns the lua state it ran in, where its funkshuns can be called from
+//! rust with [`Program::call`]:
+//!
+//! ```no_run
+//! use lulz::runtime::host::LolValue;
+//!
+//! let source = "HAI 1.2\nHOW IZ I twice YR n\nFOUND YR PRODUKT OF n AN 2\nIF U SAY SO\nKTHXBYE";
+//! let program = lulz::Session::new(Vec::new()).compile("twice.lol", source)?;
+//! let lua = program.run()?;
+//! let twice = program.call(&lua, "twice", vec![LolValue::Numbr(21)]);
+//! assert_eq!(twice.unwrap(), LolValue::Numbr(42));
+//! # Ok::<(), lulz::diagnostics::Diagnostics>(())
+//! ```
+//!
+//! A [`Session`] keeps the sources that the spans of diagnostics point into. [`Session::render`]
+//! shows diagnostics the way the compiler does, and [`Session::emit`] writes them to stderr in the
+//! session's `message_format`. Runtime errors are returned as diagnostics too, so a host can keep
+//! running programs after one fails. The options of a session also lint the programs it compiles,
+//! instrument them for coverage, or keep them for the interpreter, as the `lulz` binary does.
+
+pub mod backend;
+pub mod bundle;
+pub mod coverage;
+pub mod diagnostics;
+pub mod frontend;
+pub mod json;
+pub mod runtime;
+pub mod sourcemap;
+
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use mlua::{Lua, Value};
+
+use smallvec::SmallVec;
+
+use backend::interner::Interner;
+use backend::interp::Checked;
+use backend::lint::{self, Levels};
+use backend::translator::{SymbolTable, Translator};
+use coverage::Probe;
+use diagnostics::{Diagnostic, Diagnostics, Failible, MessageFormat, Severity};
+use frontend::{
+    ast::Block,
+    lex::{Comment, Lexer},
+    parse::Parser,
+};
+use runtime::builtins::{
+    io::{LUA_READ, LUA_WRITE},
+    register_modules,
+};
+use runtime::debug_info::DebugInfo;
+use runtime::errors::{emit_errors, lua_error, render_errors, CHUNK_NAME};
+use runtime::host::{self, HostFunctions, LolValue};
+use sourcemap::SourceMap;
+
+/// A program translated to lua
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub code: String,
+    /// Traces errors in `code` back to LOLCODE
+    pub debug_info: DebugInfo,
+    /// The host functions the program can call
+    pub host: HostFunctions,
+    /// The globals of the program, by their LOLCODE name
+    pub symbols: SymbolTable,
+    /// The lints the session checks for that the program raised as warnings
+    pub warnings: Vec<Diagnostic>,
+    /// What `code` counts when the session asks for coverage, for [`coverage::start`]
+    pub probes: Vec<Probe>,
+    /// The checked program for [`backend::interp::run`], kept when the session is asked to `interpret`
+    pub checked: Option<Checked>,
+}
+
+/// Compiles programs, and keeps the sources their diagnostics point into
+pub struct Session {
+    pub sources: SourceMap,
+    /// Directories `CAN I HAS` looks for modules in, after the one of the importing file
+    pub include: Vec<PathBuf>,
+    /// How [`Session::emit`] writes diagnostics
+    pub message_format: MessageFormat,
+    /// The lints checked before translating, at their levels, or `None` to not lint programs
+    pub lints: Option<Levels>,
+    /// Whether programs count the statements and branches they run
+    pub coverage: bool,
+    /// Whether programs keep what the interpreter needs to run them
+    pub interpret: bool,
+    host: HostFunctions,
+}
+
+impl Session {
+    pub fn new(include: Vec<PathBuf>) -> Self {
+        Session {
+            sources: SourceMap::new(),
+            include,
+            message_format: MessageFormat::Human,
+            lints: None,
+            coverage: false,
+            interpret: false,
+            host: HostFunctions::default(),
+        }
+    }
+
+    /// Lets programs compiled from now on call `f` as the funkshun `name`, with
+    /// `I IZ name YR ... MKAY`. Errors `f` returns stop the program at the call. Panics if `name`
+    /// isn't a LOLCODE identifier.
+    pub fn register<F>(&mut self, name: impl Into<String>, f: F)
+    where
+        F: for<'lua> Fn(&'lua Lua, Vec<LolValue<'lua>>) -> mlua::Result<LolValue<'lua>> + 'static,
+    {
+        self.host.add(name.into(), Rc::new(f));
+    }
+
+    /// Adds a source, and returns the file its spans refer to
+    pub fn add_source(&mut self, name: impl Into<String>, source: impl Into<String>) -> usize {
+        self.sources.add(name.into(), source.into())
+    }
+
+    /// Parses a source into an AST. Identifiers in it are looked up in the returned interner.
+    pub fn parse(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Failible<(Block, Interner)> {
+        let file = self.add_source(name, source);
+        let (ast, interner, _) = self.parse_file(file)?;
+        Ok((ast, interner))
+    }
+
+    /// Parses an added source, along with its comments
+    fn parse_file(&self, file: usize) -> Failible<(Block, Interner, Vec<Comment>)> {
+        let mut interner = Interner::default();
+        let lexer = Lexer::new(
+            self.sources.get(file).unwrap().source().chars(),
+            file,
+            &mut interner,
+        );
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse()?;
+        let comments = parser.comments();
+        Ok((ast, interner, comments))
+    }
+
+    /// Compiles a source to lua. `CAN I HAS` looks for modules next to the file `name`, then in
+    /// the `include` directories. The warnings of a program that fails to compile are returned
+    /// before its errors, which include the lints it denies.
+    pub fn compile(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Failible<Program> {
+        let file = self.add_source(name, source);
+        let (ast, interner, comments) = self.parse_file(file)?;
+        let lints = match &self.lints {
+            Some(levels) => {
+                let source = self.sources.get(file).unwrap().source();
+                lint::lint(&ast, &comments, source, &interner, levels)
+            }
+            None => Vec::new(),
+        };
+        let (mut errors, warnings): (Vec<_>, Vec<_>) = lints
+            .into_iter()
+            .partition(|lint| lint.severity == Severity::Error);
+
+        // Imported modules are added to the sources while translating
+        let sources = std::mem::replace(&mut self.sources, SourceMap::new());
+        let mut translator = Translator::new(interner, self.include.clone(), sources);
+        for name in self.host.names() {
+            translator.define_host(name);
+        }
+        if self.coverage {
+            translator.instrument();
+        }
+        // The interpreter runs the AST, once the translator has checked it
+        let interpreted = if self.interpret {
+            translator.keep_modules();
+            Some(ast.clone())
+        } else {
+            None
+        };
+        let res = translator.outer_block(ast);
+        self.sources = std::mem::replace(&mut translator.sources, SourceMap::new());
+        if let Err(es) = res {
+            errors.extend(es.into_inner());
+        }
+        if !errors.is_empty() {
+            let diagnostics: SmallVec<_> = warnings.into_iter().chain(errors).collect();
+            return Err(diagnostics.into());
+        }
+
+        Ok(Program {
+            debug_info: translator.debug_info(),
+            symbols: translator.symbol_table(),
+            probes: translator.probes().to_vec(),
+            checked: interpreted.map(|ast| Checked::new(ast, &translator)),
+            code: translator.code,
+            host: self.host.clone(),
+            warnings,
+        })
+    }
+
+    /// Renders diagnostics as plain text, with the sources they point into
+    pub fn render(&self, es: Diagnostics) -> String {
+        render_errors(es, &self.sources)
+    }
+
+    /// Writes diagnostics to stderr, in the message format of the session
+    pub fn emit(&self, es: Diagnostics) {
+        emit_errors(es, &self.sources, self.message_format)
+    }
+}
+
+impl Program {
+    /// Runs the program in a lua state that already has the builtins registered
+    pub fn exec(&self, lj: &Lua) -> mlua::Result<()> {
+        self.host.register(lj)?;
+        lj.load(&self.code).set_name(CHUNK_NAME)?.exec()
+    }
+
+    /// Calls the funkshun `name` of the program, which must have run in `lj`, and returns what it
+    /// `FOUND YR`
+    pub fn call<'lua>(
+        &self,
+        lj: &'lua Lua,
+        name: &str,
+        args: Vec<LolValue<'lua>>,
+    ) -> Result<LolValue<'lua>, CallError> {
+        let symbol = self
+            .symbols
+            .function(name)
+            .ok_or_else(|| CallError::NoSuchFunkshun(name.to_string()))?;
+        lj.globals()
+            .get(symbol)
+            .and_then(|f| host::call(lj, &f, args))
+            .map_err(|e| CallError::Failed(lua_error(e, &self.debug_info).into()))
+    }
+
+    /// Runs the program, with the process's stdin and stdout. Returns the lua state it ran in,
+    /// which its funkshuns can be called in with [`Program::call`].
+    pub fn run(&self) -> Failible<Lua> {
+        let lj = Lua::new();
+        register_modules(&lj);
+        self.exec(&lj).map_err(|e| lua_error(e, &self.debug_info))?;
+        Ok(lj)
+    }
+
+    /// Runs the program, with `GIMMEH` reading lines from `stdin`, and `VISIBLE` writing to
+    /// `stdout`. Returns the lua state it ran in like [`Program::run`], where they go back to the
+    /// process's stdin and stdout.
+    pub fn run_with(&self, stdin: &mut dyn BufRead, stdout: &mut dyn Write) -> Failible<Lua> {
+        let lj = Lua::new();
+        register_modules(&lj);
+        self.exec_with(&lj, stdin, stdout)
+            .map_err(|e| lua_error(e, &self.debug_info))?;
+        Ok(lj)
+    }
+
+    fn exec_with(
+        &self,
+        lj: &Lua,
+        stdin: &mut dyn BufRead,
+        stdout: &mut dyn Write,
+    ) -> mlua::Result<()> {
+        let globals = lj.globals();
+        let (write, read): (Value, Value) = (globals.get(LUA_WRITE)?, globals.get(LUA_READ)?);
+        let (stdin, stdout) = (RefCell::new(stdin), RefCell::new(stdout));
+        let res = lj.scope(|scope| {
+            let write = scope.create_function(|_, s: mlua::String| {
+                stdout
+                    .borrow_mut()
+                    .write_all(s.as_bytes())
+                    .map_err(mlua::Error::external)
+            })?;
+            let read = scope.create_function(|_, ()| {
+                let mut line = String::new();
+                if stdin
+                    .borrow_mut()
+                    .read_line(&mut line)
+                    .map_err(mlua::Error::external)?
+                    == 0
+                {
+                    return Ok(None);
+                }
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Some(line))
+            })?;
+            globals.set(LUA_WRITE, write)?;
+            globals.set(LUA_READ, read)?;
+            self.exec(lj)
+        });
+        // The functions of the scope can't be called once it has ended
+        globals.set(LUA_WRITE, write)?;
+        globals.set(LUA_READ, read)?;
+        res
+    }
+}
+
+/// Why [`Program::call`] failed
+#[derive(Debug)]
+pub enum CallError {
+    /// The program has no funkshun of this name
+    NoSuchFunkshun(String),
+    /// The funkshun raised a runtime error
+    Failed(Diagnostics),
+}
+
+#[cfg(test)]
+mod lib_test {
+    use super::*;
+    use backend::translator::SymbolKind;
+    use diagnostics::DiagnosticType;
+
+    /// Runs a program with `stdin`, and returns what it wrote
+    fn output(session: &mut Session, source: &str, stdin: &str) -> String {
+        let program = session.compile("test.lol", source).unwrap();
+        let mut out = Vec::new();
+        program.run_with(&mut stdin.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn runs() {
+        let source = "HAI 1.2\nI HAS A name\nGIMMEH name\nVISIBLE \"O HAI \" name\nKTHXBYE";
+        let mut session = Session::new(Vec::new());
+        assert_eq!(
+            output(&mut session, source, "CEILING CAT\r\n"),
+            "O HAI CEILING CAT\n"
+        );
+        // Input that ended reads as NOOB
+        let source = "HAI 1.2\nI HAS A name ITZ 1\nGIMMEH name\nVISIBLE name\nKTHXBYE";
+        assert_eq!(output(&mut session, source, ""), "NOOB\n");
+    }
+
+    #[test]
+    fn compile_errors() {
+        let mut session = Session::new(Vec::new());
+        let es = session
+            .compile("undefined.lol", "HAI 1.2\nGIMMEH name\nKTHXBYE")
+            .unwrap_err();
+        assert_eq!(es.clone().into_inner()[0].ty, DiagnosticType::UnknownSymbol);
+        // Sessions keep their own sources, so errors point into the right one
+        assert!(session.render(es).contains("undefined.lol"));
+        assert!(Session::new(Vec::new()).sources.get(1).is_err());
+    }
+
+    #[test]
+    fn lints() {
+        let source = "HAI 1.2\nI HAS A unused\nKTHXBYE";
+        let mut session = Session::new(Vec::new());
+        let program = session.compile("quiet.lol", source).unwrap();
+        assert!(program.warnings.is_empty());
+
+        session.lints = Some(Levels::default());
+        let program = session.compile("warns.lol", source).unwrap();
+        assert_eq!(program.warnings[0].ty, DiagnosticType::UnusedVariable);
+
+        // Denied lints fail along with other errors, after the warnings
+        session.lints = Some(Levels {
+            allow: Vec::new(),
+            deny: vec![DiagnosticType::UnusedVariable],
+        });
+        let source = "HAI 1.2\nI HAS A unused\n1\nVISIBLE x\nKTHXBYE";
+        let es = session.compile("denies.lol", source).unwrap_err();
+        let tys: Vec<_> = es.into_inner().iter().map(|e| e.ty).collect();
+        assert_eq!(
+            tys,
+            [
+                DiagnosticType::UnusedIt,
+                DiagnosticType::UnusedVariable,
+                DiagnosticType::UnknownSymbol
+            ]
+        );
+    }
+
+    #[test]
+    fn interpret() {
+        let source = "HAI 1.2\nVISIBLE \"O HAI\"\nKTHXBYE";
+        let mut session = Session::new(Vec::new());
+        assert!(session.compile("lua.lol", source).unwrap().checked.is_none());
+
+        session.interpret = true;
+        let program = session.compile("interp.lol", source).unwrap();
+        let mut out = Vec::new();
+        let checked = program.checked.unwrap();
+        backend::interp::run(&checked, &mut std::io::empty(), &mut out).unwrap();
+        assert_eq!(out, b"O HAI\n");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let source = "HAI 1.2\nVISIBLE \"before\"\nVISIBLE QUOSHUNT OF 7 AN 0\nKTHXBYE";
+        let mut session = Session::new(Vec::new());
+        let program = session.compile("div.lol", source).unwrap();
+        let mut out = Vec::new();
+        let es = program
+            .run_with(&mut std::io::empty(), &mut out)
+            .unwrap_err();
+        assert_eq!(out, b"before\n");
+
+        let diagnostic = &es.clone().into_inner()[0];
+        assert_eq!(diagnostic.ty, DiagnosticType::Runtime);
+        assert_eq!(diagnostic.annotations[0].message, "division by zero");
+        let statement = source.find("VISIBLE QUOSHUNT").unwrap();
+        assert_eq!(diagnostic.span.s, statement);
+        assert!(session.render(es).contains("div.lol"));
+    }
+
+    #[test]
+    fn host_functions() {
+        let source = "HAI 1.2\nVISIBLE I IZ SPAWN YR \"cat\" MKAY\nKTHXBYE";
+        let mut session = Session::new(Vec::new());
+        let es = session
+            .compile("spawn.lol", source)
+            .unwrap_err()
+            .into_inner();
+        assert_eq!(es[0].ty, DiagnosticType::UnknownSymbol);
+
+        session.register("SPAWN", |_, _| Ok(LolValue::Troof(true)));
+        assert_eq!(output(&mut session, source, ""), "WIN\n");
+        assert_eq!(session.host.names().collect::<Vec<_>>(), ["SPAWN"]);
+    }
+
+    #[test]
+    fn host_values() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let mut session = Session::new(Vec::new());
+        let log = received.clone();
+        session.register("TWICE", move |_, args| {
+            log.borrow_mut()
+                .extend(args.iter().map(|arg| format!("{:?}", arg)));
+            Ok(match args.into_iter().next() {
+                Some(LolValue::Numbr(n)) => LolValue::Numbr(n * 2),
+                Some(LolValue::Numbar(n)) => LolValue::Numbar(n * 2.0),
+                Some(LolValue::Yarn(s)) => LolValue::Yarn(s.repeat(2)),
+                Some(value) => value,
+                None => LolValue::Noob,
+            })
+        });
+        let source = "HAI 1.2\nVISIBLE I IZ TWICE YR 21 MKAY\n\
+                      VISIBLE I IZ TWICE YR 9007199254740993 MKAY\n\
+                      VISIBLE I IZ TWICE YR 1.25 MKAY\nVISIBLE I IZ TWICE YR \"cat\" MKAY\n\
+                      VISIBLE I IZ TWICE YR WIN MKAY\nKTHXBYE";
+        assert_eq!(
+            output(&mut session, source, ""),
+            "42\n18014398509481986\n2.5\ncatcat\nWIN\n"
+        );
+        assert_eq!(
+            *received.borrow(),
+            [
+                "Numbr(21)",
+                "Numbr(9007199254740993)",
+                "Numbar(1.25)",
+                "Yarn(\"cat\")",
+                "Troof(true)"
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not a LOLCODE identifier")]
+    fn host_names() {
+        Session::new(Vec::new()).register("VISIBLE", |_, _| Ok(LolValue::Noob));
+    }
+
+    #[test]
+    fn symbol_table() {
+        let source = "HAI 1.2\nI HAS A x ITZ 1\n\
+                      HOW IZ I f YR a\nFOUND YR a\nIF U SAY SO\nKTHXBYE";
+        let program = Session::new(Vec::new()).compile("f.lol", source).unwrap();
+        assert!(program.symbols.function("f").is_some());
+        assert!(program.symbols.function("x").is_none());
+        assert!(matches!(
+            program.symbols.get("x"),
+            Some((_, SymbolKind::Variable(None)))
+        ));
+        assert!(program.symbols.get("a").is_none());
+    }
+
+    #[test]
+    fn calls() {
+        let source = "HAI 1.2\nHOW IZ I uppin YR n\nFOUND YR SUM OF n AN 1\nIF U SAY SO\n\
+                      HOW IZ I fail\nFOUND YR QUOSHUNT OF 1 AN 0\nIF U SAY SO\nKTHXBYE";
+        let program = Session::new(Vec::new()).compile("f.lol", source).unwrap();
+        let lj = program
+            .run_with(&mut std::io::empty(), &mut Vec::new())
+            .unwrap();
+        let uppin = program.call(&lj, "uppin", vec![LolValue::Numbr(41)]);
+        assert_eq!(uppin.unwrap(), LolValue::Numbr(42));
+
+        assert!(matches!(
+            program.call(&lj, "meow", Vec::new()),
+            Err(CallError::NoSuchFunkshun(name)) if name == "meow"
+        ));
+        match program.call(&lj, "fail", Vec::new()) {
+            Err(CallError::Failed(es)) => {
+                assert_eq!(es.into_inner()[0].ty, DiagnosticType::Runtime)
+            }
+            res => panic!("expected a runtime error, got {:?}", res),
+        };
+    }
+}
@@ -0,0 +1,531 @@
+//! A language server, speaking the language server protocol over stdio
+
+use std::io::{self, BufReader};
+
+use hashbrown::HashMap;
+
+use lulz::backend::interner::Interner;
+use lulz::backend::lint::{self, Levels};
+use lulz::backend::translator::{Symbol, SymbolKind, Translator};
+use lulz::diagnostics::{Diagnostic, Severity, Span};
+use lulz::frontend::lex::{Lexer, KEYWORDS};
+use lulz::frontend::parse::Parser;
+use lulz::json::{read_message, write_message, Json};
+use lulz::sourcemap::SourceMap;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const FULL_SYNC: usize = 1;
+
+/// What is known about an open document after checking it
+struct Document {
+    /// The document, and the modules it imports
+    sources: SourceMap,
+    /// Id of the document in `sources`
+    file: usize,
+    symbols: Vec<Symbol>,
+    references: Vec<(Span, usize)>,
+    names: Vec<String>,
+    /// Whether the document parsed. If it didn't, the symbols are empty.
+    parsed: bool,
+}
+
+fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut out = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+            match u8::from_str_radix(&hex, 16) {
+                Ok(b) => out.push(b),
+                Err(_) => out.extend(format!("%{}", hex).bytes()),
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for c in path.chars() {
+        match c {
+            ' ' => uri.push_str("%20"),
+            '%' => uri.push_str("%25"),
+            '#' => uri.push_str("%23"),
+            '?' => uri.push_str("%3F"),
+            c => uri.push(c),
+        }
+    }
+    uri
+}
+
+/// Converts a character offset into a position, which counts UTF-16 code units
+fn position(text: &str, offset: usize) -> Json {
+    let (mut line, mut character) = (0usize, 0usize);
+    for c in text.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += c.len_utf16();
+        }
+    }
+    Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+/// Converts a position into a character offset
+fn offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_usize()?;
+    let character = position.get("character")?.as_usize()?;
+    let (mut current_line, mut current_character) = (0, 0);
+    for (i, c) in text.chars().enumerate() {
+        if current_line == line && (current_character >= character || c == '\n') {
+            return Some(i);
+        }
+        if c == '\n' {
+            current_line += 1;
+        } else if current_line == line {
+            current_character += c.len_utf16();
+        }
+    }
+    Some(text.chars().count())
+}
+
+fn location(span: Span, sources: &SourceMap) -> Json {
+    let file = sources.get(span.file).unwrap();
+    Json::object(vec![
+        ("uri", path_to_uri(file.name()).into()),
+        (
+            "range",
+            Json::object(vec![
+                ("start", position(file.source(), span.s)),
+                ("end", position(file.source(), span.e)),
+            ]),
+        ),
+    ])
+}
+
+fn lsp_diagnostic(diagnostic: Diagnostic, sources: &SourceMap) -> Json {
+    let description = diagnostic.ty.description();
+    let severity: usize = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut annotations = diagnostic.annotations.into_iter();
+    let mut message = annotations
+        .next()
+        .map(|a| a.message.into_owned())
+        .unwrap_or_else(|| description.to_string());
+    if let Some(note) = diagnostic.note {
+        message = format!("{}\nnote: {}", message, note);
+    }
+    let related = annotations
+        .map(|a| {
+            Json::object(vec![
+                ("location", location(a.span, sources)),
+                ("message", a.message.into_owned().into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    Json::object(vec![
+        (
+            "range",
+            location(diagnostic.span, sources)
+                .get("range")
+                .unwrap()
+                .clone(),
+        ),
+        ("severity", severity.into()),
+        ("code", diagnostic.ty.to_string().into()),
+        ("source", "lulz".into()),
+        ("message", message.into()),
+        ("relatedInformation", related.into()),
+    ])
+}
+
+impl Document {
+    /// Checks a document, returning what was learnt and its diagnostics
+    fn check(path: String, text: String) -> (Self, Vec<Diagnostic>) {
+        let mut sources = SourceMap::new();
+        let file = sources.add(path, text.clone());
+
+        let mut interner = Interner::default();
+        let mut parser = Parser::new(Lexer::new(text.chars(), file, &mut interner));
+        let ast = match parser.parse() {
+            Ok(ast) => ast,
+            Err(es) => {
+                let doc = Document {
+                    sources,
+                    file,
+                    symbols: Vec::new(),
+                    references: Vec::new(),
+                    names: Vec::new(),
+                    parsed: false,
+                };
+                return (doc, es.into_inner().into_vec());
+            }
+        };
+        let comments = parser.comments();
+        let mut diagnostics = lint::lint(&ast, &comments, &text, &interner, &Levels::default());
+
+        let mut translator = Translator::new(interner, Vec::new(), sources);
+        if let Err(es) = translator.outer_block(ast) {
+            diagnostics.extend(es.into_inner());
+        }
+        let doc = Document {
+            names: translator.debug_info().names,
+            sources: translator.sources,
+            file,
+            symbols: translator.symbols,
+            references: translator.references,
+            parsed: true,
+        };
+        (doc, diagnostics)
+    }
+
+    fn text(&self) -> &str {
+        self.sources.get(self.file).unwrap().source()
+    }
+
+    fn name(&self, symbol: &Symbol) -> &str {
+        &self.names[symbol.name.0.inner()]
+    }
+
+    /// The symbol declared or used at an offset in this document
+    fn symbol_at(&self, offset: usize) -> Option<usize> {
+        let contains = |span: &Span| span.file == self.file && span.s <= offset && offset <= span.e;
+        self.references
+            .iter()
+            .find(|(span, _)| contains(span))
+            .map(|(_, symbol)| *symbol)
+            .or_else(|| self.symbols.iter().position(|s| contains(&s.name.1)))
+    }
+
+    fn hover(&self, symbol: &Symbol) -> String {
+        let name = self.name(symbol);
+        let code = match &symbol.kind {
+            SymbolKind::Variable(Some(ty)) => format!("I HAS A {} ITZ A {}", name, ty.name()),
+            SymbolKind::Variable(None) => format!("I HAS A {}", name),
+            SymbolKind::Function(args) => {
+                let mut code = format!("HOW IZ I {}", name);
+                for (i, arg) in args.iter().enumerate() {
+                    code.push_str(if i == 0 { " YR " } else { " AN YR " });
+                    code.push_str(&self.names[arg.0.inner()]);
+                }
+                code
+            }
+            SymbolKind::Host => format!("HOW IZ I {} BTW host function", name),
+        };
+        format!("```lolcode\n{}\n```", code)
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn publish(&mut self, uri: &str, text: String) -> Json {
+        let (doc, diagnostics) = Document::check(uri_to_path(uri), text);
+        let diagnostics = diagnostics
+            .into_iter()
+            // Errors inside imported modules belong to those files
+            .filter(|d| d.span.file == doc.file)
+            .map(|d| lsp_diagnostic(d, &doc.sources))
+            .collect::<Vec<_>>();
+        // While the document is being edited, keep the symbols from the last time it parsed
+        if doc.parsed || !self.documents.contains_key(uri) {
+            self.documents.insert(uri.to_string(), doc);
+        }
+        notification(
+            "textDocument/publishDiagnostics",
+            Json::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ]),
+        )
+    }
+
+    /// The document and offset a text document position request is about
+    fn lookup(&self, params: &Json) -> Option<(&Document, usize)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let doc = self.documents.get(uri)?;
+        let offset = offset(doc.text(), params.get("position")?)?;
+        Some((doc, offset))
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (doc, offset) = self.lookup(params)?;
+        let symbol = doc.symbol_at(offset)?;
+        Some(location(doc.symbols[symbol].name.1, &doc.sources))
+    }
+
+    fn references(&self, params: &Json) -> Option<Json> {
+        let (doc, offset) = self.lookup(params)?;
+        let symbol = doc.symbol_at(offset)?;
+        let with_declaration = params
+            .get("context")
+            .and_then(|c| c.get("includeDeclaration"))
+            .and_then(Json::as_bool)
+            .unwrap_or(true);
+        let declaration = Some(doc.symbols[symbol].name.1).filter(|_| with_declaration);
+        let uses = doc
+            .references
+            .iter()
+            .filter(|(_, s)| *s == symbol)
+            .map(|(span, _)| *span);
+        Some(
+            declaration
+                .into_iter()
+                .chain(uses)
+                .map(|span| location(span, &doc.sources))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (doc, offset) = self.lookup(params)?;
+        let symbol = &doc.symbols[doc.symbol_at(offset)?];
+        Some(Json::object(vec![(
+            "contents",
+            Json::object(vec![
+                ("kind", "markdown".into()),
+                ("value", doc.hover(symbol).into()),
+            ]),
+        )]))
+    }
+
+    fn completion(&self, params: &Json) -> Option<Json> {
+        const KEYWORD: usize = 14;
+        const FUNCTION: usize = 3;
+        const VARIABLE: usize = 6;
+
+        let mut items: Vec<Json> = KEYWORDS
+            .iter()
+            .map(|(keyword, _)| {
+                Json::object(vec![("label", (*keyword).into()), ("kind", KEYWORD.into())])
+            })
+            .collect();
+        if let Some((doc, _)) = self.lookup(params) {
+            let mut seen = hashbrown::HashSet::new();
+            for symbol in doc.symbols.iter() {
+                let name = doc.name(symbol);
+                if seen.insert(name) {
+                    let kind = match symbol.kind {
+                        SymbolKind::Function(_) | SymbolKind::Host => FUNCTION,
+                        SymbolKind::Variable(_) => VARIABLE,
+                    };
+                    items.push(Json::object(vec![
+                        ("label", name.into()),
+                        ("kind", kind.into()),
+                    ]));
+                }
+            }
+        }
+        Some(items.into())
+    }
+
+    /// Handles a message, returning the messages to send back
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let result = match method {
+            "initialize" => Some(Json::object(vec![(
+                "capabilities",
+                Json::object(vec![
+                    ("textDocumentSync", FULL_SYNC.into()),
+                    ("definitionProvider", true.into()),
+                    ("referencesProvider", true.into()),
+                    ("hoverProvider", true.into()),
+                    (
+                        "completionProvider",
+                        Json::object(Vec::<(&str, Json)>::new()),
+                    ),
+                ]),
+            )])),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/didOpen" => {
+                let doc = params.get("textDocument");
+                let uri = doc.and_then(|d| d.get("uri")).and_then(Json::as_str);
+                let text = doc.and_then(|d| d.get("text")).and_then(Json::as_str);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => vec![self.publish(uri, text.to_string())],
+                    _ => Vec::new(),
+                };
+            }
+            "textDocument/didChange" => {
+                let uri = params
+                    .get("textDocument")
+                    .and_then(|d| d.get("uri"))
+                    .and_then(Json::as_str);
+                // With full sync, the last change is the whole document
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => vec![self.publish(uri, text.to_string())],
+                    _ => Vec::new(),
+                };
+            }
+            "textDocument/didClose" => {
+                let uri = params
+                    .get("textDocument")
+                    .and_then(|d| d.get("uri"))
+                    .and_then(Json::as_str);
+                return match uri {
+                    Some(uri) => {
+                        self.documents.remove(uri);
+                        vec![notification(
+                            "textDocument/publishDiagnostics",
+                            Json::object(vec![
+                                ("uri", uri.into()),
+                                ("diagnostics", Json::Array(Vec::new())),
+                            ]),
+                        )]
+                    }
+                    None => Vec::new(),
+                };
+            }
+            "textDocument/definition" => Some(self.definition(params).unwrap_or(Json::Null)),
+            "textDocument/references" => Some(self.references(params).unwrap_or(Json::Null)),
+            "textDocument/hover" => Some(self.hover(params).unwrap_or(Json::Null)),
+            "textDocument/completion" => Some(self.completion(params).unwrap_or(Json::Null)),
+            _ => None,
+        };
+
+        // Notifications have no id, and get no response
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return Vec::new(),
+        };
+        vec![match result {
+            Some(result) => Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ]),
+            None => Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                (
+                    "error",
+                    Json::object(vec![
+                        ("code", METHOD_NOT_FOUND.into()),
+                        ("message", format!("unknown method `{}`", method).into()),
+                    ]),
+                ),
+            ]),
+        }]
+    }
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+/// Serves requests until the client exits, and returns the exit code
+pub fn run() -> i32 {
+    let mut server = Server {
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+    let stdin = io::stdin();
+    let mut input = BufReader::new(stdin.lock());
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    while let Ok(Some(message)) = read_message(&mut input) {
+        if message.get("method").and_then(Json::as_str) == Some("exit") {
+            break;
+        }
+        for reply in server.handle(&message) {
+            if write_message(&mut output, &reply).is_err() {
+                return 1;
+            }
+        }
+    }
+
+    if server.shutdown {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod lsp_test {
+    use super::*;
+    use lulz::diagnostics::DiagnosticType;
+
+    fn pos(line: usize, character: usize) -> Json {
+        Json::object(vec![("line", line.into()), ("character", character.into())])
+    }
+
+    #[test]
+    fn positions() {
+        let text = "HAI 1.4\nVISIBLE \"😀\" AN x\nKTHXBYE";
+        assert_eq!(position(text, 0), pos(0, 0));
+        assert_eq!(position(text, 9), pos(1, 1));
+        // The emoji is one character, but two UTF-16 code units
+        assert_eq!(position(text, 19), pos(1, 12));
+        assert_eq!(offset(text, &pos(1, 12)), Some(19));
+        assert_eq!(offset(text, &pos(0, 100)), Some(7));
+        assert_eq!(offset(text, &pos(2, 3)), Some(text.chars().count() - 4));
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(uri_to_path("file:///tmp/my%20file.lol"), "/tmp/my file.lol");
+        assert_eq!(path_to_uri("/tmp/my file.lol"), "file:///tmp/my%20file.lol");
+    }
+
+    #[test]
+    fn keeps_symbols_until_parsed() {
+        let mut server = Server {
+            documents: HashMap::new(),
+            shutdown: false,
+        };
+        let uri = "file:///tmp/edited.lol";
+        server.publish(
+            uri,
+            "HAI 1.4\nI HAS A x ITZ 1\nVISIBLE x\nKTHXBYE".to_string(),
+        );
+        server.publish(
+            uri,
+            "HAI 1.4\nI HAS A x ITZ 1\nVISIBLE x AN\nKTHXBYE".to_string(),
+        );
+        let doc = &server.documents[uri];
+        assert!(doc.parsed);
+        assert_eq!(doc.name(&doc.symbols[0]), "x");
+        assert_eq!(doc.references.len(), 1);
+
+        server.publish(uri, "HAI 1.4\nI HAS A y\nKTHXBYE".to_string());
+        let doc = &server.documents[uri];
+        assert_eq!(doc.name(&doc.symbols[0]), "y");
+    }
+
+    #[test]
+    fn reports_unsupported() {
+        let (_, diagnostics) = Document::check(
+            "unsupported.lol".to_string(),
+            "HAI 1.4\nVISIBLE SMOOSH 1 AN 2 MKAY\nKTHXBYE".to_string(),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].ty, DiagnosticType::Unsupported);
+    }
+}
@@ -1,19 +1,23 @@
-mod backend;
 mod color;
-mod diagnostics;
+mod debugger;
 mod err;
-mod frontend;
+mod fmt;
+mod golden;
+mod lsp;
 mod opts;
-mod runtime;
-mod sourcemap;
+mod profiler;
+mod repl;
 
-use crate::backend::interner::Interner;
-use crate::diagnostics::Failible;
-use crate::runtime::builtins::register_modules;
-use crate::sourcemap::SOURCEMAP;
-use backend::translator::Translator;
-use frontend::*;
-use runtime::errors::raise_errors;
+use lulz::backend::interp;
+use lulz::bundle::Bundle;
+use lulz::coverage;
+use lulz::diagnostics::{Diagnostics, Failible, MessageFormat};
+use lulz::runtime::builtins::register_modules;
+use lulz::runtime::errors::{emit_errors, lua_error};
+use lulz::runtime::sandbox::{self, Limits};
+use lulz::sourcemap::SourceMap;
+use lulz::Session;
+use smallvec::SmallVec;
 use std::{
     borrow::Cow,
     fs::{read_to_string, File},
@@ -23,6 +27,13 @@ use std::{
 use mlua::Lua;
 
 fn main() {
+    if let Some(program) = Bundle::embedded() {
+        if let Err(es) = program.run() {
+            raise_errors(es, &program.sources(), MessageFormat::Human)
+        }
+        return;
+    }
+
     let mut opts = err::report(
         opts::parse().map_err(|e| {
             eprint!("{}", opts::HELP);
@@ -30,6 +41,14 @@ fn main() {
         }),
         Cow::Borrowed("Failed to parse arguments"),
     );
+    match opts.command {
+        opts::Command::Repl => return repl::run(opts),
+        opts::Command::Lsp => std::process::exit(lsp::run()),
+        opts::Command::Debug => std::process::exit(debugger::run(opts)),
+        opts::Command::Fmt => std::process::exit(fmt::run(opts)),
+        opts::Command::Test => std::process::exit(golden::run(opts)),
+        opts::Command::Run => {}
+    }
     let source: String = if &opts.input == "-" {
         let mut buffer = String::new();
         let stdin = io::stdin();
@@ -46,31 +65,34 @@ fn main() {
             Cow::Owned(format!("Failed to read file `{}`", opts.input)),
         )
     };
-    let id = SOURCEMAP
-        .write()
-        .unwrap()
-        .add(std::mem::take(&mut opts.input), source);
-    match pipeline(id, opts) {
-        Ok(()) => {}
-        Err(es) => {
-            raise_errors(es)
-        }
-    };
+    let mut session = Session::new(std::mem::take(&mut opts.include));
+    session.message_format = opts.message_format;
+    session.lints = Some(std::mem::take(&mut opts.lints));
+    session.coverage = opts.coverage.is_some();
+    session.interpret = opts.backend == opts::Backend::Interp;
+    let name = std::mem::take(&mut opts.input);
+    if let Err(es) = pipeline(&mut session, name, source, opts) {
+        session.emit(es);
+        std::process::exit(1)
+    }
 }
 
-fn pipeline(id: usize, opts: opts::Opts) -> Failible<()> {
-    let mut interner = Interner::default();
-    let guard = SOURCEMAP.read().unwrap();
-    let lexer = lex::Lexer::new(guard.get(id).unwrap().source().chars(), id, &mut interner);
-    let mut parser = parse::Parser::new(lexer);
-    let ast = parser.parse()?;
-    std::mem::drop(guard);
+/// Writes diagnostics to stderr, and fails
+fn raise_errors(es: Diagnostics, sources: &SourceMap, format: MessageFormat) -> ! {
+    emit_errors(es, sources, format);
+    std::process::exit(1)
+}
 
-    let mut translator = Translator::new(interner);
-    translator.outer_block(ast)?;
+fn pipeline(session: &mut Session, name: String, source: String, opts: opts::Opts) -> Failible<()> {
+    let program = session.compile(name, source)?;
+    // Warnings are shown right away, and denied lints fail along with any other errors
+    if !program.warnings.is_empty() {
+        session.emit(SmallVec::from_vec(program.warnings.clone()).into());
+    }
+    let sources = &session.sources;
 
     if opts.debug {
-        eprintln!("{}", translator.code);
+        eprintln!("{}", program.code);
     }
 
     if let Some(filename) = opts.dump_lua {
@@ -79,16 +101,81 @@ fn pipeline(id: usize, opts: opts::Opts) -> Failible<()> {
             Cow::Owned(format!("Failed to open file `{}`", filename)),
         );
         err::report(
-            file.write(translator.code.as_bytes()),
+            file.write(program.code.as_bytes()),
             Cow::Owned(format!("Failed to write to file `{}`", filename)),
         );
     }
 
+    if let Some(output) = opts.output {
+        let bundle = Bundle::new(&program.code, program.debug_info.clone(), sources)?;
+        err::report(
+            bundle.write_executable(&output),
+            Cow::Owned(format!("Failed to write executable `{}`", output.display())),
+        );
+        return Ok(());
+    }
+
+    if let Some(checked) = &program.checked {
+        return run_interp(checked);
+    }
+
+    let info = &program.debug_info;
+    let profile = opts.profile || opts.profile_folded.is_some();
     let lj = Lua::new();
     register_modules(&lj);
-    lj.load(&translator.code)
-        .exec()
-        .expect("Generated lua code should not crash");
+    if profile {
+        profiler::start(&lj).map_err(|e| lua_error(e, info))?;
+    }
+    if opts.coverage.is_some() {
+        coverage::start(&lj, &program.probes).map_err(|e| lua_error(e, info))?;
+    }
+    let limits = if opts.sandbox {
+        opts.limits.or(Limits::SANDBOX)
+    } else {
+        opts.limits
+    };
+    sandbox::limit(&lj, limits).map_err(|e| lua_error(e, info))?;
+    if opts.sandbox {
+        sandbox::restrict(&lj).map_err(|e| lua_error(e, info))?;
+    }
+    let result = program.exec(&lj);
+
+    // Programs that fail still get a profile and coverage of what ran before the error
+    if profile {
+        let profile = profiler::stop(&lj, info, sources).map_err(|e| lua_error(e, info))?;
+        eprint!("{}", profile.report(sources));
+        if let Some(filename) = opts.profile_folded {
+            err::report(
+                std::fs::write(&filename, profile.folded()),
+                Cow::Owned(format!("Failed to write to file `{}`", filename)),
+            );
+        }
+    }
+    if let Some(filename) = opts.coverage {
+        let counts = coverage::stop(&lj).map_err(|e| lua_error(e, info))?;
+        err::report(
+            std::fs::write(&filename, coverage::lcov(&program.probes, &counts, sources)),
+            Cow::Owned(format!("Failed to write to file `{}`", filename)),
+        );
+    }
+    result.map_err(|e| lua_error(e, info))?;
 
     Ok(())
 }
+
+/// Runs a program on the interpreter, on a thread with the stack its calls need
+fn run_interp(program: &interp::Checked) -> Failible<()> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(interp::STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let stdin = io::stdin();
+                let stdout = io::stdout();
+                let mut stdout = io::BufWriter::new(stdout.lock());
+                interp::run(program, &mut stdin.lock(), &mut stdout)
+            })
+            .expect("Failed to start the interpreter")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
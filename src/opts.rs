@@ -1,4 +1,8 @@
-use std::{ffi::OsString, fmt::Write};
+use std::{ffi::OsString, fmt::Write, path::PathBuf, time::Duration};
+
+use lulz::backend::lint::Levels;
+use lulz::diagnostics::{DiagnosticType, MessageFormat, LINTS};
+use lulz::runtime::sandbox::Limits;
 
 pub const HELP: &str = "\
 lulz 0.1.0
@@ -6,24 +10,124 @@ Jonathan Li
 
 USAGE:
     lulz [FLAGS] [OPTIONS] <input>
+    lulz repl [OPTIONS]
+    lulz lsp
+    lulz debug [OPTIONS]
+    lulz fmt [--check] <input>...
+    lulz test [--backend <backend> | --differential] <input>...
 
 ARGS:
     <input>    Input file to compile. Use `-` to read from stdin
 
+SUBCOMMANDS:
+    repl       Start an interactive session
+    lsp        Start a language server over stdio
+    debug      Start a debug adapter over stdio
+    fmt        Rewrite files in the canonical style. Use `-` to format stdin to stdout
+    test       Run the golden tests in files and directories
+
 FLAGS:
     -h, --help          Prints help information
     -V, --version       Prints version information
 
 OPTIONS:
-    -o, --output <file>                Output file [default: lol.out]
+    -o, --output <file>                Write a standalone executable instead of running
+    --check                            With `fmt`, fail instead of rewriting unformatted files
+    --backend <backend>                Run on LuaJIT (`lua`) or the interpreter (`interp`)
+    --differential                     With `test`, run tests on both backends and compare them
+    -I, --include <dir>...             Add a directory to search for `CAN I HAS` modules
+    -A, --allow <lint>...              Turn off a lint
+    -D, --deny <lint>...               Make a lint an error
+    --message-format <format>          Write diagnostics as `human` text or `json` lines
+    --profile                          Report the lines and funkshuns the program spends its time in
+    --profile-folded <file>            Also write folded stacks for flamegraph tools to a file
+    --sandbox                          Run without access to the system, and with the limits below
+    --max-instructions <n>             Stop the program after it runs `n` lua instructions
+    --max-memory <MB>                  Stop the program once it uses more memory
+    --timeout <seconds>                Stop the program once it runs for longer
+    --coverage <file>                  Write the statements and branches that ran to a file, as lcov
     --dump-lua <file>                  Dump generated lua code a specified file
     -d, --debug                        Turn debug mode on (for development)
+
+LINTS:
+    unused_variable, unused_parameter, unreachable, missing_ya_rly, shadowing, unused_it
 ";
 
+fn lint(name: &str) -> Result<DiagnosticType, String> {
+    DiagnosticType::lint(name).ok_or_else(|| {
+        let mut msg = format!("unknown lint `{}`, expected one of:", name);
+        for lint in LINTS {
+            let _ = write!(msg, " {}", lint.name());
+        }
+        msg
+    })
+}
+
+fn message_format(format: &str) -> Result<MessageFormat, String> {
+    match format {
+        "human" => Ok(MessageFormat::Human),
+        "json" => Ok(MessageFormat::Json),
+        _ => Err(format!(
+            "unknown message format `{}`, expected `human` or `json`",
+            format
+        )),
+    }
+}
+
+fn backend(name: &str) -> Result<Backend, String> {
+    match name {
+        "lua" => Ok(Backend::Lua),
+        "interp" => Ok(Backend::Interp),
+        _ => Err(format!(
+            "unknown backend `{}`, expected `lua` or `interp`",
+            name
+        )),
+    }
+}
+
+fn megabytes(mb: &str) -> Result<usize, String> {
+    mb.parse::<usize>()
+        .ok()
+        .and_then(|mb| mb.checked_mul(1 << 20))
+        .ok_or_else(|| format!("`{}` is not a number of megabytes", mb))
+}
+
+fn seconds(secs: &str) -> Result<Duration, String> {
+    secs.parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("`{}` is not a number of seconds", secs))
+}
+
 pub fn parse() -> Result<Opts, pico_args::Error> {
     let mut args: Vec<_> = std::env::args_os().collect();
     args.remove(0);
 
+    let command = match args.first().and_then(|arg| arg.to_str()) {
+        Some("repl") => {
+            args.remove(0);
+            Command::Repl
+        }
+        Some("lsp") => {
+            args.remove(0);
+            Command::Lsp
+        }
+        Some("debug") => {
+            args.remove(0);
+            Command::Debug
+        }
+        Some("fmt") => {
+            args.remove(0);
+            Command::Fmt
+        }
+        Some("test") => {
+            args.remove(0);
+            Command::Test
+        }
+        _ => Command::Run,
+    };
+
     let mut pargs = pico_args::Arguments::from_vec(args);
 
     if pargs.contains(["-h", "--help"]) {
@@ -32,12 +136,46 @@ pub fn parse() -> Result<Opts, pico_args::Error> {
     }
 
     let args = Opts {
-        output: pargs
-            .opt_value_from_str(["-o", "--output"])?
-            .unwrap_or_else(|| "lol.out".to_string()),
+        output: pargs.opt_value_from_str(["-o", "--output"])?,
         dump_lua: pargs.opt_value_from_str("--dump-lua").unwrap(),
+        include: pargs.values_from_str(["-I", "--include"])?,
+        lints: Levels {
+            allow: pargs.values_from_fn(["-A", "--allow"], lint)?,
+            deny: pargs.values_from_fn(["-D", "--deny"], lint)?,
+        },
+        message_format: pargs
+            .opt_value_from_fn("--message-format", message_format)?
+            .unwrap_or(MessageFormat::Human),
+        profile: pargs.contains("--profile"),
+        profile_folded: pargs.opt_value_from_str("--profile-folded")?,
+        coverage: pargs.opt_value_from_str("--coverage")?,
+        sandbox: pargs.contains("--sandbox"),
+        limits: Limits {
+            instructions: pargs.opt_value_from_str("--max-instructions")?,
+            memory: pargs.opt_value_from_fn("--max-memory", megabytes)?,
+            time: pargs.opt_value_from_fn("--timeout", seconds)?,
+        },
+        backend: pargs
+            .opt_value_from_fn("--backend", backend)?
+            .unwrap_or(Backend::Lua),
+        differential: pargs.contains("--differential"),
         debug: pargs.contains(["-d", "--debug"]),
-        input: pargs.free_from_str()?,
+        check: pargs.contains("--check"),
+        input: match command {
+            Command::Run => pargs.free_from_str()?,
+            _ => String::new(),
+        },
+        files: match command {
+            Command::Fmt | Command::Test => {
+                let mut files = vec![pargs.free_from_str()?];
+                while let Some(file) = pargs.opt_free_from_str()? {
+                    files.push(file);
+                }
+                files
+            }
+            _ => Vec::new(),
+        },
+        command,
     };
 
     let remaining = pargs.finish();
@@ -46,12 +184,96 @@ pub fn parse() -> Result<Opts, pico_args::Error> {
         eprintln!("Warning: unused arguments left: {:?}.", remaining);
     }
 
+    if args.backend == Backend::Interp {
+        // These work on the lua code, or on LuaJIT while it runs it
+        let lua_only = [
+            ("--output", args.output.is_some()),
+            ("--profile", args.profile || args.profile_folded.is_some()),
+            ("--coverage", args.coverage.is_some()),
+            (
+                "--sandbox",
+                args.sandbox || args.limits != Limits::default(),
+            ),
+        ];
+        if let Some((flag, _)) = lua_only.iter().find(|(_, used)| *used) {
+            return Err(pico_args::Error::Utf8ArgumentParsingFailed {
+                value: flag.to_string(),
+                cause: "it needs `--backend lua`".to_string(),
+            });
+        }
+    }
+
+    if args.output.is_some() {
+        // Executables run the program without these, so they would be dropped without a word
+        let run_only = [
+            ("--profile", args.profile || args.profile_folded.is_some()),
+            ("--coverage", args.coverage.is_some()),
+            (
+                "--sandbox",
+                args.sandbox || args.limits != Limits::default(),
+            ),
+        ];
+        if let Some((flag, _)) = run_only.iter().find(|(_, used)| *used) {
+            return Err(pico_args::Error::Utf8ArgumentParsingFailed {
+                value: flag.to_string(),
+                cause: "it can't be used with `--output`".to_string(),
+            });
+        }
+    }
+
     Ok(args)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Repl,
+    Lsp,
+    Debug,
+    Fmt,
+    Test,
+}
+
+/// What runs programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// LuaJIT, running the translated lua
+    Lua,
+    /// The interpreter, running the AST
+    Interp,
+}
+
+impl Backend {
+    /// The value of `--backend` that picks it
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Lua => "lua",
+            Backend::Interp => "interp",
+        }
+    }
+}
+
 pub struct Opts {
-    pub output: String,
+    pub command: Command,
+    pub output: Option<PathBuf>,
     pub input: String,
+    /// Every input of `fmt` and `test`
+    pub files: Vec<String>,
     pub dump_lua: Option<String>,
-    pub debug: bool
+    pub include: Vec<PathBuf>,
+    pub lints: Levels,
+    pub message_format: MessageFormat,
+    pub profile: bool,
+    /// Where to write folded stacks, with `--profile-folded`
+    pub profile_folded: Option<String>,
+    /// Where to write lcov coverage, with `--coverage`
+    pub coverage: Option<String>,
+    /// Whether to remove the lua libraries that reach the system
+    pub sandbox: bool,
+    pub limits: Limits,
+    pub backend: Backend,
+    /// Whether `test` compares the backends instead of checking headers
+    pub differential: bool,
+    pub debug: bool,
+    pub check: bool,
 }
@@ -0,0 +1,226 @@
+//! `lulz --profile`, which samples a running program with LuaJIT's profiler. Every sample holds
+//! the generated line of each frame, which are traced back to LOLCODE lines and funkshuns.
+
+use std::fmt::Write;
+
+use hashbrown::{HashMap, HashSet};
+use mlua::Lua;
+
+use lulz::runtime::builtins::register_profiler;
+use lulz::runtime::debug_info::DebugInfo;
+use lulz::runtime::errors::CHUNK_PREFIX;
+use lulz::sourcemap::{line_starts, SourceMap};
+
+/// Milliseconds between samples
+const INTERVAL: usize = 1;
+/// Lines shown in the report, hottest first
+const REPORT_LINES: usize = 20;
+
+/// Where a frame was running, with its line counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Frame {
+    /// Index of the name of the funkshun, or `None` outside of funkshuns
+    function: Option<usize>,
+    file: usize,
+    line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    samples: usize,
+    /// Samples spent on each line, by file and line, hottest first
+    lines: Vec<((usize, usize), usize)>,
+    /// Samples spent in each funkshun, and in it or its callees, hottest first
+    functions: Vec<(String, usize, usize)>,
+    /// Samples of each stack of funkshun names, outermost first and separated by `;`
+    stacks: Vec<(String, usize)>,
+}
+
+pub fn start(lj: &Lua) -> mlua::Result<()> {
+    register_profiler(lj);
+    lj.globals()
+        .get::<_, mlua::Function>("__lulz_profile_start")?
+        .call(INTERVAL)
+}
+
+pub fn stop(lj: &Lua, info: &DebugInfo, sources: &SourceMap) -> mlua::Result<Profile> {
+    let stacks: std::collections::HashMap<String, usize> = lj
+        .globals()
+        .get::<_, mlua::Function>("__lulz_profile_stop")?
+        .call(())?;
+    Ok(Profile::new(stacks, info, sources))
+}
+
+/// Counts from the highest, with ties in order so reports don't shuffle between runs
+fn sorted<K: Ord>(counts: HashMap<K, usize>) -> Vec<(K, usize)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+impl Profile {
+    /// Attributes sampled stacks of generated lines, like `lulz:3;lulz:12;`, to LOLCODE
+    fn new(
+        stacks: impl IntoIterator<Item = (String, usize)>,
+        info: &DebugInfo,
+        sources: &SourceMap,
+    ) -> Self {
+        let mut starts: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut resolve = |frame: &str| {
+            let span = info.line_span(frame.strip_prefix(CHUNK_PREFIX)?.parse().ok()?)?;
+            let starts = starts
+                .entry(span.file)
+                .or_insert_with(|| line_starts(sources.get(span.file).map_or("", |f| f.source())));
+            Some(Frame {
+                function: info.enclosing_function(span).map(|f| f.name),
+                file: span.file,
+                line: starts.partition_point(|&start| start <= span.s),
+            })
+        };
+        let name = |function: Option<usize>| match function {
+            Some(name) => info.names[name].clone(),
+            None => "main".to_string(),
+        };
+
+        let mut profile = Profile::default();
+        let mut lines = HashMap::new();
+        let mut functions: HashMap<Option<usize>, (usize, usize)> = HashMap::new();
+        let mut folded = HashMap::new();
+        for (stack, samples) in stacks {
+            // Frames of builtins are counted towards the LOLCODE that called them
+            let frames: Vec<Frame> = stack.split(';').filter_map(&mut resolve).collect();
+            let leaf = match frames.last() {
+                Some(leaf) => *leaf,
+                None => continue,
+            };
+            profile.samples += samples;
+            *lines.entry((leaf.file, leaf.line)).or_insert(0) += samples;
+            functions.entry(leaf.function).or_insert((0, 0)).0 += samples;
+            let mut seen = HashSet::new();
+            for frame in frames.iter().filter(|frame| seen.insert(frame.function)) {
+                functions.entry(frame.function).or_insert((0, 0)).1 += samples;
+            }
+
+            let mut names = vec![name(None)];
+            names.extend(
+                frames
+                    .iter()
+                    .filter_map(|frame| frame.function)
+                    .map(|f| name(Some(f))),
+            );
+            *folded.entry(names.join(";")).or_insert(0) += samples;
+        }
+
+        profile.lines = sorted(lines);
+        let mut functions: Vec<_> = functions
+            .into_iter()
+            .map(|(function, (own, total))| (name(function), own, total))
+            .collect();
+        functions.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then(b.2.cmp(&a.2))
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        profile.functions = functions;
+        profile.stacks = sorted(folded);
+        profile
+    }
+
+    fn percent(&self, samples: usize) -> f64 {
+        samples as f64 * 100.0 / self.samples.max(1) as f64
+    }
+
+    /// A report of the hottest lines and funkshuns
+    pub fn report(&self, sources: &SourceMap) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "\n{} samples, one every {}ms\n\n{:>8} {:>6}  line",
+            self.samples, INTERVAL, "samples", "%"
+        );
+        for &((file, line), samples) in self.lines.iter().take(REPORT_LINES) {
+            let (name, text) = match sources.get(file) {
+                Ok(file) => (
+                    file.name().as_str(),
+                    file.source().lines().nth(line - 1).unwrap_or(""),
+                ),
+                Err(_) => ("?", ""),
+            };
+            let _ = writeln!(
+                out,
+                "{:>8} {:>5.1}%  {}:{}  {}",
+                samples,
+                self.percent(samples),
+                name,
+                line,
+                text.trim()
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "\n{:>8} {:>6} {:>8} {:>6}  funkshun",
+            "self", "%", "total", "%"
+        );
+        for (name, own, total) in self.functions.iter() {
+            let _ = writeln!(
+                out,
+                "{:>8} {:>5.1}% {:>8} {:>5.1}%  {}",
+                own,
+                self.percent(*own),
+                total,
+                self.percent(*total),
+                name
+            );
+        }
+        out
+    }
+
+    /// Folded stacks, the input of flamegraph tools
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, samples) in self.stacks.iter() {
+            let _ = writeln!(out, "{} {}", stack, samples);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod profiler_test {
+    use super::*;
+    use lulz::diagnostics::Span;
+    use lulz::runtime::debug_info::FunctionInfo;
+
+    #[test]
+    fn attributes_samples() {
+        let source = "HAI 1.2\nHOW IZ I f\nVISIBLE 1\nIF U SAY SO\nI IZ f MKAY\nKTHXBYE\n";
+        let mut sources = SourceMap::new();
+        let file = sources.add("p.lol".to_string(), source.to_string());
+        let span = |s, e| Some(Span::new(s, e, file));
+        let info = DebugInfo {
+            // The body of `f`, and the call at the top level
+            line_spans: vec![span(19, 28), span(41, 52)],
+            names: vec!["f".to_string()],
+            functions: vec![FunctionInfo {
+                name: 0,
+                name_span: Span::new(17, 18, file),
+                span: Span::new(8, 40, file),
+            }],
+        };
+        let stacks = vec![
+            ("lulz:2;lulz:1;[builtin#29];".to_string(), 3),
+            ("lulz:2;".to_string(), 1),
+            ("[builtin#1];".to_string(), 5),
+        ];
+        let profile = Profile::new(stacks, &info, &sources);
+
+        assert_eq!(profile.samples, 4);
+        assert_eq!(profile.lines, [((file, 3), 3), ((file, 5), 1)]);
+        assert_eq!(
+            profile.functions,
+            [("f".to_string(), 3, 3), ("main".to_string(), 1, 4)]
+        );
+        assert_eq!(profile.folded(), "main;f 3\nmain 1\n");
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, Write};
+
+use mlua::Lua;
+
+use lulz::backend::{interner::Interner, translator::Translator};
+use lulz::diagnostics::{Diagnostics, Failible};
+use lulz::frontend::{ast::Block, lex::Lexer, parse::Parser};
+use lulz::runtime::builtins::register_modules;
+use lulz::runtime::errors::{emit_errors, lua_error, CHUNK_NAME};
+use lulz::sourcemap::SourceMap;
+
+use crate::opts::Opts;
+
+const PROMPT: &str = "lulz> ";
+const CONTINUE_PROMPT: &str = "....> ";
+
+fn prompt(s: &str) {
+    print!("{}", s);
+    let _ = io::stdout().flush();
+}
+
+fn parse(source: &str, id: usize, interner: &mut Interner) -> Failible<Block> {
+    let lexer = Lexer::new(source.chars(), id, interner);
+    Parser::new(lexer).parse_repl()
+}
+
+/// Whether parsing failed only because the input stopped early, like in an open `O RLY?`
+fn needs_more(es: Diagnostics, source: &str) -> bool {
+    let end = source.trim_end().len();
+    es.into_inner().iter().any(|e| e.span.s >= end)
+}
+
+fn eval(id: usize, lj: &Lua, translator: &mut Translator) -> Failible<()> {
+    let source = translator.sources.get(id).unwrap().source().clone();
+    let ast = parse(&source, id, translator.interner())?;
+
+    translator.repl_block(ast)?;
+    lj.load(&translator.code)
+        .set_name(CHUNK_NAME)
+        .and_then(|chunk| chunk.exec())
+        .map_err(|e| lua_error(e, &translator.debug_info()))?;
+    Ok(())
+}
+
+pub fn run(opts: Opts) {
+    let lj = Lua::new();
+    register_modules(&lj);
+    let mut translator = Translator::new(Interner::default(), opts.include, SourceMap::new());
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut inputs = 0;
+
+    loop {
+        let mut source = String::new();
+        prompt(PROMPT);
+        loop {
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => {
+                    println!();
+                    return;
+                }
+            };
+            // An empty line runs an unfinished input anyway, to show what is wrong with it
+            let can_continue = !line.trim().is_empty() || source.is_empty();
+            source.push_str(&line);
+            source.push('\n');
+            if source.trim().is_empty() {
+                source.clear();
+                prompt(PROMPT);
+                continue;
+            }
+
+            // The input isn't in the sources yet, so its id doesn't matter
+            let more = match parse(&source, usize::MAX, translator.interner()) {
+                Ok(_) => false,
+                Err(es) => can_continue && needs_more(es, &source),
+            };
+            if !more {
+                break;
+            }
+            prompt(CONTINUE_PROMPT);
+        }
+
+        inputs += 1;
+        let id = translator.sources.add(format!("<repl:{}>", inputs), source);
+        if let Err(es) = eval(id, &lj, &mut translator) {
+            emit_errors(es, &translator.sources, opts.message_format);
+        }
+        // Output from lua is buffered separately from the prompt
+        let _ = lj.load("io.stdout:flush()").exec();
+    }
+}
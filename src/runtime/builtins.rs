@@ -4,14 +4,23 @@ use super::errors::register_raise_error;
 pub mod io {
     pub const LUA_PRINT: &str = "_lulz_print";
     pub const LUA_PRINTLN: &str = "_lulz_println";
+    pub const LUA_INPUT: &str = "_lulz_input";
+    /// Where output goes and input comes from, which embedders can replace
+    pub const LUA_WRITE: &str = "__lulz_write";
+    pub const LUA_READ: &str = "__lulz_read";
 }
 
 pub mod ops {
+    pub const LUA_NUMBAR: &str = "_lulz_numbar";
     pub const LUA_ADD: &str = "_lulz_add";
     pub const LUA_SUB: &str = "_lulz_sub";
     pub const LUA_MUL: &str = "_lulz_mul";
     pub const LUA_DIV: &str = "_lulz_div";
     pub const LUA_MOD: &str = "_lulz_mod";
+    pub const LUA_MAX: &str = "_lulz_max";
+    pub const LUA_MIN: &str = "_lulz_min";
+    pub const LUA_TRUTHY: &str = "_lulz_truthy";
+    pub const LUA_ARITY: &str = "_lulz_arity";
     pub const LUA_AND: &str = "_lulz_and";
     pub const LUA_OR: &str = "_lulz_or";
     pub const LUA_EQ: &str = "_lulz_eq";
@@ -22,6 +31,20 @@ pub mod ops {
     pub const LUA_LTE: &str = "_lulz_lte";
 }
 
+pub mod bukkit {
+    pub const LUA_NEW: &str = "_lulz_bukkit";
+    pub const LUA_DEC_SLOT: &str = "_lulz_dec_slot";
+    pub const LUA_GET_SLOT: &str = "_lulz_get_slot";
+    pub const LUA_SET_SLOT: &str = "_lulz_set_slot";
+    pub const LUA_CALL_METHOD: &str = "_lulz_call_method";
+}
+
+pub mod host {
+    pub const LUA_HOST: &str = "_lulz_host";
+    pub const LUA_WRAP: &str = "__lulz_host_function";
+    pub const LUA_CALL: &str = "__lulz_host_call";
+}
+
 macro_rules! include_module {
     ($name: expr) => {
         include_str!(concat!("builtins/", concat!($name, ".lua")))
@@ -36,6 +59,18 @@ pub fn register_modules(lj: &Lua) {
     // Order of loading is important
     register_raise_error(lj);
     run_str(lj, include_module!("it"));
-    run_str(lj, include_module!("io"));
     run_str(lj, include_module!("ops"));
+    run_str(lj, include_module!("io"));
+    run_str(lj, include_module!("bukkit"));
+    run_str(lj, include_module!("host"));
+}
+
+/// Loads the helpers of `lulz --profile`
+pub fn register_profiler(lj: &Lua) {
+    run_str(lj, include_module!("profiler"));
+}
+
+/// Loads the helpers of `lulz debug` over the other modules. The `debug` library must be loaded.
+pub fn register_debugger(lj: &Lua) {
+    run_str(lj, include_module!("debugger"));
 }
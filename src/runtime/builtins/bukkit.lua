@@ -0,0 +1,89 @@
+-- Unique key holding the set of slots declared on a bukkit, so NOOB slots still exist
+local declared = {}
+
+__lulz_bukkit_mt = {}
+
+function __lulz_is_bukkit(v)
+    return getmetatable(v) == __lulz_bukkit_mt
+end
+
+-- Without this, lua would call a BUKKIT a table in the error
+function __lulz_bukkit_mt.__call()
+    error("attempt to call a BUKKIT value", 2)
+end
+
+local function find_slot(obj, key)
+    local cur = obj
+    while __lulz_is_bukkit(cur) do
+        local v = rawget(cur, key)
+        if v ~= nil or cur[declared][key] then
+            return true, v
+        end
+        cur = rawget(cur, "parent")
+    end
+    return false, nil
+end
+
+local function check_bukkit(obj, key, span)
+    if not __lulz_is_bukkit(obj) then
+        _ffi_lulz_error(
+            "cannot use slot `" .. key .. "` of a value that is not a BUKKIT",
+            span[1], span[2], span[3]
+        )
+    end
+end
+
+function __lulz_bukkit_mt.__index(obj, key)
+    local _, v = find_slot(rawget(obj, "parent"), key)
+    return v
+end
+
+function _lulz_bukkit(parent)
+    local obj = setmetatable({ [declared] = {} }, __lulz_bukkit_mt)
+    if parent ~= nil then
+        obj[declared].parent = true
+        rawset(obj, "parent", parent)
+    end
+    return obj
+end
+
+function _lulz_dec_slot(obj, key, value, span)
+    check_bukkit(obj, key, span)
+    obj[declared][key] = true
+    rawset(obj, key, value)
+end
+
+function _lulz_get_slot(obj, key, span)
+    check_bukkit(obj, key, span)
+    local found, v = find_slot(obj, key)
+    if found then
+        return v
+    end
+
+    -- The `omgwtf` slot is asked for a value for slots that don't exist
+    local _, omgwtf = find_slot(obj, "omgwtf")
+    if type(omgwtf) == "function" then
+        v = omgwtf(obj, key)
+        _lulz_dec_slot(obj, key, v, span)
+        return v
+    end
+
+    _ffi_lulz_error("slot `" .. key .. "` does not exist", span[1], span[2], span[3])
+end
+
+function _lulz_set_slot(obj, key, value, span)
+    check_bukkit(obj, key, span)
+    if not find_slot(obj, key) then
+        _ffi_lulz_error("slot `" .. key .. "` does not exist", span[1], span[2], span[3])
+    end
+    -- Inherited slots are copied onto the bukkit itself when assigned
+    _lulz_dec_slot(obj, key, value, span)
+end
+
+function _lulz_call_method(obj, key, span, ...)
+    local method = _lulz_get_slot(obj, key, span)
+    if type(method) ~= "function" then
+        _ffi_lulz_error("slot `" .. key .. "` is not a FUNKSHUN", span[1], span[2], span[3])
+    end
+    return method(obj, ...)
+end
@@ -0,0 +1,67 @@
+-- Helpers for `lulz debug`, loaded after the other builtins. They need the `debug` library.
+
+local CHUNK = "=lulz"
+
+-- Output goes to the editor, as stdin and stdout carry the debug adapter protocol. For the same
+-- reason, programs get no input, and `GIMMEH` fails where it is called.
+__lulz_write = __lulz_dbg_output
+__lulz_read = function()
+    error("GIMMEH has no input while debugging, as stdin carries the debug adapter protocol", 3)
+end
+
+function __lulz_dbg_show(v)
+    if type(v) == "string" then
+        return string.format("%q", v)
+    end
+    return __lulz_to_str(v) or __lulz_type_name(v)
+end
+
+local function variable(name, value)
+    return { name, __lulz_dbg_show(value), __lulz_type_name(value) }
+end
+
+-- The stack levels running generated code, innermost first, with their current lines. Levels
+-- count from the caller of these helpers, so they can be passed to `__lulz_dbg_locals`.
+function __lulz_dbg_frames()
+    local frames = {}
+    local level = 2
+    while true do
+        local info = debug.getinfo(level, "Sl")
+        if not info then
+            return frames
+        end
+        if info.source == CHUNK then
+            frames[#frames + 1] = { level, info.currentline }
+        end
+        level = level + 1
+    end
+end
+
+-- The locals of a stack level as `{ name, value, type }`, in the order they were declared
+function __lulz_dbg_locals(level)
+    local vars = {}
+    local i = 1
+    while true do
+        local name, value = debug.getlocal(level, i)
+        if not name then
+            return vars
+        end
+        vars[#vars + 1] = variable(name, value)
+        i = i + 1
+    end
+end
+
+function __lulz_dbg_it()
+    return variable("_lulz_it", _lulz_it)
+end
+
+-- Global LOLCODE variables, whose names are mangled to `_<id>`
+function __lulz_dbg_globals()
+    local vars = {}
+    for name, value in pairs(_G) do
+        if type(name) == "string" and name:match("^_%d+$") then
+            vars[#vars + 1] = variable(name, value)
+        end
+    end
+    return vars
+end
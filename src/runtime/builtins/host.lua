@@ -0,0 +1,61 @@
+-- Functions of the host, by their LOLCODE name. Values cross over to the host in a form it can
+-- read without knowing about the runtime: 64 bit NUMBRs as `{ numbr = "digits" }`, NUMBARs as
+-- `{ numbar = v }`, and LIZTs as copies holding values in the same form.
+local unpack = unpack
+local is_numbar = __lulz_is_numbar
+local is_bukkit = __lulz_is_bukkit
+local parse_numbr = __lulz_parse_numbr
+
+_lulz_host = {}
+
+function __lulz_to_host(v)
+    local ty = type(v)
+    if ty == "cdata" then
+        return { numbr = tostring(v):sub(1, -3) }
+    elseif is_numbar(v) then
+        return { numbar = v[1] }
+    elseif ty == "table" and not is_bukkit(v) then
+        local lizt = {}
+        for i, item in ipairs(v) do
+            lizt[i] = __lulz_to_host(item)
+        end
+        return lizt
+    end
+    return v
+end
+
+function __lulz_from_host(v)
+    if type(v) ~= "table" or getmetatable(v) ~= nil then
+        return v
+    elseif v.numbr ~= nil then
+        return parse_numbr(v.numbr)
+    elseif v.numbar ~= nil then
+        return _lulz_numbar(v.numbar)
+    end
+    local lizt = {}
+    for i, item in ipairs(v) do
+        lizt[i] = __lulz_from_host(item)
+    end
+    return lizt
+end
+
+-- Calls `f` with its arguments converted by `to`, and converts what it returns with `from`
+local function convert_call(f, to, from, ...)
+    local args = table.pack(...)
+    for i = 1, args.n do
+        args[i] = to(args[i])
+    end
+    return from(f(unpack(args, 1, args.n)))
+end
+
+-- Wraps a function of the host to be called from LOLCODE
+function __lulz_host_function(f)
+    return function(...)
+        return convert_call(f, __lulz_to_host, __lulz_from_host, ...)
+    end
+end
+
+-- Calls a FUNKSHUN from the host
+function __lulz_host_call(f, ...)
+    return convert_call(f, __lulz_from_host, __lulz_to_host, ...)
+end
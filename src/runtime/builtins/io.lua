@@ -2,10 +2,25 @@ function table.pack(...)
   return { n = select("#", ...), ... }
 end
 
+-- Where output goes and input comes from, which embedders can replace. They keep what they need
+-- of `io`, so the sandbox can remove it.
+local read = io.read
+__lulz_write = io.write
+__lulz_read = function()
+    return read("*l")
+end
+
 function __lulz_to_str(v)
     local ty = type(v)
-    if ty == "number" or ty == "string" then
+    if ty == "string" then
         return v
+    elseif ty == "number" then
+        return string.format("%d", v)
+    elseif ty == "cdata" then
+        -- Strip the `LL` suffix of 64 bit integers
+        return tostring(v):sub(1, -3)
+    elseif __lulz_is_numbar(v) then
+        return tostring(v[1])
     elseif ty == "boolean" then
         return v and "WIN" or "FAIL"
     elseif ty == "nil" then
@@ -16,14 +31,19 @@ end
 function _lulz_println(...)
     local args = table.pack(...)
     for i=1,args.n do
-        io.write(__lulz_to_str(args[i]))
+        __lulz_write(__lulz_to_str(args[i]))
     end
-    print()
+    __lulz_write("\n")
 end
 
 function _lulz_print(...)
     local args = table.pack(...)
     for i=1,args.n do
-        io.write(__lulz_to_str(args[i]))
+        __lulz_write(__lulz_to_str(args[i]))
     end
 end
+
+-- `GIMMEH`, which reads a line as a YARN, or NOOB once the input ends
+function _lulz_input()
+    return __lulz_read()
+end
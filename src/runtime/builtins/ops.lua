@@ -1,56 +1,333 @@
-function _lulz_add(l, r)
-    if type(l) == "string" and type(r) == "string" then
-        return l..r
-    else
-        return l + r
+-- NUMBRs are plain lua numbers while they can be represented exactly (|v| < 2^53),
+-- and 64 bit integer cdata outside of that range. NUMBARs are boxed so the two never mix up.
+-- Literals are boxed once per chunk by the translator, so only results of operators allocate.
+local MAX_EXACT = 2^53
+local fmod = math.fmod
+
+__lulz_numbar_mt = {}
+
+function _lulz_numbar(v)
+    return setmetatable({ v }, __lulz_numbar_mt)
+end
+
+function __lulz_is_numbar(v)
+    return getmetatable(v) == __lulz_numbar_mt
+end
+
+-- Without this, lua would call a NUMBAR a table in the error
+function __lulz_numbar_mt.__call()
+    error("attempt to call a NUMBAR value", 2)
+end
+
+-- Called by a funkshun given the wrong number of arguments, so the error is raised in its caller
+function _lulz_arity(name, expected, given)
+    local s = expected == 1 and "" or "s"
+    error("funkshun `" .. name .. "` takes " .. expected .. " argument" .. s .. ", but was called with " .. given, 3)
+end
+
+local is_numbar = __lulz_is_numbar
+
+local function is_numbr(v)
+    local ty = type(v)
+    return ty == "number" or ty == "cdata"
+end
+
+local function is_num(v)
+    return is_numbr(v) or is_numbar(v)
+end
+
+local function to_float(v)
+    if is_numbar(v) then
+        return v[1]
+    end
+    return tonumber(v)
+end
+
+local function raise(msg, span)
+    _ffi_lulz_error(msg, span[1], span[2], span[3])
+end
+
+-- The LOLCODE type of a value, like `NUMBR`
+function __lulz_type_name(v)
+    local ty = type(v)
+    if ty == "number" or ty == "cdata" then
+        return "NUMBR"
+    elseif is_numbar(v) then
+        return "NUMBAR"
+    elseif ty == "string" then
+        return "YARN"
+    elseif ty == "boolean" then
+        return "TROOF"
+    elseif ty == "nil" then
+        return "NOOB"
+    elseif ty == "function" then
+        return "FUNKSHUN"
+    elseif __lulz_is_bukkit(v) then
+        return "BUKKIT"
+    end
+    return "LIZT"
+end
+
+local type_name = __lulz_type_name
+
+-- Normalises a 64 bit integer back to a plain number when it fits
+local function numbr(v)
+    if v < MAX_EXACT and v > -MAX_EXACT then
+        return tonumber(v)
     end
+    return v
 end
 
-function _lulz_sub(l, r)
+local function exact(v)
+    return v < MAX_EXACT and v > -MAX_EXACT
+end
+
+-- Parses digits with an optional `-` as a NUMBR, failing at `span` when they don't fit in 64 bits
+local function parse_numbr(s, span)
+    if #s < 16 then
+        return tonumber(s)
+    end
+    -- Too long to be parsed exactly as a lua number
+    local negative = s:sub(1, 1) == "-"
+    local digits = s:match("^%-?0*(%d*)$")
+    local max = negative and "9223372036854775808" or "9223372036854775807"
+    if #digits > #max or (#digits == #max and digits > max) then
+        raise("cannot implicitly cast YARN \"" .. s .. "\" to a NUMBR", span)
+    end
+    local n = 0LL
+    for digit in digits:gmatch("%d") do
+        n = n * 10 + tonumber(digit)
+    end
+    if negative then
+        n = -n
+    end
+    return numbr(n)
+end
+
+__lulz_parse_numbr = parse_numbr
+
+-- Implicitly casts an operand of a math operator to a NUMBR or NUMBAR
+local function to_num(v, span)
+    if is_num(v) then
+        return v
+    end
+
+    local ty = type(v)
+    if ty == "boolean" then
+        return v and 1 or 0
+    elseif ty == "string" then
+        if v:match("^%-?%d+$") then
+            return parse_numbr(v, span)
+        elseif v:match("^%-?%d*%.%d+$") or v:match("^%-?%d+%.%d*$") then
+            return _lulz_numbar(tonumber(v))
+        end
+        raise("cannot implicitly cast YARN \"" .. v .. "\" to a NUMBR or NUMBAR", span)
+    end
+    raise("cannot implicitly cast a " .. type_name(v) .. " to a NUMBR or NUMBAR", span)
+end
+
+-- Slow path for arithmetic, used for NUMBARs, big NUMBRs and implicit casts
+local function arith(l, r, span, int_op, float_op)
+    if is_numbar(l) and is_numbar(r) then
+        return _lulz_numbar(float_op(l[1], r[1], span))
+    end
+    l, r = to_num(l, span), to_num(r, span)
+    if is_numbar(l) or is_numbar(r) then
+        return _lulz_numbar(float_op(to_float(l), to_float(r), span))
+    end
+    if type(l) == "number" and type(r) == "number" then
+        -- Small NUMBRs from casts still take the exact route
+        local v = int_op(l, r, span)
+        if exact(v) then
+            return v
+        end
+    end
+    -- Wraps around on overflow, like a C int64_t
+    return numbr(int_op(0LL + l, 0LL + r, span))
+end
+
+local function add(l, r)
+    return l + r
+end
+
+local function sub(l, r)
     return l - r
 end
 
-function _lulz_mul(l, r)
+local function mul(l, r)
     return l * r
 end
 
-function _lulz_div(l, r)
+local function div(l, r)
     return l / r
 end
 
-function _lulz_mod(l, r)
+local function max(l, r)
+    return l > r and l or r
+end
+
+local function min(l, r)
+    return l < r and l or r
+end
+
+-- Integer division and modulo truncate towards zero, like C (and int64 cdata)
+local function int_div(l, r, span)
+    if r == 0 then
+        raise("division by zero", span)
+    end
+    if type(l) == "number" then
+        -- fmod is exact, so this is the exact truncated quotient
+        return (l - fmod(l, r)) / r + 0
+    end
+    return l / r
+end
+
+local function int_mod(l, r, span)
+    if r == 0 then
+        raise("division by zero", span)
+    end
+    if type(l) == "number" then
+        -- `+ 0` turns a negative zero into a zero
+        return fmod(l, r) + 0
+    end
     return l % r
 end
 
+local function float_mod(l, r)
+    return fmod(l, r)
+end
+
+function _lulz_add(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        local v = l + r
+        if exact(v) then
+            return v
+        end
+    end
+    return arith(l, r, span, add, add)
+end
+
+function _lulz_sub(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        local v = l - r
+        if exact(v) then
+            return v
+        end
+    end
+    return arith(l, r, span, sub, sub)
+end
+
+function _lulz_mul(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        local v = l * r
+        if exact(v) then
+            return v
+        end
+    end
+    return arith(l, r, span, mul, mul)
+end
+
+function _lulz_div(l, r, span)
+    if type(l) == "number" and type(r) == "number" and r ~= 0 then
+        return (l - fmod(l, r)) / r + 0
+    end
+    return arith(l, r, span, int_div, div)
+end
+
+function _lulz_mod(l, r, span)
+    if type(l) == "number" and type(r) == "number" and r ~= 0 then
+        return fmod(l, r) + 0
+    end
+    return arith(l, r, span, int_mod, float_mod)
+end
+
+function _lulz_max(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return max(l, r)
+    end
+    return arith(l, r, span, max, max)
+end
+
+function _lulz_min(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return min(l, r)
+    end
+    return arith(l, r, span, min, min)
+end
+
+-- Whether a value casts to WIN. NOOB, FAIL, 0, 0.0 and the empty YARN are FAIL, unlike in lua.
+-- 64 bit NUMBRs are never 0, which is a lua number.
+function _lulz_truthy(v)
+    if v == nil or v == false or v == 0 or v == "" then
+        return false
+    end
+    return not is_numbar(v) or v[1] ~= 0
+end
+
+local truthy = _lulz_truthy
+
 function _lulz_and(l, r)
-    return l and r
+    return truthy(l) and truthy(r)
 end
 
 function _lulz_or(l, r)
-    return l or r
+    return truthy(l) or truthy(r)
 end
 
 function _lulz_eq(l, r)
+    if type(l) == "number" and type(r) == "number" then
+        return l == r
+    end
+    if is_num(l) and is_num(r) then
+        if is_numbar(l) or is_numbar(r) then
+            return to_float(l) == to_float(r)
+        end
+        return 0LL + l == 0LL + r
+    end
     return l == r
 end
 
 function _lulz_neq(l, r)
-    return l ~= r
+    return not _lulz_eq(l, r)
+end
+
+-- Casts both sides of a comparison to the same kind of number
+local function cmp_operands(l, r, span)
+    l, r = to_num(l, span), to_num(r, span)
+    if is_numbar(l) or is_numbar(r) then
+        return to_float(l), to_float(r)
+    end
+    return 0LL + l, 0LL + r
 end
 
-function _lulz_gt(l, r)
+function _lulz_gt(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return l > r
+    end
+    l, r = cmp_operands(l, r, span)
     return l > r
 end
 
-function _lulz_lt(l, r)
+function _lulz_lt(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return l < r
+    end
+    l, r = cmp_operands(l, r, span)
     return l < r
 end
 
-function _lulz_gte(l, r)
+function _lulz_gte(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return l >= r
+    end
+    l, r = cmp_operands(l, r, span)
     return l >= r
 end
 
-function _lulz_lte(l, r)
+function _lulz_lte(l, r, span)
+    if type(l) == "number" and type(r) == "number" then
+        return l <= r
+    end
+    l, r = cmp_operands(l, r, span)
     return l <= r
 end
-
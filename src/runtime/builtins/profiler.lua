@@ -0,0 +1,18 @@
+-- Helpers for `lulz --profile`, which samples programs with LuaJIT's profiler
+
+local profile = require("jit.profile")
+
+-- Sample counts by stack, where a stack lists the line of every frame, outermost first
+local stacks = {}
+
+function __lulz_profile_start(interval)
+    profile.start("li" .. interval, function(thread, samples)
+        local stack = profile.dumpstack(thread, "l;", -1000)
+        stacks[stack] = (stacks[stack] or 0) + samples
+    end)
+end
+
+function __lulz_profile_stop()
+    profile.stop()
+    return stacks
+end
@@ -0,0 +1,67 @@
+use crate::diagnostics::Span;
+
+/// Maps generated lua code back to the LOLCODE it came from
+#[derive(Debug, Default, Clone)]
+pub struct DebugInfo {
+    /// The statement each generated line was emitted for, indexed by line number - 1
+    pub line_spans: Vec<Option<Span>>,
+    /// Identifier names, indexed by the id in their mangled `_<id>` form
+    pub names: Vec<String>,
+    /// Every funkshun and method, to name the frames of stack traces
+    pub functions: Vec<FunctionInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// Index of the funkshun's name in `names`
+    pub name: usize,
+    /// Where the name is declared
+    pub name_span: Span,
+    /// The whole definition
+    pub span: Span,
+}
+
+impl DebugInfo {
+    /// The span of the statement that generated lua line `line` (starting at 1)
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        self.line_spans.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    /// The innermost funkshun whose definition contains a span
+    pub fn enclosing_function(&self, span: Span) -> Option<&FunctionInfo> {
+        self.functions
+            .iter()
+            .filter(|f| f.span.file == span.file && f.span.s <= span.s && span.e <= f.span.e)
+            .min_by_key(|f| f.span.e - f.span.s)
+    }
+
+    /// Replaces mangled identifiers like `_12` in a lua message with their LOLCODE names
+    pub fn demangle(&self, msg: &str) -> String {
+        let mut out = String::with_capacity(msg.len());
+        let mut rest = msg;
+        while let Some(pos) = rest.find('_') {
+            out.push_str(&rest[..pos]);
+            let after = &rest[pos + 1..];
+            let digits = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            let boundary = !out.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+            match after[..digits]
+                .parse::<usize>()
+                .ok()
+                .and_then(|id| self.names.get(id))
+            {
+                Some(name) if boundary => {
+                    out.push_str(name);
+                    rest = &after[digits..];
+                }
+                _ => {
+                    out.push('_');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out.replace("_lulz_it", "IT").replace("_lulz_me", "ME")
+    }
+}
@@ -1,64 +1,299 @@
-use std::process::exit;
-
-use codespan_reporting::{
-    diagnostic::{self, Label},
-    files::SimpleFiles,
-    term::{
-        self,
-        termcolor::{ColorChoice, StandardStream},
-    },
+use std::fmt;
+
+use codespan_reporting::term::{
+    self,
+    termcolor::{Buffer, ColorChoice, StandardStream},
 };
 use mlua::Lua;
 
-use crate::diagnostics::{DiagnosticType, Diagnostics, Span};
-use crate::sourcemap::SOURCEMAP;
+use crate::diagnostics::{Diagnostic, DiagnosticType, Diagnostics, MessageFormat, Span};
+use crate::sourcemap::SourceMap;
 
-fn raise_error_at(span: Span, msg: &str) -> ! {
-    let codespan_err = diagnostic::Diagnostic::new(diagnostic::Severity::Error)
-        .with_message(msg)
-        .with_code(DiagnosticType::Runtime.to_string())
-        .with_labels(vec![Label::primary(span.file, (span.s)..(span.e))]);
+use super::debug_info::DebugInfo;
+use std::borrow::Cow;
 
-    let writer = StandardStream::stderr(ColorChoice::Always);
-    let config = term::Config::default();
+/// The name generated code is loaded under, so its lines can be found in lua errors
+pub const CHUNK_NAME: &str = "=lulz";
+/// What lua puts in front of line numbers of generated code
+pub const CHUNK_PREFIX: &str = "lulz:";
+/// Calls shown in the stack trace of a runtime error, innermost first
+const MAX_FRAMES: usize = 8;
 
-    let mut guard = SOURCEMAP.write().unwrap();
-    let sourcemap = std::mem::replace(&mut *guard, SimpleFiles::new());
-    term::emit(&mut writer.lock(), &config, &sourcemap, &codespan_err)
-        .expect("Failed to write error");
+/// Lua's names for types in error messages, and what they are called in LOLCODE. BUKKITs and
+/// NUMBARs are tables too, but their metatables raise errors that name them.
+const LUA_TYPES: [(&str, &str); 6] = [
+    ("nil value", "NOOB value"),
+    ("number value", "NUMBR value"),
+    ("string value", "YARN value"),
+    ("boolean value", "TROOF value"),
+    ("table value", "LIZT value"),
+    ("function value", "FUNKSHUN value"),
+];
 
-    // Failed
-    exit(1)
+/// An error raised by the runtime library through `_ffi_lulz_error`. It unwinds out of the
+/// generated code as a lua error, back to the host that ran it.
+#[derive(Debug)]
+pub struct LulzError(pub Diagnostic);
+
+impl fmt::Display for LulzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.annotations.first() {
+            Some(annotation) => write!(f, "{}", annotation.message),
+            None => write!(f, "{}", self.0.ty),
+        }
+    }
 }
 
+impl std::error::Error for LulzError {}
+
 pub fn register_raise_error(lj: &Lua) {
     let globals = lj.globals();
-    globals.set(
-        "_ffi_lulz_error",
-        lj.create_function(|_, (msg, (s, e, file)): (String, (usize, usize, usize))| {
-            let span = Span::new(s, e, file);
-            raise_error_at(span, &msg);
-            Ok(())
-        }).unwrap(),
-    ).unwrap();
+    globals
+        .set(
+            "_ffi_lulz_error",
+            lj.create_function(
+                |_, (msg, (s, e, file)): (String, (usize, usize, usize))| -> mlua::Result<()> {
+                    // `lua_error` adds the stack trace once it has unwound
+                    Err(mlua::Error::external(LulzError(raised(
+                        msg,
+                        Span::new(s, e, file),
+                    ))))
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+}
+
+/// The diagnostic of an error the runtime library raised at `span`, without its stack trace
+pub fn raised(msg: String, span: Span) -> Diagnostic {
+    Diagnostic::build(DiagnosticType::Runtime, span).annotation(Cow::Owned(msg), span)
+}
+
+fn lua_message(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::RuntimeError(msg) | mlua::Error::MemoryError(msg) => msg.clone(),
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        mlua::Error::CallbackError { traceback, cause } => {
+            format!("{}\n{}", lua_message(cause), traceback)
+        }
+        e => e.to_string(),
+    }
+}
+
+fn raised_error(err: &mlua::Error) -> Option<&LulzError> {
+    match err {
+        mlua::Error::ExternalError(e) => e.downcast_ref::<LulzError>(),
+        mlua::Error::CallbackError { cause, .. } => raised_error(cause),
+        _ => None,
+    }
+}
+
+/// Finds the first line of generated code mentioned by a lua error or its traceback
+fn generated_line(msg: &str) -> Option<usize> {
+    msg.match_indices(CHUNK_PREFIX).find_map(|(pos, _)| {
+        let rest = &msg[pos + CHUNK_PREFIX.len()..];
+        let end = rest.find(|c: char| !c.is_ascii_digit())?;
+        if rest[end..].starts_with(':') {
+            rest[..end].parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Strips the `chunk:line:` prefix lua puts in front of error messages
+fn strip_location(line: &str) -> &str {
+    if !line.starts_with(CHUNK_PREFIX) && !line.starts_with("[string") {
+        return line;
+    }
+    line.match_indices(": ")
+        .map(|(pos, _)| pos)
+        .find(|&pos| line[..pos].ends_with(|c: char| c.is_ascii_digit()))
+        .map(|pos| &line[pos + 2..])
+        .unwrap_or(line)
+}
+
+/// Turns an error raised while running generated code into a runtime diagnostic
+pub fn lua_error(err: mlua::Error, info: &DebugInfo) -> Diagnostic {
+    let msg = lua_message(&err);
+    let frames = traceback_spans(&msg, info);
+    if let Some(LulzError(diagnostic)) = raised_error(&err) {
+        return stack_trace(diagnostic.clone(), diagnostic.span, frames, info);
+    }
+
+    let span = generated_line(&msg).and_then(|line| info.line_span(line));
+    let mut description = info.demangle(strip_location(msg.lines().next().unwrap_or("")));
+    for (lua_ty, lol_ty) in LUA_TYPES.iter() {
+        description = description.replace(lua_ty, lol_ty);
+    }
+    runtime_error(description, span, frames, info)
+}
+
+/// A runtime error of the VM in the statement at `span`, like calling a NOOB, with the statement
+/// of every LOLCODE frame, innermost first
+pub fn runtime_error(
+    description: String,
+    span: Option<Span>,
+    frames: Vec<Span>,
+    info: &DebugInfo,
+) -> Diagnostic {
+    let overflow = description.contains("stack overflow");
+    let out_of_memory = description.contains("not enough memory");
+    let mut diagnostic = Diagnostic::build(DiagnosticType::Runtime, span.unwrap_or_default())
+        .annotation(Cow::Owned(description), span.unwrap_or_default());
+    if overflow {
+        diagnostic = diagnostic.note(Cow::Borrowed(
+            "this is usually caused by a FUNKSHUN that calls itself forever",
+        ));
+    }
+    if out_of_memory {
+        // Running out of memory leaves no traceback, so this is the only note shown
+        return diagnostic.note(Cow::Borrowed(
+            "the program used all the memory it may use, which `--max-memory` sets",
+        ));
+    }
+    match span {
+        Some(span) => stack_trace(diagnostic, span, frames, info),
+        None => diagnostic.note(Cow::Borrowed(
+            "the error could not be traced back to a statement",
+        )),
+    }
+}
+
+/// The statement of every LOLCODE frame in a lua traceback, innermost first
+fn traceback_spans(msg: &str, info: &DebugInfo) -> Vec<Span> {
+    let traceback = match msg.find("stack traceback:") {
+        Some(pos) => &msg[pos..],
+        None => return Vec::new(),
+    };
+    traceback
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix(CHUNK_PREFIX)?;
+            let end = rest.find(|c: char| !c.is_ascii_digit())?;
+            info.line_span(rest[..end].parse().ok()?)
+        })
+        .collect()
+}
+
+/// Labels the calls a runtime error happened in, from the statement of every LOLCODE frame,
+/// innermost first. The funkshun of each frame is the innermost one defined around it.
+pub fn stack_trace(
+    mut diagnostic: Diagnostic,
+    span: Span,
+    mut frames: Vec<Span>,
+    info: &DebugInfo,
+) -> Diagnostic {
+    // The innermost frame is the failing statement itself
+    if frames
+        .first()
+        .is_some_and(|&frame| info.enclosing_function(frame) == info.enclosing_function(span))
+    {
+        frames.remove(0);
+    }
+
+    let mut location = span;
+    let mut defined = Vec::new();
+    for (i, &call) in frames.iter().enumerate() {
+        if i == MAX_FRAMES {
+            if diagnostic.note.is_none() {
+                diagnostic = diagnostic.note(Cow::Owned(format!(
+                    "{} more calls are not shown",
+                    frames.len() - i
+                )));
+            }
+            break;
+        }
+        let function = match info.enclosing_function(location) {
+            Some(function) => *function,
+            None => break,
+        };
+        let name = info
+            .names
+            .get(function.name)
+            .map_or("?", |name| name.as_str());
+        diagnostic = diagnostic.secondary(Cow::Owned(format!("in `{}`, called here", name)), call);
+        if !defined.contains(&function) {
+            defined.push(function);
+            diagnostic = diagnostic.secondary(
+                Cow::Owned(format!("`{}` is defined here", name)),
+                function.name_span,
+            );
+        }
+        location = call;
+    }
+    diagnostic
 }
 
-pub fn raise_errors(es: Diagnostics) -> ! {
+/// Writes diagnostics to stderr, in `format`
+pub fn emit_errors(es: Diagnostics, sources: &SourceMap, format: MessageFormat) {
+    if format == MessageFormat::Json {
+        for e in es.into_inner().iter() {
+            eprintln!("{}", e.to_json(sources));
+        }
+        return;
+    }
+
     let writer = StandardStream::stderr(ColorChoice::Always);
     let config = term::Config::default();
+    for e in es.into_inner().into_iter() {
+        term::emit(&mut writer.lock(), &config, sources, &(e.into_codespan()))
+            .expect("Failed to write error");
+    }
+}
 
-    let mut guard = SOURCEMAP.write().unwrap();
-    let sourcemap = std::mem::replace(&mut *guard, SimpleFiles::new());
+/// Renders diagnostics as plain text, for output that isn't a terminal
+pub fn render_errors(es: Diagnostics, sources: &SourceMap) -> String {
+    let mut buffer = Buffer::no_color();
+    let config = term::Config::default();
     for e in es.into_inner().into_iter() {
-        term::emit(
-            &mut writer.lock(),
-            &config,
-            &sourcemap,
-            &(e.into_codespan()),
-        )
-        .expect("Failed to write error");
+        term::emit(&mut buffer, &config, sources, &(e.into_codespan()))
+            .expect("Failed to write error");
     }
+    String::from_utf8_lossy(buffer.as_slice()).into_owned()
+}
 
-    // Failed
-    exit(1)
+#[cfg(test)]
+mod errors_test {
+    use super::*;
+    use crate::runtime::debug_info::FunctionInfo;
+
+    #[test]
+    fn stack_traces() {
+        let span = |s, e| Span::new(s, e, 0);
+        let function = |name, name_span, span| FunctionInfo {
+            name,
+            name_span,
+            span,
+        };
+        let info = DebugInfo {
+            line_spans: vec![Some(span(30, 40)), Some(span(80, 90)), Some(span(110, 120))],
+            names: vec!["f".to_string(), "g".to_string()],
+            functions: vec![
+                function(0, span(19, 20), span(10, 50)),
+                function(1, span(69, 70), span(60, 100)),
+            ],
+        };
+        let msg = "lulz:1: boom\nstack traceback:\n\t[C]: in function 'error'\n\
+                   \tlulz:1: in function '_0'\n\tlulz:2: in function '_1'\n\
+                   \tlulz:3: in main chunk\n\t[C]: ?";
+        let diagnostic = lua_error(mlua::Error::RuntimeError(msg.to_string()), &info);
+
+        let labels: Vec<_> = diagnostic
+            .annotations
+            .iter()
+            .map(|a| (a.message.as_ref(), a.span, a.primary))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("boom", span(30, 40), true),
+                ("in `f`, called here", span(80, 90), false),
+                ("`f` is defined here", span(19, 20), false),
+                ("in `g`, called here", span(110, 120), false),
+                ("`g` is defined here", span(69, 70), false),
+            ]
+        );
+    }
 }
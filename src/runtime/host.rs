@@ -0,0 +1,141 @@
+//! Rust functions that LOLCODE can call like funkshuns, and the values passed between them.
+//! Values are converted by the `host` builtins, so big NUMBRs and NUMBARs keep their type.
+
+use std::fmt;
+use std::rc::Rc;
+
+use mlua::{FromLua, Function, Lua, Table, ToLua, Value, Variadic};
+
+use crate::frontend::lex::is_identifier;
+
+use super::builtins::host::{LUA_CALL, LUA_HOST, LUA_WRAP};
+
+/// NUMBRs outside of this range are passed as digits, since a lua number can't hold them exactly
+const MAX_EXACT_INT: i64 = (1 << 53) - 1;
+
+/// A LOLCODE value, as the host sees it
+#[derive(Debug, Clone, PartialEq)]
+pub enum LolValue<'lua> {
+    Numbr(i64),
+    Numbar(f64),
+    Yarn(String),
+    Troof(bool),
+    Noob,
+    Lizt(Vec<LolValue<'lua>>),
+    /// A funkshun, called with [`call`]
+    Funkshun(Function<'lua>),
+}
+
+impl<'lua> FromLua<'lua> for LolValue<'lua> {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        Ok(match value {
+            Value::Nil => LolValue::Noob,
+            Value::Boolean(b) => LolValue::Troof(b),
+            Value::Integer(n) => LolValue::Numbr(n),
+            // Lua numbers that aren't whole don't come from NUMBRs
+            Value::Number(n) => LolValue::Numbar(n),
+            Value::String(s) => LolValue::Yarn(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+            Value::Function(f) => LolValue::Funkshun(f),
+            // BUKKITs keep their metatable
+            Value::Table(t) if t.get_metatable().is_none() => {
+                if let Some(n) = t.raw_get::<_, Option<f64>>("numbar")? {
+                    LolValue::Numbar(n)
+                } else if let Some(digits) = t.raw_get::<_, Option<String>>("numbr")? {
+                    LolValue::Numbr(digits.parse().map_err(mlua::Error::external)?)
+                } else {
+                    LolValue::Lizt(t.raw_sequence_values().collect::<mlua::Result<_>>()?)
+                }
+            }
+            value => {
+                return Err(mlua::Error::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "LolValue",
+                    message: Some("BUKKITs can't be passed to the host".to_string()),
+                })
+            }
+        })
+    }
+}
+
+impl<'lua> ToLua<'lua> for LolValue<'lua> {
+    fn to_lua(self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        Ok(match self {
+            LolValue::Numbr(n) if (-MAX_EXACT_INT..=MAX_EXACT_INT).contains(&n) => {
+                Value::Integer(n)
+            }
+            LolValue::Numbr(n) => {
+                Value::Table(lua.create_table_from(vec![("numbr", n.to_string())])?)
+            }
+            LolValue::Numbar(n) => Value::Table(lua.create_table_from(vec![("numbar", n)])?),
+            LolValue::Yarn(s) => Value::String(lua.create_string(&s)?),
+            LolValue::Troof(b) => Value::Boolean(b),
+            LolValue::Noob => Value::Nil,
+            LolValue::Lizt(items) => Value::Table(lua.create_sequence_from(items)?),
+            LolValue::Funkshun(f) => Value::Function(f),
+        })
+    }
+}
+
+/// Calls a funkshun of the program with `args`, and returns what it `FOUND YR`
+pub fn call<'lua>(
+    lj: &'lua Lua,
+    f: &Function<'lua>,
+    args: Vec<LolValue<'lua>>,
+) -> mlua::Result<LolValue<'lua>> {
+    let mut call_args = vec![Value::Function(f.clone())];
+    for arg in args {
+        call_args.push(arg.to_lua(lj)?);
+    }
+    lj.globals()
+        .get::<_, Function>(LUA_CALL)?
+        .call(call_args.into_iter().collect::<Variadic<_>>())
+}
+
+/// A rust function called with the arguments of a LOLCODE call, returning the value of the call
+pub type HostFunction =
+    Rc<dyn for<'lua> Fn(&'lua Lua, Vec<LolValue<'lua>>) -> mlua::Result<LolValue<'lua>>>;
+
+/// The host functions of a program, by their LOLCODE name
+#[derive(Clone, Default)]
+pub struct HostFunctions {
+    functions: Vec<(String, HostFunction)>,
+}
+
+impl fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl HostFunctions {
+    /// Adds a function, replacing any other one of the same name. Panics if `name` isn't a
+    /// LOLCODE identifier, since programs couldn't call it.
+    pub fn add(&mut self, name: String, function: HostFunction) {
+        assert!(
+            is_identifier(&name),
+            "`{}` is not a LOLCODE identifier, so programs can't call it",
+            name
+        );
+        self.functions.retain(|(other, _)| *other != name);
+        self.functions.push((name, function));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Makes the functions callable by programs run in `lj`, which must have the builtins
+    pub fn register(&self, lj: &Lua) -> mlua::Result<()> {
+        let globals = lj.globals();
+        let host: Table = globals.get(LUA_HOST)?;
+        let wrap: Function = globals.get(LUA_WRAP)?;
+        for (name, function) in self.functions.iter() {
+            let function = function.clone();
+            let function = lj.create_function(move |lj, args: Variadic<LolValue>| {
+                function(lj, args.into_iter().collect())
+            })?;
+            host.set(name.as_str(), wrap.call::<_, Function>(function)?)?;
+        }
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod builtins;
+pub mod debug_info;
 pub mod errors;
+pub mod host;
+pub mod sandbox;
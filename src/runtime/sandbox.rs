@@ -0,0 +1,305 @@
+//! Limits for running programs that aren't trusted, like `--sandbox`. Programs that pass a limit
+//! stop with a runtime error at the statement they were running.
+
+use std::ffi::c_void;
+use std::io::{self, BufRead};
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mlua::{lua_State, HookTriggers, LightUserData, Lua, Value};
+
+use super::builtins::io::LUA_READ;
+
+/// Globals that reach outside of the program, or around the limits
+const UNSAFE_GLOBALS: [&str; 16] = [
+    "os",
+    "io",
+    "package",
+    "require",
+    "module",
+    "debug",
+    "ffi",
+    "jit",
+    "load",
+    "loadstring",
+    "loadfile",
+    "dofile",
+    "collectgarbage",
+    "getfenv",
+    "setfenv",
+    "newproxy",
+];
+
+/// VM instructions run between checks of the limits
+const CHECK_INTERVAL: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// VM instructions the program may run
+    pub instructions: Option<u64>,
+    /// Bytes the lua state may use
+    pub memory: Option<usize>,
+    /// How long the program may run
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    /// What `--sandbox` allows, unless other limits are given
+    pub const SANDBOX: Limits = Limits {
+        instructions: Some(100_000_000),
+        memory: Some(256 << 20),
+        time: Some(Duration::from_secs(10)),
+    };
+
+    /// These limits, with the ones that aren't set taken from `defaults`
+    pub fn or(self, defaults: Limits) -> Limits {
+        Limits {
+            instructions: self.instructions.or(defaults.instructions),
+            memory: self.memory.or(defaults.memory),
+            time: self.time.or(defaults.time),
+        }
+    }
+}
+
+type Alloc = unsafe extern "C" fn(*mut c_void, *mut c_void, usize, usize) -> *mut c_void;
+
+extern "C" {
+    fn lua_getallocf(state: *mut lua_State, ud: *mut *mut c_void) -> Option<Alloc>;
+    fn lua_setallocf(state: *mut lua_State, f: Alloc, ud: *mut c_void);
+    fn lua_touserdata(state: *mut lua_State, index: c_int) -> *mut c_void;
+}
+
+/// The allocator of a lua state, wrapped to refuse memory past a limit
+struct Allocator {
+    alloc: Option<Alloc>,
+    ud: *mut c_void,
+    /// Bytes the state has allocated
+    used: usize,
+    max: usize,
+}
+
+unsafe extern "C" fn limited_alloc(
+    ud: *mut c_void,
+    ptr: *mut c_void,
+    osize: usize,
+    nsize: usize,
+) -> *mut c_void {
+    let allocator = &mut *(ud as *mut Allocator);
+    let alloc = match allocator.alloc {
+        Some(alloc) => alloc,
+        None => return ptr::null_mut(),
+    };
+    let old = if ptr.is_null() { 0 } else { osize };
+    // Lua raises a memory error when an allocation fails. Freeing and shrinking never fail.
+    if nsize > old && allocator.used + (nsize - old) > allocator.max {
+        return ptr::null_mut();
+    }
+    let new = alloc(allocator.ud, ptr, osize, nsize);
+    if nsize == 0 || !new.is_null() {
+        allocator.used = allocator.used.saturating_sub(old) + nsize;
+    }
+    new
+}
+
+/// Wraps the allocator of the lua state calling it with the `Allocator` passed to it
+unsafe extern "C" fn install_allocator(state: *mut lua_State) -> c_int {
+    let allocator = &mut *(lua_touserdata(state, 1) as *mut Allocator);
+    allocator.alloc = lua_getallocf(state, &mut allocator.ud);
+    if allocator.alloc.is_some() {
+        lua_setallocf(
+            state,
+            limited_alloc,
+            allocator as *mut Allocator as *mut c_void,
+        );
+    }
+    0
+}
+
+/// Makes allocations of the lua state fail once it uses more than `max` bytes
+fn limit_memory(lj: &Lua, max: usize) -> mlua::Result<()> {
+    // The state allocates with it until it is closed, which nothing notices, so it is leaked
+    let allocator = Box::into_raw(Box::new(Allocator {
+        alloc: None,
+        ud: ptr::null_mut(),
+        used: lj.used_memory(),
+        max,
+    }));
+    // SAFETY: `install_allocator` only reads its argument, which is a live `Allocator`
+    let install = unsafe { lj.create_c_function(install_allocator)? };
+    install.call(LightUserData(allocator as *mut c_void))
+}
+
+/// Makes `GIMMEH` stop the program once it has waited for input past `deadline`. Lines are read
+/// on another thread, which the program stops waiting for.
+fn limit_input(lj: &Lua, deadline: Instant, max: Duration) -> mlua::Result<()> {
+    let mut lines = None;
+    let read = lj.create_function_mut(move |_, ()| {
+        let lines = lines.get_or_insert_with(|| {
+            let (send, lines) = mpsc::channel();
+            thread::spawn(move || {
+                let lines = io::stdin().lock().lines().map_while(Result::ok);
+                for line in lines {
+                    if send.send(line).is_err() {
+                        return;
+                    }
+                }
+            });
+            lines
+        });
+        match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => Ok(Some(line)),
+            // The input ended
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
+            Err(RecvTimeoutError::Timeout) => Err(mlua::Error::RuntimeError(time_limit(max))),
+        }
+    })?;
+    lj.globals().set(LUA_READ, read)
+}
+
+fn time_limit(max: Duration) -> String {
+    format!("the program ran for longer than its {:?}", max)
+}
+
+/// Removes the globals that reach outside of the program. The builtins keep what they need, so
+/// they must be registered first.
+pub fn restrict(lj: &Lua) -> mlua::Result<()> {
+    let globals = lj.globals();
+    for name in UNSAFE_GLOBALS.iter() {
+        globals.set(*name, Value::Nil)?;
+    }
+    // Bytecode can break out of the VM
+    globals
+        .get::<_, mlua::Table>("string")?
+        .set("dump", Value::Nil)
+}
+
+/// Stops the program once it passes a limit. Memory is limited by the allocator of the lua state.
+/// Instructions and time are only checked every `CHECK_INTERVAL` instructions, so a program can
+/// pass them by a little, but `GIMMEH` gives up waiting for input on time. Compiled code doesn't
+/// run hooks, so the JIT compiler is turned off, and this must be called before `restrict`
+/// removes it.
+pub fn limit(lj: &Lua, limits: Limits) -> mlua::Result<()> {
+    if let Some(max) = limits.memory {
+        limit_memory(lj, max)?;
+    }
+    let start = Instant::now();
+    if let Some(max) = limits.time {
+        limit_input(lj, start + max, max)?;
+    }
+    if limits.instructions.is_none() && limits.time.is_none() {
+        return Ok(());
+    }
+    lj.load("jit.off()").exec()?;
+
+    let interval = limits
+        .instructions
+        .map_or(CHECK_INTERVAL, |max| max.clamp(1, CHECK_INTERVAL));
+    let mut instructions = 0;
+    let triggers = HookTriggers {
+        every_nth_instruction: Some(interval as u32),
+        ..Default::default()
+    };
+    lj.set_hook(triggers, move |_, _| {
+        instructions += interval;
+        // Raised in the statement being run, which `lua_error` finds in the traceback
+        let stop = |msg| Err(mlua::Error::RuntimeError(msg));
+        if let Some(max) = limits.instructions.filter(|&max| instructions >= max) {
+            return stop(format!("the program ran out of its {} instructions", max));
+        }
+        if let Some(max) = limits.time.filter(|&max| start.elapsed() > max) {
+            return stop(time_limit(max));
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod sandbox_test {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::runtime::builtins::register_modules;
+    use crate::runtime::errors::lua_error;
+    use crate::Session;
+
+    const LOOP: &str = "HAI 1.2\nIM IN YR loop UPPIN YR i\nIM OUTTA YR loop\nKTHXBYE";
+    /// Keeps every BUKKIT it makes, in a list
+    const ALLOCATE: &str = "HAI 1.3\nI HAS A list ITZ A BUKKIT\nIM IN YR loop\n\
+                            I HAS A node ITZ A BUKKIT\nnode HAS A next ITZ list\n\
+                            list R node\nIM OUTTA YR loop\nKTHXBYE";
+
+    /// Runs a program that can't stop by itself, until it passes a limit
+    fn stopped(source: &str, limits: Limits) -> Diagnostic {
+        let program = Session::new(Vec::new())
+            .compile("limits.lol", source)
+            .unwrap();
+        let lj = Lua::new();
+        register_modules(&lj);
+        limit(&lj, limits).unwrap();
+        restrict(&lj).unwrap();
+        lua_error(program.exec(&lj).unwrap_err(), &program.debug_info)
+    }
+
+    fn message(diagnostic: &Diagnostic) -> &str {
+        diagnostic.annotations[0].message.as_ref()
+    }
+
+    #[test]
+    fn instruction_limit() {
+        let limits = Limits {
+            instructions: Some(10_000),
+            ..Default::default()
+        };
+        let diagnostic = stopped(LOOP, limits);
+        assert_eq!(
+            message(&diagnostic),
+            "the program ran out of its 10000 instructions"
+        );
+        // Raised in the loop, which the diagnostic points at
+        assert_ne!(diagnostic.span, Default::default());
+    }
+
+    #[test]
+    fn time_limit() {
+        let limits = Limits {
+            time: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let diagnostic = stopped(LOOP, limits);
+        assert_eq!(
+            message(&diagnostic),
+            "the program ran for longer than its 50ms"
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let limits = Limits {
+            memory: Some(4 << 20),
+            ..Default::default()
+        };
+        let diagnostic = stopped(ALLOCATE, limits);
+        assert_eq!(message(&diagnostic), "not enough memory");
+        assert!(diagnostic.note.unwrap().contains("--max-memory"));
+    }
+
+    #[test]
+    fn defaults() {
+        let limits = Limits {
+            instructions: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.or(Limits::SANDBOX),
+            Limits {
+                instructions: Some(5),
+                ..Limits::SANDBOX
+            }
+        );
+        assert_eq!(Limits::default().or(Limits::default()), Limits::default());
+    }
+}
@@ -1,8 +1,13 @@
-use std::sync::RwLock;
-
 use codespan_reporting::files::SimpleFiles;
-use once_cell::sync::Lazy;
 
-pub static SOURCEMAP: Lazy<RwLock<SimpleFiles<String, String>>> = Lazy::new(|| {
-    RwLock::new(SimpleFiles::new())
-});
+/// Every source file of a session, by name. Spans refer to files by their index in it.
+pub type SourceMap = SimpleFiles<String, String>;
+
+/// The char offset each line of a source starts at, to find the line of a span with
+/// `partition_point`
+pub fn line_starts(source: &str) -> Vec<usize> {
+    let newlines = source.chars().enumerate().filter(|(_, c)| *c == '\n');
+    std::iter::once(0)
+        .chain(newlines.map(|(i, _)| i + 1))
+        .collect()
+}